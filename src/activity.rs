@@ -0,0 +1,97 @@
+use crate::{
+    config::CONFIG,
+    constants::{ACTIVITY_KEY, ACTIVITY_THROTTLE},
+    models::{ApiResult, DeliveryInfo, DeliveryOpcode, DeliveryPriority, PayloadInfo},
+};
+
+use lazy_static::lazy_static;
+use redis::AsyncCommands;
+use simd_json::ValueAccess;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+lazy_static! {
+    static ref LAST_SEEN: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub async fn record(
+    conn: &mut redis::aio::Connection,
+    kind: &str,
+    payload: &PayloadInfo,
+) -> ApiResult<()> {
+    if CONFIG.activity_window == 0 || !matches!(kind, "MESSAGE_CREATE" | "INTERACTION_CREATE") {
+        return Ok(());
+    }
+
+    let guild_id = match payload.d.get_str("guild_id") {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let now = now();
+
+    {
+        let mut last_seen = LAST_SEEN.lock().unwrap();
+        if let Some(last) = last_seen.get(guild_id) {
+            if now - last < ACTIVITY_THROTTLE {
+                return Ok(());
+            }
+        }
+        last_seen.insert(guild_id.to_owned(), now);
+    }
+
+    let _: () = redis::pipe()
+        .zadd(ACTIVITY_KEY, guild_id, now)
+        .ignore()
+        .zrembyscore(
+            ACTIVITY_KEY,
+            "-inf",
+            now.saturating_sub(CONFIG.activity_window),
+        )
+        .ignore()
+        .query_async(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_priority(
+    conn: &mut redis::aio::Connection,
+    payload: &DeliveryInfo,
+) -> ApiResult<DeliveryPriority> {
+    if CONFIG.activity_window == 0
+        || payload.priority != DeliveryPriority::Normal
+        || !matches!(payload.op, DeliveryOpcode::Send)
+    {
+        return Ok(payload.priority);
+    }
+
+    let data = match payload.data.as_ref() {
+        Some(data) if data.get_u64("op") == Some(8) => data,
+        _ => return Ok(payload.priority),
+    };
+
+    let guild_id = match data.get("d").and_then(|d| d.get_str("guild_id")) {
+        Some(guild_id) => guild_id,
+        None => return Ok(payload.priority),
+    };
+
+    let score: Option<u64> = conn.zscore(ACTIVITY_KEY, guild_id).await?;
+
+    match score {
+        Some(score) if now().saturating_sub(score) <= CONFIG.activity_window => {
+            Ok(DeliveryPriority::High)
+        }
+        _ => Ok(DeliveryPriority::Low),
+    }
+}
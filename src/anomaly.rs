@@ -0,0 +1,120 @@
+use crate::{
+    config::CONFIG,
+    constants::{ANOMALY_COLOR, ANOMALY_INTERVAL, RESUME_COLOR},
+    metrics::GATEWAY_ANOMALIES,
+    models::ApiResult,
+    utils::{log_discord, publish_event},
+};
+
+use lapin::Channel;
+use lazy_static::lazy_static;
+use simd_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    sync::Mutex,
+};
+use tokio::time::{sleep, Duration};
+use tracing::warn;
+
+const BASELINE_WEIGHT: f64 = 0.01;
+const BASELINE_SAMPLES: u64 = 60;
+
+lazy_static! {
+    static ref COUNTERS: Mutex<HashMap<(&'static str, String), u64>> = Mutex::new(HashMap::new());
+}
+
+pub fn record(kind: &str, shard: u64) {
+    if CONFIG.anomaly_factor <= 0.0 {
+        return;
+    }
+
+    let mut counters = COUNTERS.lock().unwrap();
+    *counters.entry(("type", kind.to_owned())).or_default() += 1;
+    *counters.entry(("shard", shard.to_string())).or_default() += 1;
+}
+
+async fn publish(
+    channel: &Channel,
+    scope: &str,
+    name: &str,
+    active: bool,
+    rate: f64,
+    baseline: f64,
+) -> ApiResult<()> {
+    let data = json!({
+        "scope": scope,
+        "name": name,
+        "active": active,
+        "rate": rate,
+        "baseline": baseline,
+    });
+
+    publish_event(channel, "GATEWAY_ANOMALY", data).await
+}
+
+pub async fn run_jobs(channel: Channel) {
+    if CONFIG.anomaly_factor <= 0.0 {
+        return;
+    }
+
+    let mut baselines: HashMap<(&'static str, String), (f64, u64)> = HashMap::new();
+    let mut anomalies = HashSet::new();
+
+    loop {
+        sleep(Duration::from_millis(ANOMALY_INTERVAL as u64)).await;
+
+        let counters = mem::take(&mut *COUNTERS.lock().unwrap());
+        let seconds = ANOMALY_INTERVAL as f64 / 1000.0;
+
+        let keys: HashSet<_> = baselines.keys().chain(counters.keys()).cloned().collect();
+
+        for key in keys {
+            let rate = counters.get(&key).copied().unwrap_or_default() as f64 / seconds;
+            let (baseline, samples) = baselines.entry(key.clone()).or_insert((rate, 0));
+            let (scope, name) = (key.0, key.1.as_str());
+
+            let spiking = *samples >= BASELINE_SAMPLES
+                && rate >= CONFIG.anomaly_minimum as f64
+                && rate > *baseline * CONFIG.anomaly_factor;
+
+            if spiking {
+                if anomalies.insert(key.clone()) {
+                    warn!(
+                        "Event rate anomaly for {} {} ({:.1}/s, baseline {:.1}/s)",
+                        scope, name, rate, baseline
+                    );
+                    GATEWAY_ANOMALIES.with_label_values(&[scope, name]).inc();
+                    log_discord(
+                        ANOMALY_COLOR,
+                        format!(
+                            "Event rate anomaly for {} {} ({:.1}/s, baseline {:.1}/s)",
+                            scope, name, rate, baseline
+                        ),
+                    );
+                    if let Err(err) = publish(&channel, scope, name, true, rate, *baseline).await {
+                        warn!("Failed to publish anomaly: {:?}", err);
+                    }
+                }
+
+                continue;
+            }
+
+            if anomalies.remove(&key) {
+                log_discord(
+                    RESUME_COLOR,
+                    format!(
+                        "Event rate recovered for {} {} ({:.1}/s)",
+                        scope, name, rate
+                    ),
+                );
+                if let Err(err) = publish(&channel, scope, name, false, rate, *baseline).await {
+                    warn!("Failed to publish anomaly: {:?}", err);
+                }
+            }
+
+            *baseline += (rate - *baseline) * BASELINE_WEIGHT;
+            *samples += 1;
+        }
+    }
+}
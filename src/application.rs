@@ -0,0 +1,52 @@
+use crate::{
+    cache,
+    config::CONFIG,
+    constants::{APPLICATION_KEY, APPLICATION_REFRESH_INTERVAL},
+    models::ApiResult,
+    rest::{self, CLIENT},
+    utils::{publish_event, to_value},
+};
+
+use lapin::Channel;
+use simd_json::{owned::Value, ValueAccess};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+async fn refresh(conn: &mut redis::aio::Connection, channel: &Channel) -> ApiResult<()> {
+    rest::wait().await;
+    let application = CLIENT
+        .current_user_application()
+        .exec()
+        .await?
+        .model()
+        .await?;
+    let application = to_value(&application)?;
+
+    let old: Option<Value> = cache::get(conn, APPLICATION_KEY).await?;
+    cache::set(conn, APPLICATION_KEY, &application).await?;
+
+    if let Some(old) = old {
+        if old.get("flags") != application.get("flags")
+            || old.get("description") != application.get("description")
+        {
+            info!("Application info changed");
+            publish_event(channel, "APPLICATION_UPDATE", application).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection, channel: Channel) {
+    if !CONFIG.state_enabled {
+        return;
+    }
+
+    loop {
+        if let Err(err) = refresh(conn, &channel).await {
+            warn!("Failed to refresh application info: {:?}", err);
+        }
+
+        sleep(Duration::from_millis(APPLICATION_REFRESH_INTERVAL as u64)).await;
+    }
+}
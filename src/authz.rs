@@ -0,0 +1,88 @@
+use crate::{
+    config::CONFIG,
+    metrics::GATEWAY_UNAUTHORIZED,
+    utils::{constant_time_eq, sign},
+};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+#[derive(Debug, Default)]
+pub struct Credentials<'a> {
+    pub token: Option<&'a str>,
+    pub signature: Option<&'a str>,
+    pub expires: Option<&'a str>,
+    pub user: Option<&'a str>,
+}
+
+fn is_enabled() -> bool {
+    !CONFIG.authz_tokens.is_empty()
+        || !CONFIG.authz_secret.is_empty()
+        || !CONFIG.authz_users.is_empty()
+}
+
+fn is_known(credentials: &Credentials<'_>) -> bool {
+    credentials.token.is_some_and(|token| {
+        CONFIG
+            .authz_tokens
+            .iter()
+            .any(|value| constant_time_eq(value.as_bytes(), token.as_bytes()))
+    }) || credentials
+        .user
+        .is_some_and(|user| CONFIG.authz_users.iter().any(|value| value == user))
+}
+
+fn get_expires<'a>(credentials: &Credentials<'a>) -> Option<&'a str> {
+    if CONFIG.authz_secret.is_empty() || credentials.signature.is_none() {
+        return None;
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    credentials
+        .expires
+        .filter(|expires| expires.parse::<u64>().is_ok_and(|expires| expires >= now))
+}
+
+fn is_signed(credentials: &Credentials<'_>, body: &[u8]) -> bool {
+    let (signature, expires) = match (credentials.signature, get_expires(credentials)) {
+        (Some(signature), Some(expires)) => (signature, expires),
+        _ => return false,
+    };
+
+    let mut message = format!("{}.", expires).into_bytes();
+    message.extend_from_slice(body);
+
+    constant_time_eq(
+        sign(CONFIG.authz_secret.as_str(), message.as_slice()).as_bytes(),
+        signature.as_bytes(),
+    )
+}
+
+fn reject(source: &str) {
+    warn!("Rejected unauthorized {} request", source);
+    GATEWAY_UNAUTHORIZED.with_label_values(&[source]).inc();
+}
+
+pub fn precheck(source: &str, credentials: &Credentials<'_>) -> bool {
+    if !is_enabled() || is_known(credentials) || get_expires(credentials).is_some() {
+        return true;
+    }
+
+    reject(source);
+
+    false
+}
+
+pub fn authorize(source: &str, credentials: &Credentials<'_>, body: &[u8]) -> bool {
+    if !is_enabled() || is_known(credentials) || is_signed(credentials, body) {
+        return true;
+    }
+
+    reject(source);
+
+    false
+}
@@ -0,0 +1,61 @@
+use crate::{config::CONFIG, keys::automod_rules_key, models::ApiResult};
+
+use redis::AsyncCommands;
+use simd_json::{owned::Value, ValueAccess};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+pub fn is_cached(kind: &str) -> bool {
+    CONFIG.state_automod && kind.starts_with("AUTO_MODERATION_RULE_")
+}
+
+fn get_guild_id(data: &Value) -> Option<Id<GuildMarker>> {
+    data.get_str("guild_id")
+        .and_then(|guild_id| guild_id.parse().ok())
+        .and_then(Id::new_checked)
+}
+
+pub async fn update(conn: &mut redis::aio::Connection, kind: &str, data: &Value) -> ApiResult<()> {
+    let (guild_id, id) = match (get_guild_id(data), data.get_str("id")) {
+        (Some(guild_id), Some(id)) => (guild_id, id),
+        _ => return Ok(()),
+    };
+    let key = automod_rules_key(guild_id);
+
+    match kind {
+        "AUTO_MODERATION_RULE_CREATE" | "AUTO_MODERATION_RULE_UPDATE" => {
+            let _: () = conn.hset(key, id, simd_json::to_string(data)?).await?;
+        }
+        "AUTO_MODERATION_RULE_DELETE" => {
+            let _: () = conn.hdel(key, id).await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub async fn attach_rule(
+    conn: &mut redis::aio::Connection,
+    kind: &str,
+    data: &mut Value,
+) -> ApiResult<()> {
+    if !CONFIG.state_enabled || !CONFIG.state_automod || kind != "AUTO_MODERATION_ACTION_EXECUTION"
+    {
+        return Ok(());
+    }
+
+    let rule: Option<String> = match (get_guild_id(data), data.get_str("rule_id")) {
+        (Some(guild_id), Some(id)) => conn.hget(automod_rules_key(guild_id), id).await?,
+        _ => None,
+    };
+
+    if let (Some(rule), Value::Object(data)) = (rule, data) {
+        let mut rule = rule.into_bytes();
+        data.insert(
+            "rule".to_owned(),
+            simd_json::to_owned_value(rule.as_mut_slice())?,
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use crate::{
+    config::CONFIG,
+    constants::{BACKPRESSURE_INTERVAL, BACKPRESSURE_SUSTAIN, DEGRADED_COLOR, RESUME_COLOR},
+    metrics::DISPATCH_BACKPRESSURE_ACTIVE,
+    utils::log_discord,
+};
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static TOTAL: AtomicU64 = AtomicU64::new(0);
+static COUNT: AtomicU64 = AtomicU64::new(0);
+
+pub fn record(latency: Duration) {
+    if CONFIG.backpressure_latency == 0 {
+        return;
+    }
+
+    TOTAL.fetch_add(latency.as_millis() as u64, Ordering::Relaxed);
+    COUNT.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+pub fn is_pausable(shard: u64) -> bool {
+    CONFIG.backpressure_shards.contains(&shard)
+}
+
+pub async fn run_jobs() {
+    if CONFIG.backpressure_latency == 0 {
+        return;
+    }
+
+    let mut streak = 0;
+
+    loop {
+        sleep(Duration::from_millis(BACKPRESSURE_INTERVAL as u64)).await;
+
+        let total = TOTAL.swap(0, Ordering::Relaxed);
+        let count = COUNT.swap(0, Ordering::Relaxed);
+        let latency = total.checked_div(count).unwrap_or(0);
+
+        let active = is_active();
+        if (latency > CONFIG.backpressure_latency) != active {
+            streak += 1;
+        } else {
+            streak = 0;
+        }
+
+        if streak < BACKPRESSURE_SUSTAIN {
+            continue;
+        }
+
+        streak = 0;
+        ACTIVE.store(!active, Ordering::Relaxed);
+        DISPATCH_BACKPRESSURE_ACTIVE.set(if active { 0 } else { 1 });
+
+        if active {
+            info!(
+                "Resumed shards {:?} (latency: {}ms)",
+                CONFIG.backpressure_shards, latency
+            );
+            log_discord(
+                RESUME_COLOR,
+                format!("Resumed paused shards (cache latency: {}ms)", latency),
+            );
+        } else {
+            warn!(
+                "Pausing shards {:?} (latency: {}ms)",
+                CONFIG.backpressure_shards, latency
+            );
+            log_discord(
+                DEGRADED_COLOR,
+                format!("Pausing shards due to cache latency ({}ms)", latency),
+            );
+        }
+    }
+}
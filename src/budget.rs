@@ -0,0 +1,74 @@
+use crate::{
+    config::CONFIG,
+    constants::{DEGRADED_COLOR, SESSION_REFRESH_INTERVAL},
+    metrics::GATEWAY_SESSIONS_REMAINING,
+    models::ApiResult,
+    utils::{get_gateway_info, get_shards, log_discord},
+};
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+static REMAINING: AtomicU64 = AtomicU64::new(0);
+static ALERTED: AtomicBool = AtomicBool::new(false);
+
+fn set_remaining(remaining: u64) {
+    REMAINING.store(remaining, Ordering::Relaxed);
+    GATEWAY_SESSIONS_REMAINING.set(remaining as i64);
+
+    if CONFIG.session_alert == 0 || remaining > CONFIG.session_alert {
+        ALERTED.store(false, Ordering::Relaxed);
+    } else if !ALERTED.swap(true, Ordering::Relaxed) {
+        warn!("Only {} sessions remaining", remaining);
+        log_discord(
+            DEGRADED_COLOR,
+            format!("Only {} sessions remaining", remaining),
+        );
+    }
+}
+
+async fn refresh() -> ApiResult<u64> {
+    let limit = get_gateway_info().await?.session_start_limit;
+    set_remaining(limit.remaining);
+
+    Ok(limit.reset_after)
+}
+
+pub async fn check(resumes: u64) -> ApiResult<()> {
+    let required = get_shards().saturating_sub(resumes);
+
+    loop {
+        let reset_after = refresh().await?;
+        let remaining = REMAINING.load(Ordering::Relaxed);
+
+        if remaining >= required + CONFIG.session_reserve {
+            info!(
+                "Identifying {} shards ({} sessions remaining)",
+                required, remaining
+            );
+            return Ok(());
+        }
+
+        warn!(
+            "Not enough sessions remaining to identify {} shards ({} remaining, {} reserved), waiting {}ms for reset",
+            required, remaining, CONFIG.session_reserve, reset_after
+        );
+        sleep(Duration::from_millis(reset_after)).await;
+    }
+}
+
+pub fn record_identify() {
+    let remaining = REMAINING.load(Ordering::Relaxed).saturating_sub(1);
+    set_remaining(remaining);
+}
+
+pub async fn run_jobs() {
+    loop {
+        sleep(Duration::from_millis(SESSION_REFRESH_INTERVAL as u64)).await;
+
+        if let Err(err) = refresh().await {
+            warn!("Failed to refresh session start limit: {:?}", err);
+        }
+    }
+}
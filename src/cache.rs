@@ -1,57 +1,176 @@
 use crate::{
+    automod,
     config::CONFIG,
+    conflict,
     constants::{
-        channel_key, emoji_key, guild_key, member_key, message_key, presence_key,
-        private_channel_key, role_key, voice_key, BOT_USER_KEY, CACHE_CLEANUP_INTERVAL,
-        CACHE_DUMP_INTERVAL, CHANNEL_KEY, EMOJI_KEY, EXPIRY_KEYS, GUILD_KEY, KEYS_SUFFIX,
-        MESSAGE_KEY, SESSIONS_KEY, STATUSES_KEY,
+        history_key, BOT_USER_KEY, CACHE_CLEANUP_INTERVAL, CACHE_DUMP_INTERVAL, CHANNEL_KEY,
+        DEL_SCRIPT_SOURCE, EMOJI_KEY, EXPIRY_KEYS, GUILD_ITEM_KEYS, GUILD_KEY, KEYS_SUFFIX,
+        KIND_MARKER, MEMBER_KEY, MESSAGE_KEY, MIGRATE_CHUNK, PAYLOAD_PEEK_LENGTH, PRESENCE_KEY,
+        ROLE_KEY, SESSIONS_KEY, SET_SCRIPT_SOURCE, STATUSES_KEY, TAP_FLUSH_INTERVAL, TAP_KEY,
+        USER_PURGE_CHUNK, VOICE_KEY,
     },
-    models::{ApiError, ApiResult, FormattedDateTime, GuildItem, SessionInfo, StatusInfo},
-    utils::{get_channel_key, get_keys, get_user_id, to_value},
+    deploy, forum, integrations,
+    keys::{
+        bans_key, channel_key, channel_tree_key, emoji_key, forum_settings_key, guild_aux_keys,
+        guild_key, hash_key, member_key, message_key, presence_key, private_channel_key, role_key,
+        role_positions_key, timeouts_key, voice_key, CacheKey,
+    },
+    logging, memory, migration,
+    models::{
+        ApiError, ApiResult, ChannelTreeEntry, ChannelTreeNode, DriftInfo, FormattedDateTime,
+        GuildExport, GuildItem, GuildMigrationInfo, HistoryInfo, MemberActionInfo, ModerationInfo,
+        PayloadInfo, PurgeInfo, RoleHierarchyInfo, StatusInfo,
+    },
+    policy, recorder, targets,
+    utils::{get_channel_key, get_sessions, get_tenant_key, get_user_id, to_value},
 };
 
-use redis::{AsyncCommands, FromRedisValue, ToRedisArgs};
+#[cfg(feature = "chaos")]
+use crate::chaos;
+
+use lazy_static::lazy_static;
+use redis::{aio::ConnectionLike, AsyncCommands, FromRedisValue, Script, ToRedisArgs};
 use serde::{de::DeserializeOwned, Serialize};
-use simd_json::owned::Value;
-use std::{collections::HashMap, hash::Hash, iter, sync::Arc};
-use tokio::time::{sleep, Duration};
+use simd_json::{owned::Value, ValueAccess};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    iter, str,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    task::yield_now,
+    time::{sleep, Duration},
+};
 use tracing::warn;
 use twilight_gateway::Cluster;
 use twilight_model::{
+    application::interaction::Interaction,
     channel::{Channel, Message},
     gateway::event::Event,
-    guild::{Emoji, Member},
+    guild::{Emoji, Member, PartialMember, Role},
     id::{
-        marker::{GuildMarker, UserMarker},
+        marker::{GuildMarker, RoleMarker, UserMarker},
         Id,
     },
 };
 
+lazy_static! {
+    static ref PENDING: Mutex<HashMap<String, Option<Value>>> = Mutex::new(HashMap::new());
+    static ref SET_SCRIPT: Script = Script::new(SET_SCRIPT_SOURCE);
+    static ref DEL_SCRIPT: Script = Script::new(DEL_SCRIPT_SOURCE);
+}
+
 pub async fn get<K, T>(conn: &mut redis::aio::Connection, key: K) -> ApiResult<Option<T>>
 where
-    K: ToRedisArgs + Send + Sync,
+    K: AsRef<str> + ToRedisArgs + Send + Sync,
     T: DeserializeOwned,
 {
-    let res: Option<String> = conn.get(key).await?;
+    if let Some(value) = PENDING.lock().unwrap().get(key.as_ref()) {
+        return Ok(value
+            .clone()
+            .map(simd_json::serde::from_owned_value)
+            .transpose()?);
+    }
+
+    #[cfg(feature = "chaos")]
+    chaos::delay().await;
+
+    let (res, hashed): (Option<String>, bool) = match get_hash_key(key.as_ref()) {
+        Some((hash, field)) => (conn.hget(hash, field).await?, true),
+        None => (conn.get(key.as_ref()).await?, false),
+    };
+
+    if migration::is_pending() {
+        let res = match (res, hashed) {
+            (None, true) => match migration::get_legacy_key(key.as_ref()) {
+                Some(legacy) => conn.get(legacy).await?,
+                None => None,
+            },
+            (res, _) => res,
+        };
+
+        return res.map(|res| get_migrated(key.as_ref(), res)).transpose();
+    }
 
     Ok(res
         .map(|mut value| simd_json::from_str(value.as_mut_str()))
         .transpose()?)
 }
 
+fn get_migrated<T>(key: &str, mut res: String) -> ApiResult<T>
+where
+    T: DeserializeOwned,
+{
+    let mut value: Value = simd_json::from_str(res.as_mut_str())?;
+    migration::apply(key, &mut value);
+
+    Ok(simd_json::serde::from_owned_value(value)?)
+}
+
+async fn get_all_raw<K>(
+    conn: &mut redis::aio::Connection,
+    keys: &[K],
+) -> ApiResult<Vec<Option<String>>>
+where
+    K: AsRef<str> + ToRedisArgs + Send + Sync,
+{
+    if CONFIG.state_layout == 2 {
+        let mut pipe = redis::pipe();
+        for key in keys {
+            match get_hash_key(key.as_ref()) {
+                Some((hash, field)) => pipe.hget(hash, field),
+                None => pipe.get(key),
+            };
+        }
+        Ok(pipe.query_async(conn).await?)
+    } else {
+        Ok(redis::cmd("MGET").arg(keys).query_async(conn).await?)
+    }
+}
+
 pub async fn get_all<K, T>(
     conn: &mut redis::aio::Connection,
     keys: &[K],
 ) -> ApiResult<Vec<Option<T>>>
 where
-    K: ToRedisArgs + Send + Sync,
+    K: AsRef<str> + ToRedisArgs + Send + Sync,
     T: DeserializeOwned,
 {
     if keys.is_empty() {
         return Ok(vec![]);
     }
 
-    let res: Vec<Option<String>> = conn.get(keys).await?;
+    let mut res = get_all_raw(conn, keys).await?;
+
+    if migration::is_pending() {
+        let missing: Vec<(usize, String)> = keys
+            .iter()
+            .zip(res.iter())
+            .enumerate()
+            .filter(|(_, (key, res))| res.is_none() && get_hash_key(key.as_ref()).is_some())
+            .filter_map(|(index, (key, _))| {
+                migration::get_legacy_key(key.as_ref()).map(|legacy| (index, legacy))
+            })
+            .collect();
+
+        if !missing.is_empty() {
+            let legacy: Vec<&String> = missing.iter().map(|(_, legacy)| legacy).collect();
+            let values: Vec<Option<String>> =
+                redis::cmd("MGET").arg(legacy).query_async(conn).await?;
+            for ((index, _), value) in missing.into_iter().zip(values) {
+                res[index] = value;
+            }
+        }
+
+        return keys
+            .iter()
+            .zip(res)
+            .map(|(key, res)| res.map(|res| get_migrated(key.as_ref(), res)).transpose())
+            .collect();
+    }
 
     res.into_iter()
         .map(|option| {
@@ -62,6 +181,27 @@ where
         .collect()
 }
 
+pub async fn get_hashmap_all<K, T, U>(
+    conn: &mut redis::aio::Connection,
+    keys: &[K],
+) -> ApiResult<Vec<HashMap<T, U>>>
+where
+    K: ToRedisArgs + Send + Sync,
+    T: FromRedisValue + Eq + Hash,
+    U: FromRedisValue,
+{
+    if keys.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut pipe = redis::pipe();
+    for key in keys {
+        pipe.hgetall(key);
+    }
+
+    Ok(pipe.query_async(conn).await?)
+}
+
 pub async fn get_members<K, T>(conn: &mut redis::aio::Connection, key: K) -> ApiResult<Vec<T>>
 where
     K: ToRedisArgs + Send + Sync,
@@ -81,11 +221,9 @@ where
     Ok(res)
 }
 
-pub async fn get_hashmap<K, T, U>(
-    conn: &mut redis::aio::Connection,
-    key: K,
-) -> ApiResult<HashMap<T, U>>
+pub async fn get_hashmap<C, K, T, U>(conn: &mut C, key: K) -> ApiResult<HashMap<T, U>>
 where
+    C: ConnectionLike + Send,
     K: ToRedisArgs + Send + Sync,
     T: FromRedisValue + Eq + Hash,
     U: FromRedisValue,
@@ -95,6 +233,92 @@ where
     Ok(res)
 }
 
+pub async fn get_hashmap_values<K>(
+    conn: &mut redis::aio::Connection,
+    key: K,
+) -> ApiResult<Vec<Value>>
+where
+    K: ToRedisArgs + Send + Sync,
+{
+    let values: HashMap<String, String> = get_hashmap(conn, key).await?;
+
+    values
+        .into_values()
+        .map(|value| {
+            let mut value = value.into_bytes();
+            Ok(simd_json::to_owned_value(value.as_mut_slice())?)
+        })
+        .collect()
+}
+
+pub async fn get_shard_hash<T>(
+    conn: &mut redis::aio::Connection,
+    key: &str,
+) -> ApiResult<HashMap<u64, T>>
+where
+    T: DeserializeOwned,
+{
+    let values: HashMap<u64, String> = get_hashmap(conn, key).await?;
+
+    values
+        .into_iter()
+        .map(|(shard, mut value)| {
+            simd_json::from_str(value.as_mut_str())
+                .map(|value| (shard, value))
+                .map_err(ApiError::from)
+        })
+        .collect()
+}
+
+pub async fn set_shard_hash<C, T>(conn: &mut C, key: &str, values: &[(u64, T)]) -> ApiResult<()>
+where
+    C: ConnectionLike + Send,
+    T: Serialize,
+{
+    if values.is_empty() {
+        return Ok(());
+    }
+
+    let values = values
+        .iter()
+        .map(|(shard, value)| Ok((*shard, simd_json::to_string(value)?)))
+        .collect::<ApiResult<Vec<(u64, String)>>>()?;
+
+    let _: () = conn.hset_multiple(key, values.as_slice()).await?;
+
+    Ok(())
+}
+
+async fn del_stale_shards<C>(conn: &mut C, key: &str) -> ApiResult<()>
+where
+    C: ConnectionLike + Send,
+{
+    let fields: Vec<String> = conn.hkeys(key).await?;
+    let stale: Vec<String> = fields
+        .into_iter()
+        .filter(|field| {
+            field
+                .parse::<u64>()
+                .map_or(true, |shard| shard >= CONFIG.shards_total)
+        })
+        .collect();
+
+    if !stale.is_empty() {
+        let _: () = conn.hdel(key, stale).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn is_legacy_key<C>(conn: &mut C, key: &str) -> ApiResult<bool>
+where
+    C: ConnectionLike + Send,
+{
+    let kind: String = redis::cmd("TYPE").arg(key).query_async(conn).await?;
+
+    Ok(kind == "string")
+}
+
 pub async fn set<K, T>(conn: &mut redis::aio::Connection, key: K, value: T) -> ApiResult<()>
 where
     K: AsRef<str>,
@@ -111,35 +335,52 @@ where
     K: AsRef<str>,
     T: Serialize,
 {
+    if CONFIG.state_write_behind != 0 {
+        let mut pending = PENDING.lock().unwrap();
+        for (key, value) in keys {
+            pending.insert(key.as_ref().to_owned(), Some(to_value(&value)?));
+        }
+
+        return Ok(());
+    }
+
+    if CONFIG.state_coalesce == 0 {
+        return write_all(conn, keys).await;
+    }
+
+    let keys: Vec<(K, T)> = keys.into_iter().collect();
+
+    {
+        let mut pending = PENDING.lock().unwrap();
+        for (key, _) in keys.iter() {
+            pending.remove(key.as_ref());
+        }
+    }
+
+    write_all(conn, keys).await
+}
+
+async fn write_all<I, K, T>(conn: &mut redis::aio::Connection, keys: I) -> ApiResult<()>
+where
+    I: IntoIterator<Item = (K, T)>,
+    K: AsRef<str>,
+    T: Serialize,
+{
+    if CONFIG.state_layout == 2 {
+        return write_all_hashed(conn, keys).await;
+    }
+
     let mut members = HashMap::new();
 
     let keys = keys
         .into_iter()
         .map(|(key, value)| {
-            let key = key.as_ref();
-            let parts = get_keys(key);
-
-            let new_key = if parts.len() > 2 && parts[0] == CHANNEL_KEY {
-                format!("{}:{}", parts[0], parts[2])
-            } else {
-                key.to_owned()
-            };
-
-            if parts.len() > 1 {
-                members
-                    .entry(format!("{}{}", parts[0], KEYS_SUFFIX))
-                    .or_insert_with(Vec::new)
-                    .push(new_key.clone());
-            }
+            let key = CacheKey::parse(key.as_ref());
+            let new_key = key.legacy_key();
 
-            if parts.len() > 2 && parts[0] != MESSAGE_KEY {
-                members
-                    .entry(format!("{}{}:{}", GUILD_KEY, KEYS_SUFFIX, parts[1]))
-                    .or_insert_with(Vec::new)
-                    .push(new_key.clone());
-            } else if parts.len() > 2 {
+            for set in key.index_key().into_iter().chain(key.parent_set()) {
                 members
-                    .entry(format!("{}{}:{}", CHANNEL_KEY, KEYS_SUFFIX, parts[1]))
+                    .entry(set)
                     .or_insert_with(Vec::new)
                     .push(new_key.clone());
             }
@@ -154,12 +395,76 @@ where
         return Ok(());
     }
 
-    conn.set_multiple(keys.as_slice()).await?;
+    let mut invocation = SET_SCRIPT.prepare_invoke();
+    invocation.arg(keys.len());
+
+    for (key, value) in keys.iter() {
+        invocation.key(key).arg(value);
+    }
+
+    for (key, value) in members.iter() {
+        invocation.key(key).arg(value.len()).arg(value.as_slice());
+    }
+
+    let _: () = invocation.invoke_async(conn).await?;
+
+    Ok(())
+}
+
+async fn write_all_hashed<I, K, T>(conn: &mut redis::aio::Connection, keys: I) -> ApiResult<()>
+where
+    I: IntoIterator<Item = (K, T)>,
+    K: AsRef<str>,
+    T: Serialize,
+{
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+
+    let mut empty = true;
+
+    for (key, value) in keys {
+        let key = key.as_ref();
+        let value = simd_json::to_string(&value)?;
+
+        match get_hash_key(key) {
+            Some((hash, field)) => pipe.hset(hash, field, value).ignore(),
+            None => pipe.set(key, value).ignore(),
+        };
+
+        if let Some(index) = CacheKey::parse(key).index_key() {
+            pipe.sadd(index, key).ignore();
+        }
+
+        empty = false;
+    }
+
+    if empty {
+        return Ok(());
+    }
+
+    let _: () = pipe.query_async(conn).await?;
+
+    Ok(())
+}
 
-    for (key, value) in members {
-        conn.sadd(key, value.as_slice()).await?;
+pub async fn set_coalesced<K, T>(
+    conn: &mut redis::aio::Connection,
+    key: K,
+    value: T,
+) -> ApiResult<()>
+where
+    K: AsRef<str>,
+    T: Serialize,
+{
+    if CONFIG.state_coalesce == 0 {
+        return set(conn, key, value).await;
     }
 
+    PENDING
+        .lock()
+        .unwrap()
+        .insert(key.as_ref().to_owned(), Some(to_value(&value)?));
+
     Ok(())
 }
 
@@ -192,192 +497,1262 @@ where
         return Ok(());
     }
 
+    let mut conn = targets::expiry(conn).await?;
     conn.hset_multiple(EXPIRY_KEYS, keys.as_slice()).await?;
 
     Ok(())
 }
 
-pub async fn del_all<I, K>(conn: &mut redis::aio::Connection, keys: I) -> ApiResult<()>
-where
-    I: IntoIterator<Item = K>,
-    K: AsRef<str>,
-{
-    let mut members = HashMap::new();
+async fn del_expiries(conn: &mut redis::aio::Connection, keys: &[String]) -> ApiResult<()> {
+    let mut conn = targets::expiry(conn).await?;
+    del_hashmap(&mut conn, EXPIRY_KEYS, keys).await
+}
+
+pub async fn del_all<I, K>(conn: &mut redis::aio::Connection, keys: I) -> ApiResult<()>
+where
+    I: IntoIterator<Item = K>,
+    K: AsRef<str>,
+{
+    if CONFIG.state_write_behind != 0 {
+        let mut pending = PENDING.lock().unwrap();
+        for key in keys {
+            pending.insert(key.as_ref().to_owned(), None);
+        }
+
+        return Ok(());
+    }
+
+    remove_all(conn, keys).await
+}
+
+async fn remove_all<I, K>(conn: &mut redis::aio::Connection, keys: I) -> ApiResult<()>
+where
+    I: IntoIterator<Item = K>,
+    K: AsRef<str>,
+{
+    if CONFIG.state_layout == 2 {
+        return remove_all_hashed(conn, keys).await;
+    }
+
+    let mut members = HashMap::new();
+
+    let keys = keys
+        .into_iter()
+        .map(|key| {
+            let key = CacheKey::parse(key.as_ref());
+            let new_key = key.legacy_key();
+
+            for set in key.index_key().into_iter().chain(key.parent_set()) {
+                members
+                    .entry(set)
+                    .or_insert_with(Vec::new)
+                    .push(new_key.clone());
+            }
+
+            new_key
+        })
+        .collect::<Vec<String>>();
+
+    if CONFIG.state_coalesce != 0 {
+        let mut pending = PENDING.lock().unwrap();
+        for key in keys.iter() {
+            pending.remove(key);
+        }
+    }
+
+    if keys.is_empty() {
+        return Ok(());
+    }
+
+    let mut invocation = DEL_SCRIPT.prepare_invoke();
+    invocation.arg(keys.len());
+
+    for key in keys.iter() {
+        invocation.key(key);
+    }
+
+    for (key, value) in members.iter() {
+        invocation.key(key).arg(value.len()).arg(value.as_slice());
+    }
+
+    let _: () = invocation.invoke_async(conn).await?;
+
+    Ok(())
+}
+
+async fn remove_all_hashed<I, K>(conn: &mut redis::aio::Connection, keys: I) -> ApiResult<()>
+where
+    I: IntoIterator<Item = K>,
+    K: AsRef<str>,
+{
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+
+    let mut empty = true;
+
+    for key in keys {
+        let key = key.as_ref();
+        let index = CacheKey::parse(key).index_key();
+
+        if CONFIG.state_coalesce != 0 {
+            PENDING.lock().unwrap().remove(key);
+        }
+
+        match get_hash_key(key) {
+            Some((hash, field)) => pipe.hdel(hash, field).ignore(),
+            None => pipe.del(key).ignore(),
+        };
+
+        if let Some(index) = index.as_ref() {
+            pipe.srem(index, key).ignore();
+        }
+
+        if let Some(legacy) = migration::get_legacy_key(key) {
+            pipe.del(&legacy).ignore();
+            if let Some(index) = index.as_ref() {
+                pipe.srem(index, legacy).ignore();
+            }
+        }
+
+        empty = false;
+    }
+
+    if empty {
+        return Ok(());
+    }
+
+    let _: () = pipe.query_async(conn).await?;
+
+    Ok(())
+}
+
+pub async fn del(conn: &mut redis::aio::Connection, key: impl AsRef<str>) -> ApiResult<()> {
+    del_all(conn, iter::once(key)).await?;
+
+    Ok(())
+}
+
+pub async fn del_hashmap<C, K>(conn: &mut C, key: K, keys: &[String]) -> ApiResult<()>
+where
+    C: ConnectionLike + Send,
+    K: ToRedisArgs + Send + Sync,
+{
+    if keys.is_empty() {
+        return Ok(());
+    }
+
+    let _: () = conn.hdel(key, keys).await?;
+
+    Ok(())
+}
+
+pub async fn push_history(
+    conn: &mut redis::aio::Connection,
+    shard: u64,
+    kind: impl Into<String>,
+    details: Option<String>,
+) -> ApiResult<()> {
+    if CONFIG.history_length == 0 {
+        return Ok(());
+    }
+
+    let entry = HistoryInfo {
+        kind: kind.into(),
+        details,
+        timestamp: FormattedDateTime::now(),
+    };
+
+    let mut conn = targets::status(conn).await?;
+    let key = history_key(shard);
+    let _: () = redis::pipe()
+        .lpush(&key, simd_json::to_string(&entry)?)
+        .ignore()
+        .ltrim(&key, 0, CONFIG.history_length as isize - 1)
+        .ignore()
+        .query_async(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_history(
+    conn: &mut redis::aio::Connection,
+    shard: u64,
+) -> ApiResult<Vec<HistoryInfo>> {
+    let mut conn = targets::status(conn).await?;
+    let res: Vec<String> = conn.lrange(history_key(shard), 0, -1).await?;
+
+    res.into_iter()
+        .map(|mut value| simd_json::from_str(value.as_mut_str()).map_err(ApiError::from))
+        .collect()
+}
+
+async fn push_tap(
+    conn: &mut redis::aio::Connection,
+    payloads: &[String],
+    limit: u64,
+) -> ApiResult<()> {
+    let _: () = redis::pipe()
+        .lpush(TAP_KEY, payloads)
+        .ignore()
+        .ltrim(TAP_KEY, 0, limit as isize - 1)
+        .ignore()
+        .query_async(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_tap(conn: &mut redis::aio::Connection) -> ApiResult<Vec<PayloadInfo>> {
+    let res: Vec<String> = conn.lrange(TAP_KEY, 0, -1).await?;
+
+    res.into_iter()
+        .rev()
+        .map(|mut value| simd_json::from_str(value.as_mut_str()).map_err(ApiError::from))
+        .collect()
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection, clusters: &[Arc<Cluster>]) {
+    if recorder::is_replaying() {
+        return;
+    }
+
+    let statuses_key = get_tenant_key(STATUSES_KEY);
+    let sessions_key = get_tenant_key(SESSIONS_KEY);
+
+    if let Ok(mut conn) = targets::status(conn).await {
+        if let Ok(true) = is_legacy_key(&mut conn, statuses_key.as_str()).await {
+            if let Err(err) = del(&mut conn, statuses_key.as_str()).await {
+                warn!("Failed to delete legacy gateway statuses: {:?}", err);
+            }
+        }
+
+        for key in [statuses_key.as_str(), sessions_key.as_str()] {
+            if let Err(err) = del_stale_shards(&mut conn, key).await {
+                warn!("Failed to delete stale shards from {}: {:?}", key, err);
+            }
+        }
+    }
+
+    loop {
+        let mut statuses = vec![];
+
+        for (index, cluster) in clusters.iter().enumerate() {
+            let mut status: Vec<(u64, StatusInfo)> = cluster
+                .info()
+                .into_iter()
+                .map(|(k, v)| StatusInfo {
+                    cluster: index as u64,
+                    shard: k,
+                    status: format!("{}", v.stage()),
+                    latency: v
+                        .latency()
+                        .recent()
+                        .back()
+                        .map(|value| value.as_millis() as u64)
+                        .unwrap_or_default(),
+                    last_ack: v
+                        .latency()
+                        .received()
+                        .map(|value| {
+                            FormattedDateTime::now()
+                                - time::Duration::milliseconds(value.elapsed().as_millis() as i64)
+                        })
+                        .unwrap_or_else(FormattedDateTime::now),
+                    updated: FormattedDateTime::now(),
+                })
+                .map(|status| (status.shard, status))
+                .collect();
+
+            statuses.append(&mut status);
+        }
+
+        match targets::status(conn).await {
+            Ok(mut conn) => {
+                if let Err(err) =
+                    set_shard_hash(&mut conn, statuses_key.as_str(), statuses.as_slice()).await
+                {
+                    warn!("Failed to dump gateway statuses: {:?}", err);
+                }
+
+                if !deploy::is_draining() {
+                    let dumped = Instant::now();
+                    let sessions = get_sessions(clusters);
+                    match set_shard_hash(&mut conn, sessions_key.as_str(), sessions.as_slice())
+                        .await
+                    {
+                        Ok(()) => conflict::record_dump(dumped),
+                        Err(err) => warn!("Failed to dump gateway sessions: {:?}", err),
+                    }
+                }
+            }
+            Err(err) => {
+                warn!("Failed to connect to the status Redis: {:?}", err);
+            }
+        }
+
+        sleep(Duration::from_millis(CACHE_DUMP_INTERVAL as u64)).await;
+    }
+}
+
+pub fn get_pending_len() -> usize {
+    PENDING.lock().unwrap().len()
+}
+
+pub async fn flush(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    let pending = PENDING.lock().unwrap().clone();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let writes: Vec<(&String, &Value)> = pending
+        .iter()
+        .filter_map(|(key, value)| value.as_ref().map(|value| (key, value)))
+        .collect();
+
+    let mut result = if CONFIG.state_chunk_size == 0 {
+        write_all(conn, writes).await
+    } else {
+        let mut result = Ok(());
+        for chunk in writes.chunks(CONFIG.state_chunk_size as usize) {
+            result = write_all(conn, chunk.iter().copied()).await;
+            if result.is_err() {
+                break;
+            }
+            yield_now().await;
+        }
+        result
+    };
+
+    if result.is_ok() {
+        result = remove_all(
+            conn,
+            pending
+                .iter()
+                .filter(|(_, value)| value.is_none())
+                .map(|(key, _)| key),
+        )
+        .await;
+    }
+
+    if result.is_ok() {
+        let mut current = PENDING.lock().unwrap();
+        for (key, value) in pending {
+            if current.get(&key) == Some(&value) {
+                current.remove(&key);
+            }
+        }
+    }
+
+    result
+}
+
+pub async fn run_flushes(conn: &mut redis::aio::Connection) {
+    let interval = if CONFIG.state_write_behind != 0 {
+        CONFIG.state_write_behind
+    } else {
+        CONFIG.state_coalesce
+    };
+
+    if interval == 0 {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(interval)).await;
+
+        if let Err(err) = flush(conn).await {
+            warn!("Failed to flush pending writes: {:?}", err);
+        }
+    }
+}
+
+pub async fn run_taps(conn: &mut redis::aio::Connection) {
+    loop {
+        sleep(Duration::from_millis(TAP_FLUSH_INTERVAL as u64)).await;
+
+        if let Some((payloads, limit)) = logging::take_tap() {
+            if let Err(err) = push_tap(conn, payloads.as_slice(), limit).await {
+                warn!("Failed to flush tapped payloads: {:?}", err);
+            }
+        }
+    }
+}
+
+pub async fn run_cleanups(conn: &mut redis::aio::Connection) {
+    loop {
+        let hashmap: ApiResult<HashMap<String, String>> = match targets::expiry(conn).await {
+            Ok(mut conn) => get_hashmap(&mut conn, EXPIRY_KEYS).await,
+            Err(err) => Err(err),
+        };
+
+        match hashmap {
+            Ok(hashmap) => {
+                let mut keys = vec![];
+
+                for (key, mut value) in hashmap {
+                    match simd_json::from_str::<FormattedDateTime>(value.as_mut_str()) {
+                        Ok(timestamp) => {
+                            if (timestamp - FormattedDateTime::now()).is_negative() {
+                                keys.push(key);
+                            }
+                        }
+                        Err(err) => {
+                            warn!("Failed to get expiry timestamp: {:?}", err);
+                        }
+                    }
+                }
+
+                if let Err(err) = del_all(conn, keys.as_slice()).await {
+                    warn!("Failed to delete expired keys: {:?}", err);
+                } else if let Err(err) = del_expiries(conn, keys.as_slice()).await {
+                    warn!("Failed to delete expired keys hashmap: {:?}", err);
+                }
+            }
+            Err(err) => {
+                warn!("Failed to get expiry keys: {:?}", err);
+            }
+        }
+
+        sleep(Duration::from_millis(CACHE_CLEANUP_INTERVAL as u64)).await;
+    }
+}
+
+fn get_hash_key(key: &str) -> Option<(String, String)> {
+    if CONFIG.state_layout != 2 {
+        return None;
+    }
+
+    CacheKey::parse(key).hash_field()
+}
+
+async fn get_guild_item_keys(
+    conn: &mut redis::aio::Connection,
+    prefix: &str,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<Vec<String>> {
+    if CONFIG.state_layout != 2 {
+        return get_members(
+            conn,
+            format!("{}{}{}:{}", prefix, GUILD_KEY, KEYS_SUFFIX, guild_id),
+        )
+        .await;
+    }
+
+    let mut keys = vec![];
+
+    for kind in GUILD_ITEM_KEYS {
+        let fields: Vec<String> = conn
+            .hkeys(format!("{}{}", prefix, hash_key(GUILD_KEY, guild_id, kind)))
+            .await?;
+        keys.extend(
+            fields
+                .into_iter()
+                .map(|field| format!("{}:{}:{}", kind, guild_id, field)),
+        );
+    }
+
+    Ok(keys)
+}
+
+async fn get_guild_keys(
+    conn: &mut redis::aio::Connection,
+    prefix: &str,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<(Vec<String>, Vec<String>)> {
+    let mut keys = get_guild_item_keys(conn, prefix, guild_id).await?;
+
+    let channels: Vec<String> = keys
+        .iter()
+        .filter_map(|key| {
+            let key = CacheKey::parse(key);
+            if key.kind == CHANNEL_KEY {
+                key.id.map(|channel| channel.to_owned())
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let mut sets = vec![];
+
+    if CONFIG.state_layout == 2 {
+        sets.extend(
+            GUILD_ITEM_KEYS
+                .iter()
+                .map(|kind| hash_key(GUILD_KEY, guild_id, kind)),
+        );
+
+        for channel in channels {
+            let hash = hash_key(CHANNEL_KEY, &channel, MESSAGE_KEY);
+            let fields: Vec<String> = conn.hkeys(format!("{}{}", prefix, hash)).await?;
+            keys.extend(
+                fields
+                    .into_iter()
+                    .map(|field| format!("{}:{}:{}", MESSAGE_KEY, channel, field)),
+            );
+            sets.push(hash);
+        }
+    } else {
+        sets.push(format!("{}{}:{}", GUILD_KEY, KEYS_SUFFIX, guild_id));
+
+        for channel in channels {
+            let set = format!("{}{}:{}", CHANNEL_KEY, KEYS_SUFFIX, channel);
+            let mut messages: Vec<String> = get_members(conn, format!("{}{}", prefix, set)).await?;
+            keys.append(&mut messages);
+            sets.push(set);
+        }
+    }
+
+    Ok((keys, sets))
+}
+
+pub async fn export_guild(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<GuildExport> {
+    let (keys, sets) = get_guild_keys(conn, "", guild_id).await?;
+
+    let mut export = GuildExport {
+        guild: get(conn, guild_key(guild_id)).await?,
+        ..GuildExport::default()
+    };
+
+    if CONFIG.state_layout == 2 {
+        let hashes: Vec<HashMap<String, String>> = get_hashmap_all(conn, sets.as_slice()).await?;
+
+        for (hash, values) in sets.iter().zip(hashes) {
+            let kind = CacheKey::parse(hash)
+                .id
+                .unwrap_or_default()
+                .trim_end_matches('s');
+            for (_, mut value) in values {
+                export.push(kind, simd_json::from_str(value.as_mut_str())?);
+            }
+        }
+    } else {
+        let values: Vec<Option<Value>> = get_all(conn, keys.as_slice()).await?;
+
+        for (key, value) in keys.iter().zip(values) {
+            if let Some(value) = value {
+                export.push(CacheKey::parse(key).kind, value);
+            }
+        }
+    }
+
+    Ok(export)
+}
+
+pub async fn purge_guild(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<PurgeInfo> {
+    let (mut keys, mut sets) = get_guild_keys(conn, "", guild_id).await?;
+    keys.push(guild_key(guild_id));
+    sets.extend(guild_aux_keys(guild_id));
+
+    del_all(conn, keys.as_slice()).await?;
+    del_expiries(conn, keys.as_slice()).await?;
+    let _: () = conn.del(sets).await?;
+
+    Ok(PurgeInfo {
+        keys: keys.len() as u64,
+    })
+}
+
+pub async fn migrate_guild(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    migration: &GuildMigrationInfo,
+) -> ApiResult<PurgeInfo> {
+    if migration.from.is_empty() {
+        flush(conn).await?;
+    }
+
+    let from = migration.from.as_str();
+    let to = migration.to.as_str();
+
+    let (mut keys, mut sets) = get_guild_keys(conn, from, guild_id).await?;
+    keys.push(guild_key(guild_id));
+    sets.extend(guild_aux_keys(guild_id));
+
+    let mut raw = sets.clone();
+    if CONFIG.state_layout == 2 {
+        raw.push(guild_key(guild_id));
+    } else {
+        raw.extend(keys.iter().cloned());
+    }
+
+    for chunk in raw.chunks(MIGRATE_CHUNK) {
+        let mut pipe = redis::pipe();
+        for key in chunk {
+            pipe.cmd("DUMP").arg(format!("{}{}", from, key));
+        }
+        let dumps: Vec<Option<Vec<u8>>> = pipe.query_async(conn).await?;
+
+        let mut pipe = redis::pipe();
+        for key in chunk {
+            pipe.pttl(format!("{}{}", from, key));
+        }
+        let ttls: Vec<i64> = pipe.query_async(conn).await?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for ((key, dump), ttl) in chunk.iter().zip(dumps).zip(ttls) {
+            if let Some(dump) = dump {
+                pipe.cmd("RESTORE")
+                    .arg(format!("{}{}", to, key))
+                    .arg(ttl.max(0))
+                    .arg(dump)
+                    .arg("REPLACE")
+                    .ignore();
+            }
+        }
+        let _: () = pipe.query_async(conn).await?;
+    }
+
+    for chunk in keys.chunks(MIGRATE_CHUNK) {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for key in chunk {
+            let set = format!("{}{}", CacheKey::parse(key).kind, KEYS_SUFFIX);
+            pipe.sadd(format!("{}{}", to, set), key).ignore();
+        }
+        let _: () = pipe.query_async(conn).await?;
+
+        let mut conn = targets::expiry(conn).await?;
+        let expiries: Vec<Option<String>> =
+            conn.hget(format!("{}{}", from, EXPIRY_KEYS), chunk).await?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for (key, expiry) in chunk.iter().zip(expiries) {
+            if let Some(expiry) = expiry {
+                pipe.hset(format!("{}{}", to, EXPIRY_KEYS), key, expiry)
+                    .ignore();
+            }
+        }
+        let _: () = pipe.query_async(&mut *conn).await?;
+    }
+
+    if migration.remove {
+        for chunk in raw.chunks(MIGRATE_CHUNK) {
+            let chunk: Vec<String> = chunk.iter().map(|key| format!("{}{}", from, key)).collect();
+            let _: () = conn.del(chunk).await?;
+        }
+
+        for chunk in keys.chunks(MIGRATE_CHUNK) {
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            for key in chunk {
+                let set = format!("{}{}", CacheKey::parse(key).kind, KEYS_SUFFIX);
+                pipe.srem(format!("{}{}", from, set), key).ignore();
+            }
+            let _: () = pipe.query_async(conn).await?;
+
+            let mut conn = targets::expiry(conn).await?;
+            let _: () = conn.hdel(format!("{}{}", from, EXPIRY_KEYS), chunk).await?;
+        }
+    }
+
+    Ok(PurgeInfo {
+        keys: keys.len() as u64,
+    })
+}
+
+pub async fn evict_members(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<u64> {
+    let bot: Option<Value> = get(conn, BOT_USER_KEY).await?;
+    let bot_id = bot.as_ref().and_then(|bot| bot.get_str("id"));
+
+    let keys: Vec<String> = get_guild_item_keys(conn, "", guild_id)
+        .await?
+        .into_iter()
+        .filter(|key| {
+            let key = CacheKey::parse(key);
+            key.kind == MEMBER_KEY && key.id != bot_id
+        })
+        .collect();
+
+    del_all(conn, keys.as_slice()).await?;
+    del_expiries(conn, keys.as_slice()).await?;
+
+    Ok(keys.len() as u64)
+}
+
+pub async fn evict_kind(conn: &mut redis::aio::Connection, kind: &str) -> ApiResult<u64> {
+    let bot: Option<Value> = get(conn, BOT_USER_KEY).await?;
+    let bot_id = bot.as_ref().and_then(|bot| bot.get_str("id"));
+
+    let keys: Vec<String> = get_members(conn, format!("{}{}", kind, KEYS_SUFFIX))
+        .await?
+        .into_iter()
+        .filter(|key: &String| CacheKey::parse(key).id != bot_id || kind != MEMBER_KEY)
+        .collect();
+
+    for chunk in keys.chunks(USER_PURGE_CHUNK) {
+        del_all(conn, chunk).await?;
+        del_expiries(conn, chunk).await?;
+    }
+
+    Ok(keys.len() as u64)
+}
+
+pub async fn reconcile_guild(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    mut channels: Vec<Channel>,
+    roles: Vec<Role>,
+    member_count: Option<u64>,
+) -> ApiResult<DriftInfo> {
+    let mut drift = DriftInfo::default();
+
+    let mut guild: Value = match get(conn, guild_key(guild_id)).await? {
+        Some(guild) => guild,
+        None => return Ok(drift),
+    };
+
+    let keys = get_guild_item_keys(conn, "", guild_id).await?;
+    let get_cached = |kind: &str| -> HashSet<u64> {
+        keys.iter()
+            .map(|key| CacheKey::parse(key))
+            .filter(|key| key.kind == kind)
+            .filter_map(|key| key.id.and_then(|id| id.parse().ok()))
+            .collect()
+    };
+
+    let cached = get_cached(CHANNEL_KEY);
+    let current: HashSet<u64> = channels.iter().map(|channel| channel.id.get()).collect();
+    drift.channels = cached.symmetric_difference(&current).count() as u64;
+
+    if drift.channels > 0 {
+        del_all(
+            conn,
+            cached
+                .difference(&current)
+                .filter_map(|id| Id::new_checked(*id))
+                .map(|id| channel_key(guild_id, id)),
+        )
+        .await?;
+        set_channel_tree(conn, guild_id, channels.as_slice()).await?;
+        for channel in channels.iter_mut() {
+            channel.guild_id = Some(guild_id);
+        }
+        set_all(
+            conn,
+            channels
+                .iter()
+                .map(|channel| (channel_key(guild_id, channel.id), channel)),
+        )
+        .await?;
+    }
+
+    let cached = get_cached(ROLE_KEY);
+    let current: HashSet<u64> = roles.iter().map(|role| role.id.get()).collect();
+    drift.roles = cached.symmetric_difference(&current).count() as u64;
+
+    if drift.roles > 0 {
+        del_all(
+            conn,
+            cached
+                .difference(&current)
+                .filter_map(|id| Id::new_checked(*id))
+                .map(|id| role_key(guild_id, id)),
+        )
+        .await?;
+        set_role_positions(conn, guild_id, roles.as_slice()).await?;
+        set_all(
+            conn,
+            roles.iter().map(|role| (role_key(guild_id, role.id), role)),
+        )
+        .await?;
+    }
+
+    if let Some(member_count) = member_count {
+        let cached = guild.get_u64("member_count").unwrap_or_default();
+        drift.members = cached.max(member_count) - cached.min(member_count);
+
+        if drift.members > 0 {
+            if let Value::Object(object) = &mut guild {
+                object.insert("member_count".to_owned(), Value::from(member_count));
+            }
+            set(conn, guild_key(guild_id), &guild).await?;
+        }
+    }
+
+    Ok(drift)
+}
+
+pub async fn purge_user(
+    conn: &mut redis::aio::Connection,
+    user_id: Id<UserMarker>,
+) -> ApiResult<PurgeInfo> {
+    let mut keys = vec![];
+
+    for kind in [MEMBER_KEY, PRESENCE_KEY, VOICE_KEY] {
+        let mut iter: redis::AsyncIter<'_, String> = conn
+            .sscan_match(
+                format!("{}{}", kind, KEYS_SUFFIX),
+                format!("{}:*:{}", kind, user_id),
+            )
+            .await?;
+
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+    }
+
+    let guilds: HashSet<Id<GuildMarker>> = keys
+        .iter()
+        .filter_map(|key| CacheKey::parse(key).parent.and_then(|id| id.parse().ok()))
+        .filter_map(Id::new_checked)
+        .collect();
+
+    let mut messages = vec![];
+    for guild_id in guilds {
+        let (guild_keys, _) = get_guild_keys(conn, "", guild_id).await?;
+        messages.extend(
+            guild_keys
+                .into_iter()
+                .filter(|key| CacheKey::parse(key).kind == MESSAGE_KEY),
+        );
+    }
+
+    for chunk in messages.chunks(USER_PURGE_CHUNK) {
+        let values: Vec<Option<Message>> = get_all(conn, chunk).await?;
+
+        for (key, value) in chunk.iter().zip(values) {
+            if matches!(value, Some(message) if message.author.id == user_id) {
+                keys.push(key.clone());
+            }
+        }
+    }
+
+    del_all(conn, keys.as_slice()).await?;
+    del_expiries(conn, keys.as_slice()).await?;
+
+    Ok(PurgeInfo {
+        keys: keys.len() as u64,
+    })
+}
+
+pub async fn migrate_schema(
+    conn: &mut redis::aio::Connection,
+    layout: u64,
+    throttle: Duration,
+) -> ApiResult<u64> {
+    let mut migrated = 0;
+    let mut sets = HashSet::new();
+    let relayout = layout != CONFIG.state_layout;
+
+    for kind in GUILD_ITEM_KEYS.iter().chain(iter::once(&MESSAGE_KEY)) {
+        let index = format!("{}{}", kind, KEYS_SUFFIX);
+        let keys: Vec<String> = get_members(conn, &index).await?;
+
+        for chunk in keys.chunks(MIGRATE_CHUNK) {
+            migration::refresh_lock(conn).await?;
+
+            let values: Vec<Option<Value>> = if relayout {
+                let values: Vec<Option<String>> =
+                    redis::cmd("MGET").arg(chunk).query_async(conn).await?;
+                values
+                    .into_iter()
+                    .map(|value| {
+                        value
+                            .map(|mut value| simd_json::from_str(value.as_mut_str()))
+                            .transpose()
+                    })
+                    .collect::<Result<_, _>>()?
+            } else {
+                get_all_raw(conn, chunk)
+                    .await?
+                    .into_iter()
+                    .map(|value| {
+                        value
+                            .map(|mut value| simd_json::from_str(value.as_mut_str()))
+                            .transpose()
+                    })
+                    .collect::<Result<_, _>>()?
+            };
+
+            let mut items = vec![];
+            let mut old = vec![];
+            let mut renamed = vec![];
+
+            for (key, value) in chunk.iter().zip(values) {
+                let mut value = match value {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                if !relayout {
+                    if migration::apply(key, &mut value) {
+                        items.push((key.clone(), value));
+                    }
+                    continue;
+                }
+
+                let old_key = CacheKey::parse(key);
+
+                let new_key = match (value.get_str("guild_id"), old_key.parent, old_key.id) {
+                    (Some(guild_id), None, Some(id)) if *kind == CHANNEL_KEY => {
+                        format!("{}:{}:{}", CHANNEL_KEY, guild_id, id)
+                    }
+                    _ => key.clone(),
+                };
+
+                if get_hash_key(&new_key).is_none() {
+                    continue;
+                }
+
+                sets.extend(CacheKey::parse(&new_key).parent_set());
+
+                if new_key != *key {
+                    renamed.push(key.clone());
+                }
+
+                migration::apply(&new_key, &mut value);
+                items.push((new_key, value));
+                old.push(key.clone());
+            }
+
+            if items.is_empty() {
+                continue;
+            }
+
+            if relayout {
+                let mut pipe = redis::pipe();
+                for (key, _) in items.iter() {
+                    if let Some((hash, field)) = get_hash_key(key) {
+                        pipe.hexists(hash, field);
+                    }
+                }
+                let exists: Vec<bool> = pipe.query_async(conn).await?;
+
+                items = items
+                    .into_iter()
+                    .zip(exists)
+                    .filter(|(_, exists)| !exists)
+                    .map(|(item, _)| item)
+                    .collect();
+            }
+
+            write_all(conn, items.iter().map(|(key, value)| (key, value))).await?;
+            if !old.is_empty() {
+                let _: () = conn.del(old.as_slice()).await?;
+            }
+            if !renamed.is_empty() {
+                let _: () = conn.srem(&index, renamed.as_slice()).await?;
+            }
+
+            migrated += if relayout { old.len() } else { items.len() } as u64;
+
+            if !throttle.is_zero() {
+                sleep(throttle).await;
+            }
+        }
+    }
+
+    let sets: Vec<String> = sets.into_iter().collect();
+    for chunk in sets.chunks(MIGRATE_CHUNK) {
+        let _: () = conn.del(chunk).await?;
+    }
+
+    Ok(migrated)
+}
+
+fn get_channel_tree_entry(channel: &Channel) -> ApiResult<String> {
+    Ok(simd_json::to_string(&ChannelTreeEntry {
+        kind: channel.kind,
+        position: channel.position.unwrap_or_default(),
+        parent_id: channel.parent_id,
+    })?)
+}
+
+async fn set_channel_tree(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    channels: &[Channel],
+) -> ApiResult<()> {
+    let key = channel_tree_key(guild_id);
+    let items = channels
+        .iter()
+        .map(|channel| Ok((channel.id.get(), get_channel_tree_entry(channel)?)))
+        .collect::<ApiResult<Vec<(u64, String)>>>()?;
+
+    let mut pipe = redis::pipe();
+    pipe.atomic().del(&key).ignore();
+    if !items.is_empty() {
+        pipe.hset_multiple(&key, items.as_slice()).ignore();
+    }
+
+    let _: () = pipe.query_async(conn).await?;
+
+    Ok(())
+}
+
+async fn set_channel_tree_entry(
+    conn: &mut redis::aio::Connection,
+    channel: &Channel,
+) -> ApiResult<()> {
+    if let (true, Some(guild_id)) = (channel.kind.is_guild(), channel.guild_id) {
+        let _: () = conn
+            .hset(
+                channel_tree_key(guild_id),
+                channel.id.get(),
+                get_channel_tree_entry(channel)?,
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn del_channel_tree_entry(
+    conn: &mut redis::aio::Connection,
+    channel: &Channel,
+) -> ApiResult<()> {
+    if let (true, Some(guild_id)) = (channel.kind.is_guild(), channel.guild_id) {
+        let _: () = conn
+            .hdel(channel_tree_key(guild_id), channel.id.get())
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn build_channel_tree(
+    parent_id: Option<u64>,
+    groups: &mut HashMap<Option<u64>, Vec<(u64, ChannelTreeEntry)>>,
+) -> Vec<ChannelTreeNode> {
+    let mut entries = groups.remove(&parent_id).unwrap_or_default();
+    entries.sort_by_key(|(channel_id, entry)| (entry.position, *channel_id));
 
-    let keys = keys
+    entries
         .into_iter()
-        .map(|key| {
-            let key = key.as_ref();
-            let parts = get_keys(key);
-
-            let new_key = if parts.len() > 2 && parts[0] == CHANNEL_KEY {
-                format!("{}:{}", parts[0], parts[2])
-            } else {
-                key.to_owned()
-            };
-
-            if parts.len() > 1 {
-                members
-                    .entry(format!("{}{}", parts[0], KEYS_SUFFIX))
-                    .or_insert_with(Vec::new)
-                    .push(new_key.clone());
-            }
-
-            if parts.len() > 2 {
-                if parts[0] != MESSAGE_KEY {
-                    members
-                        .entry(format!("{}{}:{}", GUILD_KEY, KEYS_SUFFIX, parts[1]))
-                        .or_insert_with(Vec::new)
-                        .push(new_key.clone());
-                } else {
-                    members
-                        .entry(format!("{}{}:{}", CHANNEL_KEY, KEYS_SUFFIX, parts[1]))
-                        .or_insert_with(Vec::new)
-                        .push(new_key.clone());
-                }
-            }
-
-            new_key
+        .map(|(channel_id, entry)| ChannelTreeNode {
+            channel_id: Id::new(channel_id),
+            kind: entry.kind,
+            position: entry.position,
+            children: build_channel_tree(Some(channel_id), groups),
         })
-        .collect::<Vec<String>>();
+        .collect()
+}
 
-    if keys.is_empty() {
-        return Ok(());
+pub async fn get_forum_settings(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<Vec<Value>> {
+    get_hashmap_values(conn, forum_settings_key(guild_id)).await
+}
+
+pub async fn get_channel_tree(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<Vec<ChannelTreeNode>> {
+    let entries: HashMap<u64, String> = get_hashmap(conn, channel_tree_key(guild_id)).await?;
+
+    let mut groups: HashMap<Option<u64>, Vec<(u64, ChannelTreeEntry)>> = HashMap::new();
+    for (channel_id, mut entry) in entries.clone() {
+        let entry: ChannelTreeEntry = simd_json::from_str(entry.as_mut_str())?;
+        let parent_id = entry
+            .parent_id
+            .map(|parent_id| parent_id.get())
+            .filter(|parent_id| entries.contains_key(parent_id));
+
+        groups
+            .entry(parent_id)
+            .or_default()
+            .push((channel_id, entry));
     }
 
-    conn.del(keys).await?;
+    Ok(build_channel_tree(None, &mut groups))
+}
 
-    for (key, value) in members {
-        conn.srem(key, value).await?;
+async fn set_role_positions(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    roles: &[Role],
+) -> ApiResult<()> {
+    let key = role_positions_key(guild_id);
+
+    let mut pipe = redis::pipe();
+    pipe.atomic().del(&key).ignore();
+    if !roles.is_empty() {
+        let items: Vec<(i64, u64)> = roles
+            .iter()
+            .map(|role| (role.position, role.id.get()))
+            .collect();
+        pipe.zadd_multiple(&key, items.as_slice()).ignore();
     }
 
+    let _: () = pipe.query_async(conn).await?;
+
     Ok(())
 }
 
-pub async fn del(conn: &mut redis::aio::Connection, key: impl AsRef<str>) -> ApiResult<()> {
-    del_all(conn, iter::once(key)).await?;
+async fn set_role_position(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    role: &Role,
+) -> ApiResult<()> {
+    let _: () = conn
+        .zadd(role_positions_key(guild_id), role.id.get(), role.position)
+        .await?;
 
     Ok(())
 }
 
-pub async fn del_hashmap<K>(
+async fn del_role_position(
     conn: &mut redis::aio::Connection,
-    key: K,
-    keys: &[String],
-) -> ApiResult<()>
-where
-    K: ToRedisArgs + Send + Sync,
-{
-    if keys.is_empty() {
-        return Ok(());
-    }
-
-    let _: () = conn.hdel(key, keys).await?;
+    guild_id: Id<GuildMarker>,
+    role_id: Id<RoleMarker>,
+) -> ApiResult<()> {
+    let _: () = conn
+        .zrem(role_positions_key(guild_id), role_id.get())
+        .await?;
 
     Ok(())
 }
 
-pub async fn run_jobs(conn: &mut redis::aio::Connection, clusters: &[Arc<Cluster>]) {
-    loop {
-        let mut statuses = vec![];
-        let mut sessions = HashMap::new();
-
-        for cluster in clusters {
-            let mut status: Vec<StatusInfo> = cluster
-                .info()
-                .into_iter()
-                .map(|(k, v)| StatusInfo {
-                    shard: k,
-                    status: format!("{}", v.stage()),
-                    latency: v
-                        .latency()
-                        .recent()
-                        .back()
-                        .map(|value| value.as_millis() as u64)
-                        .unwrap_or_default(),
-                    last_ack: v
-                        .latency()
-                        .received()
-                        .map(|value| {
-                            FormattedDateTime::now()
-                                - time::Duration::milliseconds(value.elapsed().as_millis() as i64)
-                        })
-                        .unwrap_or_else(FormattedDateTime::now),
-                })
-                .collect();
-
-            statuses.append(&mut status);
-
-            for (shard, info) in cluster.info() {
-                sessions.insert(
-                    shard.to_string(),
-                    SessionInfo {
-                        session_id: info.session_id().unwrap_or_default().to_owned(),
-                        sequence: info.seq(),
-                    },
-                );
-            }
-        }
+async fn get_highest_role_of(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    roles: &[Id<RoleMarker>],
+) -> ApiResult<RoleHierarchyInfo> {
+    let positions: Vec<(u64, i64)> = conn
+        .zrange_withscores(role_positions_key(guild_id), 0, -1)
+        .await?;
 
-        statuses.sort_by(|a, b| a.shard.cmp(&b.shard));
+    let highest = positions
+        .into_iter()
+        .filter(|(role_id, _)| roles.iter().any(|role| role.get() == *role_id))
+        .max_by_key(|(role_id, position)| (*position, Reverse(*role_id)));
+
+    Ok(match highest {
+        Some((role_id, position)) => RoleHierarchyInfo {
+            role_id: Id::new(role_id),
+            position,
+        },
+        None => RoleHierarchyInfo {
+            role_id: guild_id.cast(),
+            position: 0,
+        },
+    })
+}
 
-        if let Err(err) = set(conn, STATUSES_KEY, &statuses).await {
-            warn!("Failed to dump gateway statuses: {:?}", err);
-        }
+pub async fn get_highest_role(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+) -> ApiResult<Option<RoleHierarchyInfo>> {
+    let member: Option<Member> = get(conn, member_key(guild_id, user_id)).await?;
+
+    match member {
+        Some(member) => Ok(Some(
+            get_highest_role_of(conn, guild_id, member.roles.as_slice()).await?,
+        )),
+        None => Ok(None),
+    }
+}
 
-        if let Err(err) = set(conn, SESSIONS_KEY, &sessions).await {
-            warn!("Failed to dump gateway sessions: {:?}", err);
-        }
+pub async fn can_act_on(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    actor_id: Id<UserMarker>,
+    target_id: Id<UserMarker>,
+) -> ApiResult<Option<MemberActionInfo>> {
+    let guild: Option<Value> = get(conn, guild_key(guild_id)).await?;
+    let owner_id = guild
+        .as_ref()
+        .and_then(|guild| guild.get_str("owner_id"))
+        .and_then(|owner_id| owner_id.parse::<u64>().ok());
+
+    let actor = get_highest_role(conn, guild_id, actor_id).await?;
+    let target = get_highest_role(conn, guild_id, target_id).await?;
+
+    let (actor, target) = match (actor, target) {
+        (Some(actor), Some(target)) => (actor, target),
+        _ => return Ok(None),
+    };
+
+    let owner = owner_id == Some(actor_id.get());
+    let allowed = if owner {
+        actor_id != target_id
+    } else {
+        owner_id != Some(target_id.get()) && actor.position > target.position
+    };
+
+    Ok(Some(MemberActionInfo {
+        allowed,
+        owner,
+        actor,
+        target,
+    }))
+}
 
-        sleep(Duration::from_millis(CACHE_DUMP_INTERVAL as u64)).await;
-    }
+fn get_unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
 
-pub async fn run_cleanups(conn: &mut redis::aio::Connection) {
-    loop {
-        let hashmap: ApiResult<HashMap<String, String>> = get_hashmap(conn, EXPIRY_KEYS).await;
+async fn set_timeout(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    until: Option<u64>,
+) -> ApiResult<()> {
+    let key = timeouts_key(guild_id);
+    let now = get_unix_secs();
 
-        match hashmap {
-            Ok(hashmap) => {
-                let mut keys = vec![];
+    let mut pipe = redis::pipe();
+    pipe.zrembyscore(&key, "-inf", now).ignore();
+    match until.filter(|until| *until > now) {
+        Some(until) => pipe.zadd(&key, user_id.get(), until).ignore(),
+        None => pipe.zrem(&key, user_id.get()).ignore(),
+    };
 
-                for (key, mut value) in hashmap {
-                    match simd_json::from_str::<FormattedDateTime>(value.as_mut_str()) {
-                        Ok(timestamp) => {
-                            if (timestamp - FormattedDateTime::now()).is_negative() {
-                                keys.push(key);
-                            }
-                        }
-                        Err(err) => {
-                            warn!("Failed to get expiry timestamp: {:?}", err);
-                        }
-                    }
-                }
+    let _: () = pipe.query_async(conn).await?;
 
-                if let Err(err) = del_all(conn, keys.as_slice()).await {
-                    warn!("Failed to delete expired keys: {:?}", err);
-                } else if let Err(err) = del_hashmap(conn, EXPIRY_KEYS, keys.as_slice()).await {
-                    warn!("Failed to delete expired keys hashmap: {:?}", err);
-                }
-            }
-            Err(err) => {
-                warn!("Failed to get expiry keys: {:?}", err);
-            }
-        }
+    Ok(())
+}
 
-        sleep(Duration::from_millis(CACHE_CLEANUP_INTERVAL as u64)).await;
-    }
+pub async fn get_moderation(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+) -> ApiResult<ModerationInfo> {
+    let (banned, timeout_until): (bool, Option<u64>) = redis::pipe()
+        .sismember(bans_key(guild_id), user_id.get())
+        .zscore(timeouts_key(guild_id), user_id.get())
+        .query_async(conn)
+        .await?;
+
+    Ok(ModerationInfo {
+        banned,
+        timeout_until: timeout_until.filter(|until| *until > get_unix_secs()),
+    })
 }
 
 async fn clear_guild<T: DeserializeOwned>(
     conn: &mut redis::aio::Connection,
     guild_id: Id<GuildMarker>,
 ) -> ApiResult<Option<T>> {
-    let members: Vec<String> =
-        get_members(conn, format!("{}{}:{}", GUILD_KEY, KEYS_SUFFIX, guild_id)).await?;
+    let members = get_guild_item_keys(conn, "", guild_id).await?;
 
     del_all(conn, members).await?;
 
+    let _: () = conn
+        .del(vec![
+            role_positions_key(guild_id),
+            channel_tree_key(guild_id),
+        ])
+        .await?;
+
     let guild = get(conn, guild_key(guild_id)).await?;
 
     del(conn, guild_key(guild_id)).await?;
@@ -385,16 +1760,80 @@ async fn clear_guild<T: DeserializeOwned>(
     Ok(guild)
 }
 
+fn get_payload_kind(bytes: &[u8]) -> Option<&str> {
+    let peek = &bytes[..bytes.len().min(PAYLOAD_PEEK_LENGTH)];
+    let start = peek
+        .windows(KIND_MARKER.len())
+        .position(|window| window == KIND_MARKER)?
+        + KIND_MARKER.len();
+    let end = start + peek[start..].iter().position(|byte| *byte == b'"')?;
+
+    str::from_utf8(&peek[start..end]).ok()
+}
+
+async fn update_payload(conn: &mut redis::aio::Connection, bytes: &[u8]) -> ApiResult<()> {
+    let kind = match get_payload_kind(bytes) {
+        Some(kind)
+            if automod::is_cached(kind)
+                || integrations::is_cached(kind)
+                || forum::is_cached(kind) =>
+        {
+            kind
+        }
+        _ => return Ok(()),
+    };
+
+    let mut bytes = bytes.to_vec();
+    let payload: PayloadInfo = simd_json::from_slice(bytes.as_mut_slice())?;
+
+    if automod::is_cached(kind) {
+        automod::update(conn, kind, &payload.d).await?;
+    }
+    if integrations::is_cached(kind) {
+        integrations::update(conn, kind, &payload.d).await?;
+    }
+    if forum::is_cached(kind) {
+        forum::update(conn, kind, &payload.d).await?;
+    }
+
+    Ok(())
+}
+
+fn is_member_cached(guild_id: Id<GuildMarker>) -> bool {
+    memory::is_member_cached() && policy::is_member_cached(guild_id.get())
+}
+
+fn get_cached_member(member: &PartialMember, guild_id: Id<GuildMarker>) -> Option<Member> {
+    Some(Member {
+        avatar: member.avatar,
+        communication_disabled_until: member.communication_disabled_until,
+        deaf: member.deaf,
+        guild_id,
+        joined_at: member.joined_at,
+        mute: member.mute,
+        nick: member.nick.clone(),
+        pending: false,
+        premium_since: member.premium_since,
+        roles: member.roles.clone(),
+        user: member.user.clone()?,
+    })
+}
+
 pub async fn update(
     conn: &mut redis::aio::Connection,
     event: &Event,
     bot_id: Id<UserMarker>,
-) -> ApiResult<Option<Value>> {
+) -> ApiResult<(Option<Value>, Option<Value>)> {
     let mut old: Option<Value> = None;
+    let mut old_features: Option<Value> = None;
+
+    #[cfg(feature = "chaos")]
+    chaos::delay().await;
 
     match event {
         Event::ChannelCreate(data) => {
             set(conn, get_channel_key(data), &data).await?;
+            set_channel_tree_entry(conn, data).await?;
         }
         Event::ChannelDelete(data) => {
             let key = get_channel_key(data);
@@ -402,6 +1841,7 @@ pub async fn update(
                 old = get(conn, &key).await?;
             }
             del(conn, &key).await?;
+            del_channel_tree_entry(conn, data).await?;
         }
         Event::ChannelPinsUpdate(data) => {
             let key = if let Some(guild_id) = data.guild_id {
@@ -412,7 +1852,7 @@ pub async fn update(
             let channel: Option<Channel> = get(conn, &key).await?;
             if let Some(mut channel) = channel {
                 channel.last_pin_timestamp = data.last_pin_timestamp;
-                set(conn, &key, &channel).await?;
+                set_coalesced(conn, &key, &channel).await?;
             }
         }
         Event::ChannelUpdate(data) => {
@@ -420,13 +1860,15 @@ pub async fn update(
             if CONFIG.state_old {
                 old = get(conn, &key).await?;
             }
-            set(conn, &key, &data).await?;
+            set_coalesced(conn, &key, &data).await?;
+            set_channel_tree_entry(conn, data).await?;
         }
         Event::GuildCreate(data) => {
             old = clear_guild(conn, data.id).await?;
 
             let mut items = vec![];
             let mut guild = data.clone();
+            set_channel_tree(conn, data.id, guild.channels.as_slice()).await?;
             for mut channel in guild.channels.drain(..) {
                 channel.guild_id = Some(data.id);
                 items.push((
@@ -434,6 +1876,7 @@ pub async fn update(
                     GuildItem::Channel(channel),
                 ));
             }
+            set_role_positions(conn, data.id, guild.roles.as_slice()).await?;
             for role in guild.roles.drain(..) {
                 items.push((role_key(data.id, role.id), GuildItem::Role(role)));
             }
@@ -443,8 +1886,9 @@ pub async fn update(
             for voice in guild.voice_states.drain(..) {
                 items.push((voice_key(data.id, voice.user_id), GuildItem::Voice(voice)));
             }
+            let members_cached = is_member_cached(data.id);
             for member in guild.members.drain(..) {
-                if CONFIG.state_member || member.user.id == bot_id {
+                if members_cached || member.user.id == bot_id {
                     items.push((
                         member_key(data.id, member.user.id),
                         GuildItem::Member(member),
@@ -453,14 +1897,21 @@ pub async fn update(
             }
             for presence in guild.presences.drain(..) {
                 let id = get_user_id(&presence.user);
-                if CONFIG.state_presence {
+                if memory::is_presence_cached() {
                     items.push((presence_key(data.id, id), GuildItem::Presence(presence)));
                 }
             }
             items.push((guild_key(data.id), GuildItem::Guild(guild)));
 
-            set_all(conn, items).await?;
-            if CONFIG.state_member {
+            if CONFIG.state_chunk_size == 0 {
+                set_all(conn, items).await?;
+            } else {
+                for chunk in items.chunks(CONFIG.state_chunk_size as usize) {
+                    set_all(conn, chunk.iter().map(|(key, value)| (key, value))).await?;
+                    yield_now().await;
+                }
+            }
+            if members_cached {
                 expire_all(
                     conn,
                     data.members.iter().map(|member| {
@@ -470,18 +1921,28 @@ pub async fn update(
                 .await?;
             }
         }
+        Event::BanAdd(data) if CONFIG.state_moderation => {
+            let _: () = conn
+                .sadd(bans_key(data.guild_id), data.user.id.get())
+                .await?;
+            set_timeout(conn, data.guild_id, data.user.id, None).await?;
+        }
+        Event::BanRemove(data) if CONFIG.state_moderation => {
+            let _: () = conn
+                .srem(bans_key(data.guild_id), data.user.id.get())
+                .await?;
+        }
         Event::GuildDelete(data) => {
             old = clear_guild(conn, data.id).await?;
+            if !data.unavailable {
+                let _: () = conn.del(guild_aux_keys(data.id)).await?;
+            }
         }
         Event::GuildEmojisUpdate(data) => {
-            let keys: Vec<String> = get_members(
-                conn,
-                format!("{}{}:{}", GUILD_KEY, KEYS_SUFFIX, data.guild_id),
-            )
-            .await?;
+            let keys = get_guild_item_keys(conn, "", data.guild_id).await?;
             let emoji_keys: Vec<String> = keys
                 .into_iter()
-                .filter(|key| get_keys(key)[0] == EMOJI_KEY)
+                .filter(|key| CacheKey::parse(key).kind == EMOJI_KEY)
                 .collect();
             let emojis: Vec<Emoji> = get_all(conn, emoji_keys.as_slice())
                 .await?
@@ -509,32 +1970,61 @@ pub async fn update(
         }
         Event::GuildUpdate(data) => {
             let key = guild_key(data.id);
+            let previous: Option<Value> = get(conn, &key).await?;
+            old_features = previous
+                .as_ref()
+                .and_then(|previous| previous.get("features"))
+                .cloned();
             if CONFIG.state_old {
-                old = get(conn, &key).await?;
+                old = previous;
             }
-            set(conn, &key, &data).await?;
+            set_coalesced(conn, &key, &data).await?;
         }
-        Event::MemberAdd(data) => {
-            if CONFIG.state_member {
-                let key = member_key(data.guild_id, data.user.id);
-                set(conn, &key, &data).await?;
-                expire(conn, &key, CONFIG.state_member_ttl).await?;
+        Event::InteractionCreate(data) if memory::is_member_cached() => {
+            let (guild_id, member) = match &data.0 {
+                Interaction::ApplicationCommand(data) => (data.guild_id, data.member.as_ref()),
+                Interaction::ApplicationCommandAutocomplete(data) => {
+                    (data.guild_id, data.member.as_ref())
+                }
+                Interaction::MessageComponent(data) => (data.guild_id, data.member.as_ref()),
+                _ => (None, None),
+            };
+
+            if let (Some(guild_id), Some(member)) = (guild_id, member) {
+                if let Some(member) =
+                    get_cached_member(member, guild_id).filter(|_| is_member_cached(guild_id))
+                {
+                    let key = member_key(guild_id, member.user.id);
+                    set_coalesced(conn, &key, &member).await?;
+                    expire(conn, &key, CONFIG.state_member_ttl).await?;
+                }
             }
         }
+        Event::MemberAdd(data) if is_member_cached(data.guild_id) => {
+            let key = member_key(data.guild_id, data.user.id);
+            set(conn, &key, &data).await?;
+            expire(conn, &key, CONFIG.state_member_ttl).await?;
+        }
         Event::MemberRemove(data) => {
-            if CONFIG.state_member {
+            if memory::is_member_cached() {
                 let key = member_key(data.guild_id, data.user.id);
                 if CONFIG.state_old {
                     old = get(conn, &key).await?;
                 }
                 del(conn, &key).await?;
             }
-            if CONFIG.state_presence {
+            if memory::is_presence_cached() {
                 del(conn, presence_key(data.guild_id, data.user.id)).await?;
             }
         }
         Event::MemberUpdate(data) => {
-            if CONFIG.state_member || data.user.id == bot_id {
+            if CONFIG.state_moderation {
+                let until = data
+                    .communication_disabled_until
+                    .map(|until| until.as_secs() as u64);
+                set_timeout(conn, data.guild_id, data.user.id, until).await?;
+            }
+            if is_member_cached(data.guild_id) || data.user.id == bot_id {
                 let key = member_key(data.guild_id, data.user.id);
                 let member: Option<Member> = get(conn, &key).await?;
                 if let Some(mut member) = member {
@@ -546,118 +2036,122 @@ pub async fn update(
                     member.premium_since = data.premium_since;
                     member.roles = data.roles.clone();
                     member.user = data.user.clone();
-                    set(conn, &key, &member).await?;
+                    set_coalesced(conn, &key, &member).await?;
                     expire(conn, &key, CONFIG.state_member_ttl).await?;
                 }
             }
         }
-        Event::MemberChunk(data) => {
-            if CONFIG.state_member {
-                set_all(
-                    conn,
-                    data.members
-                        .iter()
-                        .map(|member| (member_key(data.guild_id, member.user.id), member)),
-                )
-                .await?;
-                expire_all(
-                    conn,
-                    data.members.iter().map(|member| {
-                        (
-                            member_key(data.guild_id, member.user.id),
-                            CONFIG.state_member_ttl,
-                        )
-                    }),
-                )
-                .await?;
-            }
+        Event::MemberChunk(data) if is_member_cached(data.guild_id) => {
+            set_all(
+                conn,
+                data.members
+                    .iter()
+                    .map(|member| (member_key(data.guild_id, member.user.id), member)),
+            )
+            .await?;
+            expire_all(
+                conn,
+                data.members.iter().map(|member| {
+                    (
+                        member_key(data.guild_id, member.user.id),
+                        CONFIG.state_member_ttl,
+                    )
+                }),
+            )
+            .await?;
         }
         Event::MessageCreate(data) => {
-            if CONFIG.state_message {
+            if memory::is_message_cached() {
                 let key = message_key(data.channel_id, data.id);
                 set(conn, &key, &data).await?;
                 expire(conn, &key, CONFIG.state_message_ttl).await?;
             }
-        }
-        Event::MessageDelete(data) => {
-            if CONFIG.state_message {
-                let key = message_key(data.channel_id, data.id);
-                if CONFIG.state_old {
-                    old = get(conn, &key).await?;
+            if memory::is_member_cached() {
+                if let (Some(guild_id), Some(member)) = (
+                    data.guild_id.filter(|guild_id| is_member_cached(*guild_id)),
+                    data.member.as_ref(),
+                ) {
+                    let mut member = member.clone();
+                    member.user = Some(data.author.clone());
+                    if let Some(member) = get_cached_member(&member, guild_id) {
+                        let key = member_key(guild_id, member.user.id);
+                        set_coalesced(conn, &key, &member).await?;
+                        expire(conn, &key, CONFIG.state_member_ttl).await?;
+                    }
                 }
-                del(conn, &key).await?;
             }
         }
-        Event::MessageDeleteBulk(data) => {
-            if CONFIG.state_message {
-                let message_keys: Vec<String> = data
-                    .ids
-                    .iter()
-                    .map(|id| message_key(data.channel_id, *id))
-                    .collect();
-                let messages: Vec<Message> = get_all(conn, message_keys.as_slice())
-                    .await?
-                    .into_iter()
-                    .flatten()
-                    .collect();
-                del_all(conn, message_keys).await?;
-                if CONFIG.state_old {
-                    old = Some(to_value(&messages)?);
-                }
+        Event::MessageDelete(data) if memory::is_message_cached() => {
+            let key = message_key(data.channel_id, data.id);
+            if CONFIG.state_old {
+                old = get(conn, &key).await?;
             }
+            del(conn, &key).await?;
         }
-        Event::MessageUpdate(data) => {
-            if CONFIG.state_message {
-                let key = message_key(data.channel_id, data.id);
-                let message: Option<Message> = get(conn, &key).await?;
-                if let Some(mut message) = message {
-                    if CONFIG.state_old {
-                        old = Some(to_value(&message)?);
-                    }
-                    if let Some(attachments) = &data.attachments {
-                        message.attachments = attachments.clone();
-                    }
-                    if let Some(content) = &data.content {
-                        message.content = content.clone();
-                    }
-                    if let Some(edited_timestamp) = data.edited_timestamp {
-                        message.edited_timestamp = Some(edited_timestamp);
-                    }
-                    if let Some(embeds) = &data.embeds {
-                        message.embeds = embeds.clone();
-                    }
-                    if let Some(mention_everyone) = data.mention_everyone {
-                        message.mention_everyone = mention_everyone;
-                    }
-                    if let Some(mention_roles) = &data.mention_roles {
-                        message.mention_roles = mention_roles.clone();
-                    }
-                    if let Some(mentions) = &data.mentions {
-                        message.mentions = mentions.clone();
-                    }
-                    if let Some(pinned) = data.pinned {
-                        message.pinned = pinned;
-                    }
-                    if let Some(timestamp) = data.timestamp {
-                        message.timestamp = timestamp;
-                    }
-                    if let Some(tts) = data.tts {
-                        message.tts = tts;
-                    }
-                    set(conn, &key, &message).await?;
-                    expire(conn, &key, CONFIG.state_message_ttl).await?;
-                }
+        Event::MessageDeleteBulk(data) if memory::is_message_cached() => {
+            let message_keys: Vec<String> = data
+                .ids
+                .iter()
+                .map(|id| message_key(data.channel_id, *id))
+                .collect();
+            let messages: Vec<Message> = get_all(conn, message_keys.as_slice())
+                .await?
+                .into_iter()
+                .flatten()
+                .collect();
+            del_all(conn, message_keys).await?;
+            if CONFIG.state_old {
+                old = Some(to_value(&messages)?);
             }
         }
-        Event::PresenceUpdate(data) => {
-            if CONFIG.state_presence {
-                let key = presence_key(data.guild_id, get_user_id(&data.user));
+        Event::MessageUpdate(data) if memory::is_message_cached() => {
+            let key = message_key(data.channel_id, data.id);
+            let message: Option<Message> = get(conn, &key).await?;
+            if let Some(mut message) = message {
                 if CONFIG.state_old {
-                    old = get(conn, &key).await?;
+                    old = Some(to_value(&message)?);
                 }
-                set(conn, &key, &data).await?;
+                if let Some(attachments) = &data.attachments {
+                    message.attachments = attachments.clone();
+                }
+                if let Some(content) = &data.content {
+                    message.content = content.clone();
+                }
+                if let Some(edited_timestamp) = data.edited_timestamp {
+                    message.edited_timestamp = Some(edited_timestamp);
+                }
+                if let Some(embeds) = &data.embeds {
+                    message.embeds = embeds.clone();
+                }
+                if let Some(mention_everyone) = data.mention_everyone {
+                    message.mention_everyone = mention_everyone;
+                }
+                if let Some(mention_roles) = &data.mention_roles {
+                    message.mention_roles = mention_roles.clone();
+                }
+                if let Some(mentions) = &data.mentions {
+                    message.mentions = mentions.clone();
+                }
+                if let Some(pinned) = data.pinned {
+                    message.pinned = pinned;
+                }
+                if let Some(timestamp) = data.timestamp {
+                    message.timestamp = timestamp;
+                }
+                if let Some(tts) = data.tts {
+                    message.tts = tts;
+                }
+                set_coalesced(conn, &key, &message).await?;
+                expire(conn, &key, CONFIG.state_message_ttl).await?;
             }
         }
+        Event::PresenceUpdate(data) if memory::is_presence_cached() => {
+            let key = presence_key(data.guild_id, get_user_id(&data.user));
+            if CONFIG.state_old {
+                old = get(conn, &key).await?;
+            }
+            set_coalesced(conn, &key, &data).await?;
+        }
         Event::Ready(data) => {
             set(conn, BOT_USER_KEY, &data.user).await?;
             set_all(
@@ -668,6 +2162,7 @@ pub async fn update(
         }
         Event::RoleCreate(data) => {
             set(conn, role_key(data.guild_id, data.role.id), &data.role).await?;
+            set_role_position(conn, data.guild_id, &data.role).await?;
         }
         Event::RoleDelete(data) => {
             let key = role_key(data.guild_id, data.role_id);
@@ -675,13 +2170,15 @@ pub async fn update(
                 old = get(conn, &key).await?;
             }
             del(conn, &key).await?;
+            del_role_position(conn, data.guild_id, data.role_id).await?;
         }
         Event::RoleUpdate(data) => {
             let key = role_key(data.guild_id, data.role.id);
             if CONFIG.state_old {
                 old = get(conn, &key).await?;
             }
-            set(conn, &key, &data.role).await?;
+            set_coalesced(conn, &key, &data.role).await?;
+            set_role_position(conn, data.guild_id, &data.role).await?;
         }
         Event::UnavailableGuild(data) => {
             old = clear_guild(conn, data.id).await?;
@@ -693,6 +2190,9 @@ pub async fn update(
             }
             set(conn, BOT_USER_KEY, &data).await?;
         }
+        Event::ShardPayload(data) => {
+            update_payload(conn, data.bytes.as_slice()).await?;
+        }
         Event::VoiceStateUpdate(data) => {
             if let Some(guild_id) = data.0.guild_id {
                 let key = voice_key(guild_id, data.0.user_id);
@@ -708,5 +2208,101 @@ pub async fn update(
         _ => {}
     }
 
-    Ok(old)
+    Ok((old, old_features))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{get_redis_connection, get_redis_info};
+
+    use simd_json::json;
+
+    const GUILD: u64 = 4242;
+    const ROLE: u64 = 4243;
+
+    async fn setup() -> redis::aio::Connection {
+        dotenv::dotenv().ok();
+
+        let redis = redis::Client::open(get_redis_info()).unwrap();
+        let mut conn = get_redis_connection(&redis).await.unwrap();
+
+        set_all(
+            &mut conn,
+            [
+                (
+                    guild_key(Id::new(GUILD)),
+                    json!({ "id": GUILD.to_string() }),
+                ),
+                (
+                    role_key(Id::new(GUILD), Id::new(ROLE)),
+                    json!({ "id": ROLE.to_string() }),
+                ),
+            ],
+        )
+        .await
+        .unwrap();
+        let _: () = conn
+            .zadd(role_positions_key(Id::new(GUILD)), ROLE, 1)
+            .await
+            .unwrap();
+
+        conn
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a .env and a Redis instance"]
+    async fn purge_guild_removes_all_keys() {
+        let mut conn = setup().await;
+
+        let info = purge_guild(&mut conn, Id::new(GUILD)).await.unwrap();
+        assert_eq!(info.keys, 2);
+
+        let role: Option<Value> = get(&mut conn, role_key(Id::new(GUILD), Id::new(ROLE)))
+            .await
+            .unwrap();
+        assert!(role.is_none());
+
+        for key in guild_aux_keys(Id::new(GUILD)) {
+            let exists: bool = conn.exists(key).await.unwrap();
+            assert!(!exists);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a .env and a Redis instance"]
+    async fn migrate_guild_copies_all_keys() {
+        let mut conn = setup().await;
+        let migration = GuildMigrationInfo {
+            from: "".to_owned(),
+            to: "migrate_test:".to_owned(),
+            remove: true,
+        };
+
+        let info = migrate_guild(&mut conn, Id::new(GUILD), &migration)
+            .await
+            .unwrap();
+        assert_eq!(info.keys, 2);
+
+        let role = role_key(Id::new(GUILD), Id::new(ROLE));
+        let exists: bool = conn.exists(role.as_str()).await.unwrap();
+        assert!(!exists);
+
+        let migrated: bool = conn
+            .exists(format!("{}{}", migration.to, role))
+            .await
+            .unwrap();
+        let positions: bool = conn
+            .exists(format!(
+                "{}{}",
+                migration.to,
+                role_positions_key(Id::new(GUILD))
+            ))
+            .await
+            .unwrap();
+        assert!(migrated && positions);
+
+        let keys: Vec<String> = conn.keys("migrate_test:*").await.unwrap();
+        let _: () = conn.del(keys).await.unwrap();
+    }
 }
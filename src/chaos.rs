@@ -0,0 +1,65 @@
+use crate::models::{ApiError, ApiResult, ChaosInfo, DeliveryInfo};
+
+use lazy_static::lazy_static;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing::warn;
+use twilight_gateway::Cluster;
+
+lazy_static! {
+    static ref REDIS_LATENCY: AtomicU64 = AtomicU64::new(0);
+    static ref PUBLISH_FAILURE_RATE: AtomicU64 = AtomicU64::new(0);
+    static ref PUBLISH_COUNT: AtomicU64 = AtomicU64::new(0);
+}
+
+pub async fn delay() {
+    let latency = REDIS_LATENCY.load(Ordering::Relaxed);
+    if latency > 0 {
+        sleep(Duration::from_millis(latency)).await;
+    }
+}
+
+pub fn should_fail() -> bool {
+    let rate = f64::from_bits(PUBLISH_FAILURE_RATE.load(Ordering::Relaxed));
+    if rate <= 0.0 {
+        return false;
+    }
+
+    let count = PUBLISH_COUNT.fetch_add(1, Ordering::Relaxed) as f64;
+
+    ((count + 1.0) * rate).floor() > (count * rate).floor()
+}
+
+pub fn execute(clusters: &[Arc<Cluster>], payload: DeliveryInfo) -> ApiResult<()> {
+    let mut bytes = simd_json::to_vec(&payload.data.unwrap_or_default())?;
+    let chaos: ChaosInfo = simd_json::from_slice(bytes.as_mut_slice())?;
+
+    warn!(
+        "Injecting chaos (redis latency: {}ms, publish failure rate: {})",
+        chaos.redis_latency, chaos.publish_failure_rate
+    );
+
+    REDIS_LATENCY.store(chaos.redis_latency, Ordering::Relaxed);
+    PUBLISH_FAILURE_RATE.store(
+        chaos.publish_failure_rate.clamp(0.0, 1.0).to_bits(),
+        Ordering::Relaxed,
+    );
+
+    if chaos.disconnect {
+        let shard = clusters
+            .iter()
+            .find_map(|cluster| cluster.shard(payload.shard))
+            .ok_or(ApiError::InvalidShard(payload.shard))?;
+
+        warn!("[Shard {}] Forcing disconnect", payload.shard);
+        shard.shutdown_resumable();
+    }
+
+    Ok(())
+}
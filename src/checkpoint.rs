@@ -0,0 +1,64 @@
+use crate::{
+    cache,
+    config::CONFIG,
+    constants::SESSIONS_KEY,
+    deploy,
+    models::{ApiResult, SessionInfo},
+    resume, targets,
+    utils::get_tenant_key,
+};
+
+use lazy_static::lazy_static;
+use std::{collections::HashMap, sync::Mutex};
+use twilight_gateway::Cluster;
+
+lazy_static! {
+    static ref COUNTERS: Mutex<HashMap<u64, u64>> = Mutex::new(HashMap::new());
+}
+
+fn is_due(shard: u64) -> bool {
+    let mut counters = COUNTERS.lock().unwrap();
+    let counter = counters.entry(shard).or_insert(0);
+
+    *counter += 1;
+    if *counter < CONFIG.session_checkpoint {
+        return false;
+    }
+
+    *counter = 0;
+    true
+}
+
+pub async fn record(
+    conn: &mut redis::aio::Connection,
+    cluster: &Cluster,
+    shard: u64,
+    sequence: u64,
+) -> ApiResult<()> {
+    if CONFIG.session_checkpoint == 0 || deploy::is_draining() || !is_due(shard) {
+        return Ok(());
+    }
+
+    let session_id = match cluster
+        .shard(shard)
+        .and_then(|shard| shard.info().ok())
+        .and_then(|info| info.session_id().map(|session_id| session_id.to_owned()))
+    {
+        Some(session_id) => session_id,
+        None => return Ok(()),
+    };
+
+    let session = SessionInfo {
+        session_id,
+        sequence,
+        resume_gateway_url: resume::get(shard),
+    };
+
+    let mut conn = targets::status(conn).await?;
+    cache::set_shard_hash(
+        &mut conn,
+        get_tenant_key(SESSIONS_KEY).as_str(),
+        &[(shard, session)],
+    )
+    .await
+}
@@ -0,0 +1,175 @@
+use crate::{
+    commands,
+    constants::{CHUNK_NONCE_TTL, CHUNK_REPLY_PREFIX},
+    intents,
+    models::{
+        ApiError, ApiResult, GatewayCommand, MemberRequestInfo, MemberRequestStatus, PayloadInfo,
+    },
+    utils::get_guild_shard,
+};
+
+use lazy_static::lazy_static;
+use simd_json::ValueAccess;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use twilight_gateway::{shard::raw_message::Message, Cluster, Intents};
+use twilight_model::{
+    gateway::payload::outgoing::RequestGuildMembers,
+    id::{marker::GuildMarker, Id},
+};
+
+lazy_static! {
+    static ref COUNTER: AtomicU64 = AtomicU64::new(0);
+    static ref REQUESTS: Mutex<HashMap<String, (Instant, MemberRequestStatus)>> =
+        Mutex::new(HashMap::new());
+}
+
+pub fn get_nonce() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+
+    format!("{:x}{:x}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+pub fn track(nonce: &str, guild_id: u64) {
+    let mut requests = REQUESTS.lock().unwrap();
+
+    requests.retain(|_, (created, _)| {
+        created.elapsed() < Duration::from_millis(CHUNK_NONCE_TTL as u64)
+    });
+    requests.insert(
+        nonce.to_owned(),
+        (
+            Instant::now(),
+            MemberRequestStatus {
+                nonce: nonce.to_owned(),
+                guild_id: guild_id.to_string(),
+                ..MemberRequestStatus::default()
+            },
+        ),
+    );
+}
+
+pub fn record(kind: &str, payload: &PayloadInfo) {
+    if kind != "GUILD_MEMBERS_CHUNK" {
+        return;
+    }
+
+    let nonce = match payload.d.get_str("nonce") {
+        Some(nonce) => nonce,
+        None => return,
+    };
+
+    let mut requests = REQUESTS.lock().unwrap();
+    if let Some((_, status)) = requests.get_mut(nonce) {
+        status.chunks += 1;
+        status.chunk_count = payload.d.get_u64("chunk_count").unwrap_or_default();
+        status.members += payload
+            .d
+            .get_array("members")
+            .map(|members| members.len() as u64)
+            .unwrap_or_default();
+        if let Some(not_found) = payload.d.get_array("not_found") {
+            status.not_found.extend(
+                not_found
+                    .iter()
+                    .filter_map(|id| id.as_str().map(|id| id.to_owned())),
+            );
+        }
+        status.complete = status.chunks >= status.chunk_count;
+    }
+}
+
+pub fn get_reply_key(kind: &str, payload: &PayloadInfo) -> Option<String> {
+    if kind != "GUILD_MEMBERS_CHUNK" {
+        return None;
+    }
+
+    let nonce = payload.d.get_str("nonce")?;
+    if !nonce.starts_with(CHUNK_REPLY_PREFIX)
+        || !nonce
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        || !REQUESTS.lock().unwrap().contains_key(nonce)
+    {
+        return None;
+    }
+
+    Some(format!("{}.{}", kind, nonce))
+}
+
+pub fn get(nonce: &str) -> Option<MemberRequestStatus> {
+    REQUESTS
+        .lock()
+        .unwrap()
+        .get(nonce)
+        .map(|(_, status)| status.clone())
+}
+
+pub async fn request(
+    clusters: &[Arc<Cluster>],
+    guild_id: Id<GuildMarker>,
+    info: MemberRequestInfo,
+) -> ApiResult<MemberRequestStatus> {
+    let shard = get_guild_shard(guild_id.get());
+    let cluster = clusters
+        .iter()
+        .find(|cluster| cluster.shard(shard).is_some())
+        .ok_or(ApiError::InvalidShard(shard))?;
+
+    let intents = intents::get();
+    if !intents.contains(Intents::GUILD_MEMBERS)
+        && info.user_ids.is_empty()
+        && info.query.as_deref().unwrap_or_default().is_empty()
+    {
+        return Err(ApiError::InvalidCommand(
+            "Requesting all members requires the GUILD_MEMBERS intent".to_owned(),
+        ));
+    }
+    if info.presences && !intents.contains(Intents::GUILD_PRESENCES) {
+        return Err(ApiError::InvalidCommand(
+            "Requesting presences requires the GUILD_PRESENCES intent".to_owned(),
+        ));
+    }
+
+    let nonce = format!("{}{}", CHUNK_REPLY_PREFIX, get_nonce());
+    let builder = RequestGuildMembers::builder(guild_id)
+        .nonce(nonce.as_str())
+        .presences(info.presences);
+
+    let command = if info.user_ids.is_empty() {
+        builder.query(info.query.unwrap_or_default(), info.limit)
+    } else {
+        builder
+            .user_ids(
+                info.user_ids
+                    .into_iter()
+                    .filter_map(Id::new_checked)
+                    .collect::<Vec<_>>(),
+            )
+            .map_err(|err| ApiError::InvalidCommand(format!("{}", err)))?
+    };
+
+    track(nonce.as_str(), guild_id.get());
+    commands::reserve(shard).await;
+
+    cluster
+        .send(
+            shard,
+            Message::Binary(simd_json::to_vec(&GatewayCommand::RequestGuildMembers(
+                command,
+            ))?),
+        )
+        .await
+        .map_err(|err| ApiError::ClusterSend(format!("{}", err)))?;
+
+    get(nonce.as_str()).ok_or(ApiError::Empty(()))
+}
@@ -0,0 +1,226 @@
+pub use crate::{
+    delivery::{DeliveryInfo, DeliveryOpcode, DeliveryPriority, DeliveryResult, TapInfo},
+    ring::{ShmReader, ShmWriter},
+};
+
+use futures_util::{Stream, StreamExt};
+use lapin::{
+    message::Delivery,
+    options::{BasicConsumeOptions, BasicPublishOptions, QueueBindOptions, QueueDeclareOptions},
+    types::FieldTable,
+    BasicProperties, Channel, Error as LapinError,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use simd_json::{owned::Value, Error as SimdJsonError};
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+use twilight_model::gateway::OpCode;
+
+pub const EXCHANGE: &str = "gateway";
+pub const QUEUE_SEND: &str = "gateway.send";
+pub const QUEUE_SEND_RESULTS: &str = "gateway.send.results";
+
+#[derive(Debug)]
+pub enum ClientError {
+    Lapin(LapinError),
+    SimdJson(SimdJsonError),
+}
+
+impl Error for ClientError {}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<LapinError> for ClientError {
+    fn from(err: LapinError) -> Self {
+        Self::Lapin(err)
+    }
+}
+
+impl From<SimdJsonError> for ClientError {
+    fn from(err: SimdJsonError) -> Self {
+        Self::SimdJson(err)
+    }
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Envelope<T = Value> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<u64>,
+    pub op: OpCode,
+    pub t: Option<String>,
+    pub d: T,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup: Option<bool>,
+}
+
+impl<T: DeserializeOwned> Envelope<T> {
+    pub fn from_slice(data: &mut [u8]) -> ClientResult<Self> {
+        Ok(simd_json::from_slice(data)?)
+    }
+}
+
+impl DeliveryInfo {
+    fn new(op: DeliveryOpcode, shard: u64, data: Option<Value>) -> Self {
+        Self {
+            op,
+            shard,
+            data,
+            priority: DeliveryPriority::Normal,
+            correlation_id: None,
+        }
+    }
+
+    pub fn send<T: Serialize>(shard: u64, command: &T) -> ClientResult<Self> {
+        let mut bytes = simd_json::to_vec(command)?;
+        let data = simd_json::owned::to_value(bytes.as_mut_slice())?;
+
+        Ok(Self::new(DeliveryOpcode::Send, shard, Some(data)))
+    }
+
+    pub fn reconnect(shard: u64) -> Self {
+        Self::new(DeliveryOpcode::Reconnect, shard, None)
+    }
+
+    pub fn tap(tap: Option<&TapInfo>) -> ClientResult<Self> {
+        let data = match tap {
+            Some(tap) => {
+                let mut bytes = simd_json::to_vec(tap)?;
+                Some(simd_json::owned::to_value(bytes.as_mut_slice())?)
+            }
+            None => None,
+        };
+
+        Ok(Self::new(DeliveryOpcode::Tap, 0, data))
+    }
+
+    pub fn replay() -> Self {
+        Self::new(DeliveryOpcode::Replay, 0, None)
+    }
+
+    pub fn chaos(shard: u64, data: Value) -> Self {
+        Self::new(DeliveryOpcode::Chaos, shard, Some(data))
+    }
+
+    pub fn shutdown() -> Self {
+        Self::new(DeliveryOpcode::Shutdown, 0, None)
+    }
+
+    pub fn drain() -> Self {
+        Self::new(DeliveryOpcode::Drain, 0, None)
+    }
+
+    pub fn priority(mut self, priority: DeliveryPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id = Some(correlation_id.into());
+        self
+    }
+}
+
+async fn declare_queue(channel: &Channel, queue: &str) -> ClientResult<()> {
+    channel
+        .queue_declare(
+            queue,
+            QueueDeclareOptions {
+                passive: false,
+                durable: true,
+                exclusive: false,
+                auto_delete: false,
+                nowait: false,
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn consume_queue(
+    channel: &Channel,
+    queue: &str,
+) -> ClientResult<impl Stream<Item = ClientResult<(Delivery, Vec<u8>)>>> {
+    let consumer = channel
+        .basic_consume(
+            queue,
+            "",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    Ok(consumer.map(|delivery| {
+        let delivery = delivery?;
+        let data = delivery.data.clone();
+        Ok((delivery, data))
+    }))
+}
+
+pub async fn consume<T: DeserializeOwned>(
+    channel: &Channel,
+    queue: &str,
+    kinds: &[&str],
+) -> ClientResult<impl Stream<Item = ClientResult<(Delivery, Envelope<T>)>>> {
+    declare_queue(channel, queue).await?;
+
+    let routing_keys = if kinds.is_empty() { &["#"][..] } else { kinds };
+    for routing_key in routing_keys {
+        channel
+            .queue_bind(
+                queue,
+                EXCHANGE,
+                routing_key,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    Ok(consume_queue(channel, queue).await?.map(|result| {
+        let (delivery, mut data) = result?;
+        let envelope = Envelope::from_slice(data.as_mut_slice())?;
+        Ok((delivery, envelope))
+    }))
+}
+
+pub async fn consume_results(
+    channel: &Channel,
+) -> ClientResult<impl Stream<Item = ClientResult<(Delivery, DeliveryResult)>>> {
+    declare_queue(channel, QUEUE_SEND_RESULTS).await?;
+
+    Ok(consume_queue(channel, QUEUE_SEND_RESULTS)
+        .await?
+        .map(|result| {
+            let (delivery, mut data) = result?;
+            let result = simd_json::from_slice(data.as_mut_slice())?;
+            Ok((delivery, result))
+        }))
+}
+
+pub async fn send(channel: &Channel, info: &DeliveryInfo) -> ClientResult<()> {
+    channel
+        .basic_publish(
+            "",
+            QUEUE_SEND,
+            BasicPublishOptions::default(),
+            &simd_json::to_vec(info)?,
+            BasicProperties::default(),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use crate::constants::{COMMAND_LIMIT, COMMAND_RESERVE, COMMAND_WINDOW};
+
+use lazy_static::lazy_static;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Instant,
+};
+use tokio::time::{sleep, Duration};
+
+lazy_static! {
+    static ref COMMANDS: Mutex<HashMap<u64, VecDeque<Instant>>> = Mutex::new(HashMap::new());
+}
+
+fn get_window() -> Duration {
+    Duration::from_millis(COMMAND_WINDOW as u64)
+}
+
+fn get_sent(commands: &mut VecDeque<Instant>) -> u64 {
+    while let Some(sent) = commands.front() {
+        if sent.elapsed() < get_window() {
+            break;
+        }
+        commands.pop_front();
+    }
+
+    commands.len() as u64
+}
+
+pub fn get_capacity(shard: u64) -> u64 {
+    let mut commands = COMMANDS.lock().unwrap();
+    let sent = commands.get_mut(&shard).map(get_sent).unwrap_or_default();
+
+    (COMMAND_LIMIT - COMMAND_RESERVE).saturating_sub(sent)
+}
+
+pub async fn reserve(shard: u64) {
+    loop {
+        let delay = {
+            let mut commands = COMMANDS.lock().unwrap();
+            let entry = commands.entry(shard).or_default();
+
+            if get_sent(entry) < COMMAND_LIMIT - COMMAND_RESERVE {
+                entry.push_back(Instant::now());
+                return;
+            }
+
+            entry
+                .front()
+                .map(|sent| get_window().saturating_sub(sent.elapsed()))
+                .unwrap_or_default()
+        };
+
+        sleep(delay).await;
+    }
+}
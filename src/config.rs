@@ -1,90 +1,359 @@
+use crate::{
+    constants::{ENVELOPE_VERSION_LATEST, SHARD_LOCK_TTL_MINIMUM},
+    models::ListenerInfo,
+};
+
 use lazy_static::lazy_static;
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Serialize};
 use std::env;
 use twilight_model::gateway::presence::{ActivityType, Status};
 
 lazy_static! {
     pub static ref CONFIG: Config = {
-        Config {
+        let config = Config {
             rust_log: get_env("RUST_LOG"),
             bot_token: get_env("BOT_TOKEN"),
             shards_start: get_env_as("SHARDS_START"),
             shards_end: get_env_as("SHARDS_END"),
             shards_total: get_env_as("SHARDS_TOTAL"),
+            tenant: get_env_or("TENANT", ""),
             shards_concurrency: get_env_as("SHARDS_CONCURRENCY"),
+            rest_global_limit: get_env_as_or("REST_GLOBAL_LIMIT", 0),
+            session_reserve: get_env_as_or("SESSION_RESERVE", 0),
+            session_alert: get_env_as_or("SESSION_ALERT", 0),
+            shard_lock_ttl: get_env_as_or("SHARD_LOCK_TTL", 0),
+            dedup_window: get_env_as_or("DEDUP_WINDOW", 0),
+            content_dedup_window: get_env_as_or("CONTENT_DEDUP_WINDOW", 0),
+            typing_window: get_env_as_or("TYPING_WINDOW", 0),
+            normalize_timestamps: get_env_as_or("NORMALIZE_TIMESTAMPS", false),
+            firehose_secret: get_env_or("FIREHOSE_SECRET", ""),
             shards_wait: get_env_as("SHARDS_WAIT"),
+            startup_prewarm: get_env_as_or("STARTUP_PREWARM", false),
+            startup_priority: get_env_as_or("STARTUP_PRIORITY", false),
             clusters: get_env_as("CLUSTERS"),
+            gateway_url: get_env_or("GATEWAY_URL", ""),
+            gateway_cluster_urls: get_env_as_or("GATEWAY_CLUSTER_URLS", vec![]),
+            gateway_proxy: get_env_as_or("GATEWAY_PROXY", false),
             default_queue: get_env_as("DEFAULT_QUEUE"),
+            envelope_version: get_env_as_or("ENVELOPE_VERSION", 1),
+            envelope_dual_version: get_env_as_or("ENVELOPE_DUAL_VERSION", 0),
             resume: get_env_as("RESUME"),
+            session_checkpoint: get_env_as_or("SESSION_CHECKPOINT", 0),
+            session_conflict_backoff: get_env_as_or("SESSION_CONFLICT_BACKOFF", 0),
+            close_codes_reidentify: get_env_as_or("CLOSE_CODES_REIDENTIFY", vec![]),
+            close_codes_halt: get_env_as_or("CLOSE_CODES_HALT", vec![]),
             intents: get_env_as("INTENTS"),
+            intents_fallback: get_env_as_or("INTENTS_FALLBACK", 0),
             large_threshold: get_env_as("LARGE_THRESHOLD"),
+            large_thresholds: get_env_as_or("LARGE_THRESHOLDS", vec![]),
+            large_threshold_target: get_env_as_or("LARGE_THRESHOLD_TARGET", 0),
+            payload_warn_size: get_env_as_or("PAYLOAD_WARN_SIZE", 0),
             status: get_env_as("STATUS"),
             activity_type: get_env_as("ACTIVITY_TYPE"),
             activity_name: get_env("ACTIVITY_NAME"),
+            degraded_presence_after: get_env_as_or("DEGRADED_PRESENCE_AFTER", 0),
+            degraded_status: get_env_as_or("DEGRADED_STATUS", Status::DoNotDisturb),
+            degraded_activity_name: get_env_or("DEGRADED_ACTIVITY_NAME", "Maintenance"),
             log_channel: get_env_as("LOG_CHANNEL"),
             log_guild_channel: get_env_as("LOG_GUILD_CHANNEL"),
+            guild_milestones: get_env_as_or("GUILD_MILESTONES", vec![]),
+            guild_drop_percent: get_env_as_or("GUILD_DROP_PERCENT", 0),
+            status_url: get_env_or("STATUS_URL", ""),
+            status_interval: get_env_as_or("STATUS_INTERVAL", 0),
+            sample_rate: get_env_as_or("SAMPLE_RATE", 0.0),
+            sample_events: get_env_as_or("SAMPLE_EVENTS", vec![]),
+            sample_path: get_env_or("SAMPLE_PATH", "samples"),
+            record_path: get_env_or("RECORD_PATH", ""),
+            snapshot_interval: get_env_as_or("SNAPSHOT_INTERVAL", 0),
+            snapshot_path: get_env_or("SNAPSHOT_PATH", "snapshots"),
+            capture_path: get_env_or("CAPTURE_PATH", "captures"),
+            validate_rate: get_env_as_or("VALIDATE_RATE", 0.0),
+            history_length: get_env_as_or("HISTORY_LENGTH", 0),
+            history_latency: get_env_as_or("HISTORY_LATENCY", 1000),
+            quality_threshold: get_env_as_or("QUALITY_THRESHOLD", 0),
+            anomaly_factor: get_env_as_or("ANOMALY_FACTOR", 0.0),
+            anomaly_minimum: get_env_as_or("ANOMALY_MINIMUM", 50),
             state_enabled: get_env_as("STATE_ENABLED"),
             state_member: get_env_as("STATE_MEMBER"),
             state_member_ttl: get_env_as("STATE_MEMBER_TTL"),
+            member_activity_threshold: get_env_as_or("MEMBER_ACTIVITY_THRESHOLD", 0),
             state_message: get_env_as("STATE_MESSAGE"),
             state_message_ttl: get_env_as("STATE_MESSAGE_TTL"),
             state_presence: get_env_as("STATE_PRESENCE"),
             state_old: get_env_as("STATE_OLD"),
+            state_moderation: get_env_as_or("STATE_MODERATION", false),
+            state_automod: get_env_as_or("STATE_AUTOMOD", false),
+            state_integrations: get_env_as_or("STATE_INTEGRATIONS", false),
+            state_forums: get_env_as_or("STATE_FORUMS", false),
+            state_layout: get_env_as_or("STATE_LAYOUT", 1),
+            state_chunk_size: get_env_as_or("STATE_CHUNK_SIZE", 0),
+            state_coalesce: get_env_as_or("STATE_COALESCE", 0),
+            state_write_behind: get_env_as_or("STATE_WRITE_BEHIND", 0),
+            state_memory_budget: get_env_as_or("STATE_MEMORY_BUDGET", 0),
+            reconcile_interval: get_env_as_or("RECONCILE_INTERVAL", 0),
+            reconcile_sample: get_env_as_or("RECONCILE_SAMPLE", 5),
+            prune_interval: get_env_as_or("PRUNE_INTERVAL", 0),
+            activity_window: get_env_as_or("ACTIVITY_WINDOW", 0),
+            usage_enabled: get_env_as_or("USAGE_ENABLED", false),
+            usage_ttl: get_env_as_or("USAGE_TTL", 604800),
             rabbit_host: get_env("RABBIT_HOST"),
             rabbit_port: get_env_as("RABBIT_PORT"),
             rabbit_username: get_env("RABBIT_USERNAME"),
             rabbit_password: get_env("RABBIT_PASSWORD"),
+            shed_queue: get_env_or("SHED_QUEUE", "gateway.recv"),
+            shed_events: get_env_as_or("SHED_EVENTS", vec![]),
+            shed_high: get_env_as_or("SHED_HIGH", 100000),
+            shed_low: get_env_as_or("SHED_LOW", 10000),
+            backpressure_latency: get_env_as_or("BACKPRESSURE_LATENCY", 0),
+            backpressure_shards: get_env_as_or("BACKPRESSURE_SHARDS", vec![]),
+            cache_update_deadline: get_env_as_or("CACHE_UPDATE_DEADLINE", 10000),
+            cache_deadline_detach: get_env_as_or("CACHE_DEADLINE_DETACH", false),
+            socket_path: get_env_or("SOCKET_PATH", ""),
+            shm_path: get_env_or("SHM_PATH", ""),
+            shm_size: get_env_as_or("SHM_SIZE", 67108864),
+            webhook_urls: get_env_as_or("WEBHOOK_URLS", vec![]),
+            webhook_events: get_env_as_or("WEBHOOK_EVENTS", vec![]),
+            webhook_secret: get_env_or("WEBHOOK_SECRET", ""),
+            authz_tokens: get_env_as_or("AUTHZ_TOKENS", vec![]),
+            authz_secret: get_env_or("AUTHZ_SECRET", ""),
+            authz_users: get_env_as_or("AUTHZ_USERS", vec![]),
+            signing_keys: get_env_as_or("SIGNING_KEYS", vec![]),
+            webhook_retries: get_env_as_or("WEBHOOK_RETRIES", 3),
+            webhook_concurrency: get_env_as_or("WEBHOOK_CONCURRENCY", 10),
+            mirror_rabbit_host: get_env_or("MIRROR_RABBIT_HOST", ""),
+            mirror_rabbit_port: get_env_as_or("MIRROR_RABBIT_PORT", 5672),
+            mirror_rabbit_username: get_env_or("MIRROR_RABBIT_USERNAME", "guest"),
+            mirror_rabbit_password: get_env_or("MIRROR_RABBIT_PASSWORD", "guest"),
+            mirror_rabbit_vhost: get_env_or("MIRROR_RABBIT_VHOST", "/"),
+            mirror_exchange: get_env_or("MIRROR_EXCHANGE", "gateway"),
+            mirror_sample_rate: get_env_as_or("MIRROR_SAMPLE_RATE", 100.0),
+            mirror_guilds: get_env_as_or("MIRROR_GUILDS", vec![]),
             redis_host: get_env("REDIS_HOST"),
             redis_port: get_env_as("REDIS_PORT"),
+            redis_tls: get_env_as_or("REDIS_TLS", false),
+            redis_username: get_env_or("REDIS_USERNAME", ""),
+            redis_password: get_env_or("REDIS_PASSWORD", ""),
+            redis_database: get_env_as_or("REDIS_DATABASE", 0),
+            redis_timeout: get_env_as_or("REDIS_TIMEOUT", 5000),
+            redis_status_url: get_env_or("REDIS_STATUS_URL", ""),
+            redis_expiry_url: get_env_or("REDIS_EXPIRY_URL", ""),
             prometheus_host: get_env("PROMETHEUS_HOST"),
             prometheus_port: get_env_as("PROMETHEUS_PORT"),
+            server_listeners: get_env_as_or("SERVER_LISTENERS", vec![]),
+            server_token: get_env_or("SERVER_TOKEN", ""),
+            server_username: get_env_or("SERVER_USERNAME", ""),
+            server_password: get_env_or("SERVER_PASSWORD", ""),
+            server_tls_cert: get_env_or("SERVER_TLS_CERT", ""),
+            server_tls_key: get_env_or("SERVER_TLS_KEY", ""),
+            pushgateway_url: get_env_or("PUSHGATEWAY_URL", ""),
+            pushgateway_job: get_env_or("PUSHGATEWAY_JOB", "twilight-dispatch"),
+            pushgateway_instance: get_env_or("PUSHGATEWAY_INSTANCE", "default"),
+            pushgateway_interval: get_env_as_or("PUSHGATEWAY_INTERVAL", 15000),
+        };
+
+        if config.shard_lock_ttl != 0 && config.shard_lock_ttl < SHARD_LOCK_TTL_MINIMUM {
+            panic!("Invalid environmental variable: SHARD_LOCK_TTL");
+        }
+
+        if config.state_layout == 0 || config.state_layout > 2 {
+            panic!("Invalid environmental variable: STATE_LAYOUT");
+        }
+
+        if config
+            .large_thresholds
+            .iter()
+            .any(|[from, to, threshold]| from > to || !(50..=250).contains(threshold))
+        {
+            panic!("Invalid environmental variable: LARGE_THRESHOLDS");
+        }
+
+        if config.state_coalesce != 0 && config.state_write_behind != 0 {
+            panic!("Invalid environmental variable: STATE_WRITE_BEHIND");
+        }
+
+        if config.envelope_version == 0 || config.envelope_version > ENVELOPE_VERSION_LATEST {
+            panic!("Invalid environmental variable: ENVELOPE_VERSION");
         }
+
+        if config.envelope_dual_version > ENVELOPE_VERSION_LATEST
+            || config.envelope_dual_version == config.envelope_version
+        {
+            panic!("Invalid environmental variable: ENVELOPE_DUAL_VERSION");
+        }
+
+        if config.signing_keys.iter().any(|key| {
+            key.split_once(':').is_none_or(|(id, secret)| {
+                id.is_empty() || id.contains(&['=', ','][..]) || secret.is_empty()
+            })
+        }) {
+            panic!("Invalid environmental variable: SIGNING_KEYS");
+        }
+
+        config
     };
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Config {
     pub rust_log: String,
     pub bot_token: String,
     pub shards_start: u64,
     pub shards_end: u64,
     pub shards_total: u64,
+    pub tenant: String,
     pub shards_concurrency: u64,
+    pub rest_global_limit: u64,
+    pub session_reserve: u64,
+    pub session_alert: u64,
+    pub shard_lock_ttl: u64,
+    pub dedup_window: u64,
+    pub content_dedup_window: u64,
+    pub typing_window: u64,
+    pub normalize_timestamps: bool,
+    pub firehose_secret: String,
     pub shards_wait: u64,
+    pub startup_prewarm: bool,
+    pub startup_priority: bool,
     pub clusters: u64,
+    pub gateway_url: String,
+    pub gateway_cluster_urls: Vec<String>,
+    pub gateway_proxy: bool,
     pub default_queue: bool,
+    pub envelope_version: u64,
+    pub envelope_dual_version: u64,
     pub resume: bool,
+    pub session_checkpoint: u64,
+    pub session_conflict_backoff: u64,
+    pub close_codes_reidentify: Vec<u16>,
+    pub close_codes_halt: Vec<u16>,
     pub intents: u64,
+    pub intents_fallback: u64,
     pub large_threshold: u64,
+    pub large_thresholds: Vec<[u64; 3]>,
+    pub large_threshold_target: u64,
+    pub payload_warn_size: u64,
     pub status: Status,
     pub activity_type: ActivityType,
     pub activity_name: String,
+    pub degraded_presence_after: u64,
+    pub degraded_status: Status,
+    pub degraded_activity_name: String,
     pub log_channel: u64,
     pub log_guild_channel: u64,
+    pub guild_milestones: Vec<u64>,
+    pub guild_drop_percent: u64,
+    pub status_url: String,
+    pub status_interval: u64,
+    pub sample_rate: f64,
+    pub sample_events: Vec<String>,
+    pub sample_path: String,
+    pub record_path: String,
+    pub snapshot_interval: u64,
+    pub snapshot_path: String,
+    pub capture_path: String,
+    pub validate_rate: f64,
+    pub history_length: u64,
+    pub history_latency: u64,
+    pub quality_threshold: i64,
+    pub anomaly_factor: f64,
+    pub anomaly_minimum: u64,
     pub state_enabled: bool,
     pub state_member: bool,
     pub state_member_ttl: u64,
+    pub member_activity_threshold: u64,
     pub state_message: bool,
     pub state_message_ttl: u64,
     pub state_presence: bool,
     pub state_old: bool,
+    pub state_moderation: bool,
+    pub state_automod: bool,
+    pub state_integrations: bool,
+    pub state_forums: bool,
+    pub state_layout: u64,
+    pub state_chunk_size: u64,
+    pub state_coalesce: u64,
+    pub state_write_behind: u64,
+    pub state_memory_budget: u64,
+    pub reconcile_interval: u64,
+    pub reconcile_sample: u64,
+    pub prune_interval: u64,
+    pub activity_window: u64,
+    pub usage_enabled: bool,
+    pub usage_ttl: u64,
     pub rabbit_host: String,
     pub rabbit_port: u64,
     pub rabbit_username: String,
     pub rabbit_password: String,
+    pub shed_queue: String,
+    pub shed_events: Vec<String>,
+    pub shed_high: u64,
+    pub shed_low: u64,
+    pub backpressure_latency: u64,
+    pub backpressure_shards: Vec<u64>,
+    pub cache_update_deadline: u64,
+    pub cache_deadline_detach: bool,
+    pub socket_path: String,
+    pub shm_path: String,
+    pub shm_size: u64,
+    pub webhook_urls: Vec<String>,
+    pub webhook_events: Vec<String>,
+    pub webhook_secret: String,
+    pub authz_tokens: Vec<String>,
+    pub authz_secret: String,
+    pub authz_users: Vec<String>,
+    pub signing_keys: Vec<String>,
+    pub webhook_retries: u64,
+    pub webhook_concurrency: u64,
+    pub mirror_rabbit_host: String,
+    pub mirror_rabbit_port: u64,
+    pub mirror_rabbit_username: String,
+    pub mirror_rabbit_password: String,
+    pub mirror_rabbit_vhost: String,
+    pub mirror_exchange: String,
+    pub mirror_sample_rate: f64,
+    pub mirror_guilds: Vec<u64>,
     pub redis_host: String,
     pub redis_port: u64,
+    pub redis_tls: bool,
+    pub redis_username: String,
+    pub redis_password: String,
+    pub redis_database: i64,
+    pub redis_timeout: u64,
+    pub redis_status_url: String,
+    pub redis_expiry_url: String,
     pub prometheus_host: String,
     pub prometheus_port: u64,
+    pub server_listeners: Vec<ListenerInfo>,
+    pub server_token: String,
+    pub server_username: String,
+    pub server_password: String,
+    pub server_tls_cert: String,
+    pub server_tls_key: String,
+    pub pushgateway_url: String,
+    pub pushgateway_job: String,
+    pub pushgateway_instance: String,
+    pub pushgateway_interval: u64,
 }
 
 fn get_env(name: &str) -> String {
     env::var(name).unwrap_or_else(|_| panic!("Missing environmental variable: {}", name))
 }
 
+fn get_env_or(name: &str, default: &str) -> String {
+    env::var(name).ok().unwrap_or_else(|| default.to_owned())
+}
+
 fn get_env_as<T: DeserializeOwned>(name: &str) -> T {
-    let mut variable = get_env(name);
+    parse_env(name, get_env(name))
+}
+
+fn get_env_as_or<T: DeserializeOwned>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .map_or(default, |variable| parse_env(name, variable))
+}
 
+fn parse_env<T: DeserializeOwned>(name: &str, mut variable: String) -> T {
     simd_json::from_str(variable.as_mut_str())
         .or_else(|_| simd_json::from_str(format!(r#""{}""#, variable).as_mut_str()))
         .unwrap_or_else(|_| panic!("Invalid environmental variable: {}", name))
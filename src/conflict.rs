@@ -0,0 +1,158 @@
+use crate::{
+    config::CONFIG,
+    constants::{CONFLICT_THRESHOLD, CONFLICT_WINDOW, HALT_COLOR, SESSIONS_KEY},
+    models::{ApiResult, SessionInfo},
+    targets,
+    utils::{get_tenant_key, log_discord},
+    webhook,
+};
+
+use lazy_static::lazy_static;
+use redis::AsyncCommands;
+use simd_json::json;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Instant,
+};
+use tokio::time::{sleep, Duration};
+use tracing::{error, warn};
+use twilight_gateway::Cluster;
+
+lazy_static! {
+    static ref INVALIDATIONS: Mutex<HashMap<u64, VecDeque<Instant>>> = Mutex::new(HashMap::new());
+    static ref BACKOFFS: Mutex<HashMap<u64, Instant>> = Mutex::new(HashMap::new());
+    static ref READIES: Mutex<HashMap<u64, Instant>> = Mutex::new(HashMap::new());
+    static ref DUMPED: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+pub fn record_ready(shard: u64) {
+    READIES.lock().unwrap().insert(shard, Instant::now());
+}
+
+pub fn record_dump(dumped: Instant) {
+    *DUMPED.lock().unwrap() = Some(dumped);
+}
+
+fn is_stored_stale(shard: u64) -> bool {
+    let ready = match READIES.lock().unwrap().get(&shard) {
+        Some(ready) => *ready,
+        None => return false,
+    };
+
+    DUMPED.lock().unwrap().is_none_or(|dumped| dumped < ready)
+}
+
+fn record_invalidation(shard: u64) -> u64 {
+    let mut invalidations = INVALIDATIONS.lock().unwrap();
+    let entry = invalidations.entry(shard).or_default();
+
+    entry.push_back(Instant::now());
+    while let Some(invalidated) = entry.front() {
+        if invalidated.elapsed() < Duration::from_millis(CONFLICT_WINDOW as u64) {
+            break;
+        }
+        entry.pop_front();
+    }
+
+    entry.len() as u64
+}
+
+async fn get_stored_session(
+    conn: &mut redis::aio::Connection,
+    shard: u64,
+) -> ApiResult<Option<SessionInfo>> {
+    let mut conn = targets::status(conn).await?;
+    let value: Option<String> = conn.hget(get_tenant_key(SESSIONS_KEY), shard).await?;
+
+    Ok(value.and_then(|mut value| simd_json::from_str(value.as_mut_str()).ok()))
+}
+
+fn alert(shard: u64, session_id: Option<&str>, conflicting: Option<&str>, invalidations: u64) {
+    let message = format!(
+        "[Shard {}] Another connection is using this shard (session: {}, conflicting session: {}, invalid sessions: {})",
+        shard,
+        session_id.unwrap_or("none"),
+        conflicting.unwrap_or("unknown"),
+        invalidations
+    );
+
+    error!("{}", message);
+    log_discord(HALT_COLOR, message);
+
+    if webhook::is_enabled("SESSION_CONFLICT") {
+        let payload = json!({
+            "op": 0,
+            "t": "SESSION_CONFLICT",
+            "d": {
+                "shard": shard,
+                "session_id": session_id,
+                "conflicting_session_id": conflicting,
+                "invalid_sessions": invalidations,
+            },
+        });
+
+        match simd_json::to_vec(&payload) {
+            Ok(payload) => webhook::dispatch(payload),
+            Err(err) => warn!("Failed to serialize session conflict: {:?}", err),
+        }
+    }
+}
+
+pub async fn record(conn: &mut redis::aio::Connection, cluster: &Cluster, shard: u64) {
+    let invalidations = record_invalidation(shard);
+
+    let session_id = cluster
+        .shard(shard)
+        .and_then(|shard| shard.info().ok())
+        .and_then(|info| info.session_id().map(|session_id| session_id.to_owned()));
+
+    let stored = if is_stored_stale(shard) {
+        Ok(None)
+    } else {
+        get_stored_session(conn, shard).await
+    };
+
+    let conflicting = match stored {
+        Ok(stored) => stored.map(|stored| stored.session_id).filter(|stored| {
+            matches!(&session_id, Some(session_id) if !stored.is_empty() && stored != session_id)
+        }),
+        Err(err) => {
+            warn!("[Shard {}] Failed to get stored session: {:?}", shard, err);
+            None
+        }
+    };
+
+    if conflicting.is_none() && invalidations < CONFLICT_THRESHOLD {
+        return;
+    }
+
+    INVALIDATIONS.lock().unwrap().remove(&shard);
+    alert(
+        shard,
+        session_id.as_deref(),
+        conflicting.as_deref(),
+        invalidations,
+    );
+
+    if CONFIG.session_conflict_backoff != 0 {
+        BACKOFFS.lock().unwrap().insert(
+            shard,
+            Instant::now() + Duration::from_millis(CONFIG.session_conflict_backoff),
+        );
+    }
+}
+
+pub async fn wait(shard: u64) {
+    let until = BACKOFFS.lock().unwrap().remove(&shard);
+
+    if let Some(until) = until {
+        let delay = until.saturating_duration_since(Instant::now());
+        warn!(
+            "[Shard {}] Backing off identifying for {}ms due to a session conflict",
+            shard,
+            delay.as_millis()
+        );
+        sleep(delay).await;
+    }
+}
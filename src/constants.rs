@@ -1,18 +1,37 @@
-use twilight_model::id::{
-    marker::{ChannelMarker, EmojiMarker, GuildMarker, MessageMarker, RoleMarker, UserMarker},
-    Id,
-};
+use crate::models::RegistryEntry;
 
 pub const EXCHANGE: &str = "gateway";
 pub const QUEUE_RECV: &str = "gateway.recv";
 pub const QUEUE_SEND: &str = "gateway.send";
+pub const QUEUE_SEND_RESULTS: &str = "gateway.send.results";
+
+pub const ENVELOPE_VERSION_LATEST: u64 = 2;
+
+pub const DEFAULT_GATEWAY_URL: &str = "wss://gateway.discord.gg";
 
 pub const SESSIONS_KEY: &str = "gateway_sessions";
 pub const STATUSES_KEY: &str = "gateway_statuses";
 pub const STARTED_KEY: &str = "gateway_started";
 pub const SHARDS_KEY: &str = "gateway_shards";
+pub const HISTORY_KEY: &str = "gateway_history";
+pub const TAP_KEY: &str = "gateway_tap";
+pub const USAGE_KEY: &str = "gateway_usage";
+pub const ACTIVITY_KEY: &str = "gateway_activity";
+pub const LOCK_KEY: &str = "gateway_lock";
+pub const TAKEOVER_KEY: &str = "gateway_takeover";
+pub const DEDUP_KEY: &str = "gateway_dedup";
+pub const OUTAGES_KEY: &str = "gateway_outages";
+pub const RATELIMIT_KEY: &str = "rest_ratelimit";
+pub const MEMBER_ACTIVITY_KEY: &str = "gateway_member_activity";
+pub const SCHEMA_KEY: &str = "cache_schema";
+pub const SCHEMA_LOCK_KEY: &str = "cache_schema_lock";
+pub const INTENTS_FALLBACK_KEY: &str = "gateway_intents_fallback";
+pub const GUILD_SIZES_KEY: &str = "gateway_guild_create_sizes";
+pub const THRESHOLDS_KEY: &str = "gateway_large_thresholds";
+pub const CLUSTERS_KEY: &str = "gateway_cluster_mapping";
 
 pub const BOT_USER_KEY: &str = "bot_user";
+pub const APPLICATION_KEY: &str = "application";
 pub const GUILD_KEY: &str = "guild";
 pub const CHANNEL_KEY: &str = "channel";
 pub const MESSAGE_KEY: &str = "message";
@@ -21,6 +40,26 @@ pub const EMOJI_KEY: &str = "emoji";
 pub const MEMBER_KEY: &str = "member";
 pub const PRESENCE_KEY: &str = "presence";
 pub const VOICE_KEY: &str = "voice";
+pub const ROLE_POSITIONS_KEY: &str = "role_positions";
+pub const CHANNEL_TREE_KEY: &str = "channel_tree";
+pub const BANS_KEY: &str = "guild_bans";
+pub const TIMEOUTS_KEY: &str = "guild_timeouts";
+pub const AUTOMOD_RULES_KEY: &str = "automod_rules";
+pub const INTEGRATIONS_KEY: &str = "guild_integrations";
+pub const COMMAND_PERMISSIONS_KEY: &str = "command_permissions";
+pub const FORUM_SETTINGS_KEY: &str = "forum_settings";
+
+pub const AUTOMOD_INTENTS: u64 = (1 << 20) | (1 << 21);
+pub const POLL_INTENTS: u64 = (1 << 24) | (1 << 25);
+
+pub const GUILD_ITEM_KEYS: [&str; 6] = [
+    CHANNEL_KEY,
+    ROLE_KEY,
+    EMOJI_KEY,
+    MEMBER_KEY,
+    PRESENCE_KEY,
+    VOICE_KEY,
+];
 
 pub const KEYS_SUFFIX: &str = "_keys";
 pub const EXPIRY_KEYS: &str = "expiry_keys";
@@ -28,46 +67,549 @@ pub const EXPIRY_KEYS: &str = "expiry_keys";
 pub const CACHE_DUMP_INTERVAL: usize = 1000;
 pub const CACHE_CLEANUP_INTERVAL: usize = 1000;
 pub const METRICS_DUMP_INTERVAL: usize = 1000;
+pub const LATENCY_WINDOW: usize = 600000;
+pub const LATENCY_QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+pub const USAGE_FLUSH_INTERVAL: usize = 5000;
+pub const SAMPLE_FLUSH_INTERVAL: usize = 5000;
+pub const RECORD_FLUSH_INTERVAL: usize = 1000;
+pub const RECORD_BUFFER_SIZE: usize = 10000;
+pub const REPLAY_BUFFER_SIZE: usize = 1000;
+pub const SNAPSHOT_POSITION: &str = "position.json";
+pub const CAPTURE_FLUSH_INTERVAL: usize = 1000;
+pub const TAP_FLUSH_INTERVAL: usize = 1000;
+pub const ANOMALY_INTERVAL: usize = 1000;
+pub const SHED_INTERVAL: usize = 1000;
+pub const BACKPRESSURE_INTERVAL: usize = 1000;
+pub const BACKPRESSURE_SUSTAIN: u64 = 5;
+pub const MEMORY_INTERVAL: usize = 10000;
+pub const MEMORY_RECOVERY: f64 = 0.8;
+pub const MEMORY_DISABLE_PERIOD: usize = 600000;
+pub const CACHE_DETACH_LIMIT: usize = 16;
+pub const FAILOVER_INTERVAL: usize = 1000;
+pub const MIRROR_RECONNECT_INTERVAL: usize = 5000;
+pub const SESSION_REFRESH_INTERVAL: usize = 600000;
+pub const APPLICATION_REFRESH_INTERVAL: usize = 600000;
+pub const CHUNK_NONCE_TTL: usize = 600000;
+pub const CHUNK_REPLY_PREFIX: &str = "reply_";
+pub const LEAVE_STATUS_TTL: usize = 3600000;
+pub const COMMAND_WINDOW: usize = 60000;
+pub const CONFLICT_WINDOW: usize = 60000;
+pub const CONFLICT_THRESHOLD: u64 = 3;
+pub const COMMAND_LIMIT: u64 = 120;
+pub const COMMAND_RESERVE: u64 = 5;
+pub const INTENTS_FALLBACK_TTL: usize = 86400;
+pub const POLICY_INTERVAL: usize = 60000;
+pub const THRESHOLD_FLUSH_INTERVAL: usize = 60000;
+pub const MILESTONE_INTERVAL: usize = 10000;
+pub const INCIDENT_WINDOW: usize = 300000;
+pub const TAKEOVER_INTERVAL: usize = 1000;
+pub const TAKEOVER_TIMEOUT: usize = 60000;
+pub const TAKEOVER_DRAIN: usize = 5000;
+
+pub const EVENT_BUFFER_SIZE: usize = 1000;
+pub const COMMAND_BUFFER_SIZE: usize = 100;
+pub const SERVER_BODY_LIMIT: usize = 1048576;
+pub const SERVER_ACCEPT_BACKOFF: usize = 100;
+pub const SERVER_CONNECTION_LIMIT: usize = 10;
+pub const IPC_BUFFER_SIZE: usize = 10000;
+pub const FIREHOSE_BUFFER_SIZE: usize = 10000;
+pub const FIREHOSE_SUBSCRIBER_LIMIT: usize = 100;
+pub const MIRROR_BUFFER_SIZE: usize = 10000;
+pub const WEBHOOK_BUFFER_SIZE: usize = 1000;
+pub const FIREHOSE_TOKEN_TTL: u64 = 3600;
+pub const ACTIVITY_THROTTLE: u64 = 60;
+pub const USER_PURGE_CHUNK: usize = 1000;
+pub const MIGRATE_CHUNK: usize = 1000;
+pub const SCHEMA_MIGRATE_INTERVAL: usize = 100;
+pub const SCHEMA_RETRY_INTERVAL: usize = 10000;
+pub const SCHEMA_LOCK_TTL: u64 = 60000;
+pub const SHARD_LOCK_TTL_MINIMUM: u64 = 3000;
+pub const CACHE_SCHEMA_VERSION: u64 = 1;
+pub const SCHEMA_DIR: &str = "schema";
+pub const AUTHZ_TOKEN_HEADER: &str = "x-authz-token";
+pub const AUTHZ_SIGNATURE_HEADER: &str = "x-signature";
+pub const AUTHZ_EXPIRES_HEADER: &str = "x-expires";
+pub const SIGNATURE_HEADER: &str = "x-payload-signature";
+pub const PAYLOAD_PEEK_LENGTH: usize = 64;
+pub const GUILD_CREATE_MARKER: &[u8] = b"\"t\":\"GUILD_CREATE\"";
+pub const KIND_MARKER: &[u8] = b"\"t\":\"";
+
+pub const REDACTED_KEYS: [&str; 17] = [
+    "bot_token",
+    "rabbit_password",
+    "mirror_rabbit_password",
+    "redis_password",
+    "redis_status_url",
+    "redis_expiry_url",
+    "server_token",
+    "server_password",
+    "webhook_secret",
+    "authz_tokens",
+    "authz_secret",
+    "signing_keys",
+    "firehose_secret",
+    "pushgateway_url",
+    "webhook_urls",
+    "gateway_url",
+    "gateway_cluster_urls",
+];
+
+pub const SNAPSHOT_FIELDS: [&str; 8] = [
+    "channels",
+    "emojis",
+    "members",
+    "presences",
+    "roles",
+    "stage_instances",
+    "threads",
+    "voice_states",
+];
+
+pub const PRIVILEGED_ROUTES: [(&str, &[&str]); 19] = [
+    ("GET", &["config"]),
+    ("GET", &["guilds", "*", "export"]),
+    ("GET", &["guilds", "*", "events"]),
+    ("GET", &["guilds", "*", "events", "token"]),
+    ("POST", &["guilds", "leave"]),
+    ("DELETE", &["guilds", "leave", "*"]),
+    ("DELETE", &["guilds", "*"]),
+    ("POST", &["guilds", "*", "request-members"]),
+    ("POST", &["guilds", "*", "migrate"]),
+    ("DELETE", &["users", "*"]),
+    ("PUT", &["log", "filter"]),
+    ("POST", &["log", "capture"]),
+    ("GET", &["guilds", "*", "moderation", "*"]),
+    ("GET", &["guilds", "*", "integrations"]),
+    ("GET", &["guilds", "*", "commands", "permissions"]),
+    ("GET", &["shards", "*", "history"]),
+    ("GET", &["guilds", "*", "at", "*"]),
+    ("GET", &["guilds", "*", "at", "*", "channels", "*"]),
+    ("GET", &["guilds", "*", "at", "*", "members", "*"]),
+];
+
+pub const REGISTRY: [RegistryEntry; 47] = [
+    RegistryEntry {
+        kind: "exchange",
+        name: EXCHANGE,
+        pattern: "gateway",
+        tenant: false,
+        description: "Exchange the gateway events are published to, routed by event name",
+    },
+    RegistryEntry {
+        kind: "exchange",
+        name: EXCHANGE,
+        pattern: "gateway.v{version}",
+        tenant: false,
+        description: "Exchange for events in the dual envelope version",
+    },
+    RegistryEntry {
+        kind: "queue",
+        name: QUEUE_RECV,
+        pattern: "gateway.recv",
+        tenant: false,
+        description: "Queue bound to the exchange for all gateway events",
+    },
+    RegistryEntry {
+        kind: "queue",
+        name: QUEUE_SEND,
+        pattern: "gateway.send",
+        tenant: false,
+        description: "Queue consumed for gateway commands to send",
+    },
+    RegistryEntry {
+        kind: "queue",
+        name: QUEUE_SEND_RESULTS,
+        pattern: "gateway.send.results",
+        tenant: false,
+        description: "Queue the results of sent gateway commands are published to",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: BOT_USER_KEY,
+        pattern: "bot_user",
+        tenant: false,
+        description: "Bot user object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: APPLICATION_KEY,
+        pattern: "application",
+        tenant: false,
+        description: "Bot application object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: GUILD_KEY,
+        pattern: "guild:guild_id",
+        tenant: false,
+        description: "Guild object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: CHANNEL_KEY,
+        pattern: "channel:channel_id",
+        tenant: false,
+        description: "Channel object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: MESSAGE_KEY,
+        pattern: "message:channel_id:message_id",
+        tenant: false,
+        description: "Channel message object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: ROLE_KEY,
+        pattern: "role:guild_id:role_id",
+        tenant: false,
+        description: "Guild role object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: EMOJI_KEY,
+        pattern: "emoji:guild_id:emoji_id",
+        tenant: false,
+        description: "Guild emoji object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: MEMBER_KEY,
+        pattern: "member:guild_id:user_id",
+        tenant: false,
+        description: "Guild member object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: PRESENCE_KEY,
+        pattern: "presence:guild_id:user_id",
+        tenant: false,
+        description: "Guild member presence object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: VOICE_KEY,
+        pattern: "voice:guild_id:user_id",
+        tenant: false,
+        description: "Guild member voice state object",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: ROLE_POSITIONS_KEY,
+        pattern: "role_positions:guild_id",
+        tenant: false,
+        description: "Sorted set of role IDs by position",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: CHANNEL_TREE_KEY,
+        pattern: "channel_tree:guild_id",
+        tenant: false,
+        description: "Hash of channel positions and parents",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: BANS_KEY,
+        pattern: "guild_bans:guild_id",
+        tenant: false,
+        description: "Guild bans",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: TIMEOUTS_KEY,
+        pattern: "guild_timeouts:guild_id",
+        tenant: false,
+        description: "Guild member timeouts",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: AUTOMOD_RULES_KEY,
+        pattern: "automod_rules:guild_id",
+        tenant: false,
+        description: "Guild auto moderation rules",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: INTEGRATIONS_KEY,
+        pattern: "guild_integrations:guild_id",
+        tenant: false,
+        description: "Guild integrations",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: COMMAND_PERMISSIONS_KEY,
+        pattern: "command_permissions:guild_id",
+        tenant: false,
+        description: "Guild application command permissions",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: FORUM_SETTINGS_KEY,
+        pattern: "forum_settings:guild_id",
+        tenant: false,
+        description: "Guild forum channel settings",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: KEYS_SUFFIX,
+        pattern: "kind_keys",
+        tenant: false,
+        description: "Set of the keys of an object type, such as member_keys",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: KEYS_SUFFIX,
+        pattern: "guild_keys:guild_id",
+        tenant: false,
+        description: "Set of the keys related to a guild",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: KEYS_SUFFIX,
+        pattern: "channel_keys:channel_id",
+        tenant: false,
+        description: "Set of the keys related to a channel",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: EXPIRY_KEYS,
+        pattern: "expiry_keys",
+        tenant: false,
+        description: "Hash of cache keys and their expiry timestamps",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: SCHEMA_KEY,
+        pattern: "cache_schema",
+        tenant: false,
+        description: "Hash of the layout and format version of the cache",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: SCHEMA_LOCK_KEY,
+        pattern: "cache_schema_lock",
+        tenant: false,
+        description: "Instance currently migrating the cache schema",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: SESSIONS_KEY,
+        pattern: "gateway_sessions",
+        tenant: true,
+        description: "Hash of the session of every shard",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: STATUSES_KEY,
+        pattern: "gateway_statuses",
+        tenant: true,
+        description: "Status of every cluster and shard",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: STARTED_KEY,
+        pattern: "gateway_started",
+        tenant: true,
+        description: "Timestamp the service was started at",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: SHARDS_KEY,
+        pattern: "gateway_shards",
+        tenant: true,
+        description: "Total number of shards",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: CLUSTERS_KEY,
+        pattern: "gateway_cluster_mapping:from-to",
+        tenant: true,
+        description: "Shard range of every cluster",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: HISTORY_KEY,
+        pattern: "gateway_history:shard",
+        tenant: false,
+        description: "List of the recent connection events of a shard",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: TAP_KEY,
+        pattern: "gateway_tap",
+        tenant: false,
+        description: "List of tapped payloads",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: USAGE_KEY,
+        pattern: "gateway_usage:guild_id:hour",
+        tenant: false,
+        description: "Hash of the event counts of a guild per hour",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: ACTIVITY_KEY,
+        pattern: "gateway_activity",
+        tenant: false,
+        description: "Sorted set of guilds by last activity",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: MEMBER_ACTIVITY_KEY,
+        pattern: "gateway_member_activity:hour",
+        tenant: false,
+        description: "Sorted set of guilds by member activity in an hour",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: LOCK_KEY,
+        pattern: "gateway_lock:shard",
+        tenant: true,
+        description: "Owner of the lock of a shard",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: TAKEOVER_KEY,
+        pattern: "gateway_takeover",
+        tenant: true,
+        description: "State of a running deployment takeover",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: DEDUP_KEY,
+        pattern: "gateway_dedup:shard:sequence",
+        tenant: true,
+        description: "Marker of a published event",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: OUTAGES_KEY,
+        pattern: "gateway_outages",
+        tenant: true,
+        description: "Hash of unavailable guilds and when they became unavailable",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: RATELIMIT_KEY,
+        pattern: "rest_ratelimit:scope",
+        tenant: true,
+        description: "Global REST ratelimit counter and lock, shared between instances",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: INTENTS_FALLBACK_KEY,
+        pattern: "gateway_intents_fallback",
+        tenant: true,
+        description: "Close code the intents fallback was triggered by",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: GUILD_SIZES_KEY,
+        pattern: "gateway_guild_create_sizes",
+        tenant: true,
+        description: "Hash of the average guild create sizes per shard",
+    },
+    RegistryEntry {
+        kind: "key",
+        name: THRESHOLDS_KEY,
+        pattern: "gateway_large_thresholds",
+        tenant: true,
+        description: "Hash of the tuned large thresholds per shard",
+    },
+];
+
+pub const SET_SCRIPT_SOURCE: &str = r"
+local count = tonumber(ARGV[1])
+for i = 1, count do
+    redis.call('SET', KEYS[i], ARGV[i + 1])
+end
+local index = count + 2
+for i = count + 1, #KEYS do
+    local size = tonumber(ARGV[index])
+    for j = 1, size do
+        redis.call('SADD', KEYS[i], ARGV[index + j])
+    end
+    index = index + size + 1
+end
+return 0
+";
+
+pub const DEL_SCRIPT_SOURCE: &str = r"
+local count = tonumber(ARGV[1])
+for i = 1, count do
+    redis.call('DEL', KEYS[i])
+end
+local index = 2
+for i = count + 1, #KEYS do
+    local size = tonumber(ARGV[index])
+    for j = 1, size do
+        redis.call('SREM', KEYS[i], ARGV[index + j])
+    end
+    index = index + size + 1
+end
+return 0
+";
 
 pub const CONNECT_COLOR: usize = 0x00FF00;
 pub const DISCONNECT_COLOR: usize = 0xFF0000;
 pub const READY_COLOR: usize = 0x00FF00;
 pub const RESUME_COLOR: usize = 0x1E90FF;
+pub const HALT_COLOR: usize = 0x8B0000;
+pub const DEGRADED_COLOR: usize = 0xFFA500;
+pub const ANOMALY_COLOR: usize = 0xFF4500;
+pub const MILESTONE_COLOR: usize = 0xFFD700;
+pub const INCIDENT_COLOR: usize = 0x9400D3;
 pub const JOIN_COLOR: usize = 0x00FF00;
 pub const LEAVE_COLOR: usize = 0xFF0000;
 
-pub fn guild_key(guild: Id<GuildMarker>) -> String {
-    format!("{}:{}", GUILD_KEY, guild)
+pub fn history_key(shard: u64) -> String {
+    format!("{}:{}", HISTORY_KEY, shard)
 }
 
-pub fn channel_key(guild: Id<GuildMarker>, channel: Id<ChannelMarker>) -> String {
-    format!("{}:{}:{}", CHANNEL_KEY, guild, channel)
+pub fn usage_key(guild: &str, hour: u64) -> String {
+    format!("{}:{}:{}", USAGE_KEY, guild, hour)
 }
 
-pub fn private_channel_key(channel: Id<ChannelMarker>) -> String {
-    format!("{}:{}", CHANNEL_KEY, channel)
-}
-
-pub fn message_key(channel: Id<ChannelMarker>, message: Id<MessageMarker>) -> String {
-    format!("{}:{}:{}", MESSAGE_KEY, channel, message)
-}
+pub const LOCK_REFRESH_SCRIPT_SOURCE: &str = r"
+local lost = 0
+for i = 1, #KEYS do
+    local owner = redis.call('GET', KEYS[i])
+    if owner == ARGV[1] then
+        redis.call('PEXPIRE', KEYS[i], ARGV[2])
+    elseif not owner then
+        redis.call('SET', KEYS[i], ARGV[1], 'PX', ARGV[2])
+    else
+        lost = lost + 1
+    end
+end
+return lost
+";
 
-pub fn role_key(guild: Id<GuildMarker>, role: Id<RoleMarker>) -> String {
-    format!("{}:{}:{}", ROLE_KEY, guild, role)
-}
-
-pub fn emoji_key(guild: Id<GuildMarker>, emoji: Id<EmojiMarker>) -> String {
-    format!("{}:{}:{}", EMOJI_KEY, guild, emoji)
-}
+pub const LOCK_RELEASE_SCRIPT_SOURCE: &str = r"
+local released = 0
+for i = 1, #KEYS do
+    if redis.call('GET', KEYS[i]) == ARGV[1] then
+        redis.call('DEL', KEYS[i])
+        released = released + 1
+    end
+end
+return released
+";
 
-pub fn member_key(guild: Id<GuildMarker>, member: Id<UserMarker>) -> String {
-    format!("{}:{}:{}", MEMBER_KEY, guild, member)
-}
-
-pub fn presence_key(guild: Id<GuildMarker>, member: Id<UserMarker>) -> String {
-    format!("{}:{}:{}", PRESENCE_KEY, guild, member)
-}
-
-pub fn voice_key(guild: Id<GuildMarker>, member: Id<UserMarker>) -> String {
-    format!("{}:{}:{}", VOICE_KEY, guild, member)
-}
+pub const RATELIMIT_SCRIPT_SOURCE: &str = r"
+local locked = redis.call('PTTL', KEYS[2])
+if locked > 0 then
+    return locked
+end
+local count = redis.call('INCR', KEYS[1])
+local ttl = redis.call('PTTL', KEYS[1])
+if ttl < 0 then
+    redis.call('PEXPIRE', KEYS[1], 1000)
+    ttl = 1000
+end
+if count > tonumber(ARGV[1]) then
+    return ttl
+end
+return 0
+";
@@ -0,0 +1,84 @@
+use crate::{config::CONFIG, models::PayloadInfo};
+
+use lazy_static::lazy_static;
+use simd_json::{owned::Value, ValueAccess};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::Hasher,
+    sync::Mutex,
+    time::Instant,
+};
+use tokio::time::{sleep, Duration};
+
+lazy_static! {
+    static ref HASHES: Mutex<HashMap<String, (u64, Instant)>> = Mutex::new(HashMap::new());
+}
+
+fn get_key(kind: &str, payload: &PayloadInfo) -> Option<String> {
+    let (scope, user) = match kind {
+        "PRESENCE_UPDATE" => (
+            payload.d.get_str("guild_id")?,
+            payload.d.get("user")?.get_str("id")?,
+        ),
+        "TYPING_START" => (
+            payload.d.get_str("channel_id")?,
+            payload.d.get_str("user_id")?,
+        ),
+        _ => return None,
+    };
+
+    Some(format!("{}:{}:{}", kind, scope, user))
+}
+
+fn get_hash(payload: &PayloadInfo) -> Option<u64> {
+    let mut data = payload.d.clone();
+    if let Value::Object(object) = &mut data {
+        object.remove("timestamp");
+    }
+
+    let mut hasher = DefaultHasher::new();
+    hasher.write(simd_json::to_vec(&data).ok()?.as_slice());
+
+    Some(hasher.finish())
+}
+
+pub fn is_repeated(kind: &str, payload: &PayloadInfo) -> bool {
+    if CONFIG.content_dedup_window == 0 {
+        return false;
+    }
+
+    let key = match get_key(kind, payload) {
+        Some(key) => key,
+        None => return false,
+    };
+    let hash = match get_hash(payload) {
+        Some(hash) => hash,
+        None => return false,
+    };
+
+    let window = Duration::from_millis(CONFIG.content_dedup_window);
+    let now = Instant::now();
+
+    let mut hashes = HASHES.lock().unwrap();
+    match hashes.insert(key, (hash, now)) {
+        Some((previous, seen)) => previous == hash && now.duration_since(seen) < window,
+        None => false,
+    }
+}
+
+pub async fn run_jobs() {
+    if CONFIG.content_dedup_window == 0 {
+        return;
+    }
+
+    let window = Duration::from_millis(CONFIG.content_dedup_window);
+
+    loop {
+        sleep(window).await;
+
+        HASHES
+            .lock()
+            .unwrap()
+            .retain(|_, (_, seen)| seen.elapsed() < window);
+    }
+}
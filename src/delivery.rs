@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+use simd_json::owned::Value;
+
+#[derive(Clone, Copy, Debug, Deserialize_repr, Serialize_repr, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DeliveryOpcode {
+    Send,
+    Reconnect,
+    Tap,
+    Replay,
+    Chaos,
+    Shutdown,
+    Drain,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize_repr, Serialize_repr, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum DeliveryPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TapInfo {
+    #[serde(default)]
+    pub kinds: Vec<String>,
+    #[serde(default)]
+    pub guild_id: Option<String>,
+    pub limit: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeliveryInfo {
+    pub op: DeliveryOpcode,
+    pub shard: u64,
+    pub data: Option<Value>,
+    #[serde(default)]
+    pub priority: DeliveryPriority,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeliveryResult {
+    pub correlation_id: String,
+    pub shard: u64,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
@@ -0,0 +1,231 @@
+use crate::{
+    cache,
+    config::CONFIG,
+    constants::{DEDUP_KEY, TAKEOVER_DRAIN, TAKEOVER_INTERVAL, TAKEOVER_KEY, TAKEOVER_TIMEOUT},
+    lock,
+    models::{ApiError, ApiResult},
+    recorder, sampler,
+    utils::{dump_sessions, get_tenant_key, save_sessions},
+};
+
+use lazy_static::lazy_static;
+use redis::AsyncCommands;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::Notify,
+    time::{sleep, Duration},
+};
+use tracing::{info, warn};
+use twilight_gateway::Cluster;
+
+const REQUESTED: &str = "requested";
+const DONE: &str = "done";
+
+static DRAINING: AtomicBool = AtomicBool::new(false);
+static HANDED_OVER: AtomicBool = AtomicBool::new(false);
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+static DEDUP_UNTIL: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref SHUTDOWN: Notify = Notify::new();
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn start_dedup(duration: u64) {
+    if CONFIG.dedup_window != 0 {
+        DEDUP_UNTIL.fetch_max(now() + duration, Ordering::Relaxed);
+    }
+}
+
+pub fn request_shutdown() {
+    SHUTDOWN.notify_one();
+}
+
+pub async fn wait_shutdown() {
+    SHUTDOWN.notified().await;
+}
+
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::Relaxed)
+}
+
+pub async fn drain(conn: &mut redis::aio::Connection, clusters: &[Arc<Cluster>]) -> ApiResult<()> {
+    if DRAINING.swap(true, Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    info!("Draining, events will no longer be published");
+
+    HANDED_OVER.store(true, Ordering::Relaxed);
+    let result = async {
+        dump_sessions(conn, clusters).await?;
+        cache::flush(conn).await
+    }
+    .await;
+
+    if result.is_err() {
+        HANDED_OVER.store(false, Ordering::Relaxed);
+        DRAINING.store(false, Ordering::Relaxed);
+    }
+
+    result
+}
+
+pub async fn shutdown(
+    conn: &mut redis::aio::Connection,
+    clusters: &[Arc<Cluster>],
+) -> ApiResult<()> {
+    if SHUT_DOWN.swap(true, Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    info!("Shutting down");
+
+    let replaying = recorder::is_replaying();
+    if !HANDED_OVER.load(Ordering::Relaxed) && !replaying {
+        save_sessions(conn, clusters).await?;
+    }
+    cache::flush(conn).await?;
+    sampler::flush().await;
+    if !replaying {
+        lock::release(conn).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn is_duplicate(
+    conn: &mut redis::aio::Connection,
+    cluster: &Cluster,
+    shard: u64,
+    sequence: u64,
+) -> bool {
+    if DEDUP_UNTIL.load(Ordering::Relaxed) < now() {
+        return false;
+    }
+
+    let session_id = match cluster
+        .shard(shard)
+        .and_then(|shard| shard.info().ok())
+        .and_then(|info| info.session_id().map(|session_id| session_id.to_owned()))
+    {
+        Some(session_id) => session_id,
+        None => return false,
+    };
+
+    let result: ApiResult<Option<String>> = redis::cmd("SET")
+        .arg(get_tenant_key(
+            format!("{}:{}:{}", DEDUP_KEY, session_id, sequence).as_str(),
+        ))
+        .arg(1)
+        .arg("NX")
+        .arg("PX")
+        .arg(CONFIG.dedup_window)
+        .query_async(conn)
+        .await
+        .map_err(Into::into);
+
+    match result {
+        Ok(value) => value.is_none(),
+        Err(err) => {
+            warn!("[Shard {}] Failed to check duplicate: {:?}", shard, err);
+            false
+        }
+    }
+}
+
+pub async fn request_takeover(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    info!("Requesting takeover from the running instance");
+
+    let _: () = conn
+        .set_ex(
+            get_tenant_key(TAKEOVER_KEY),
+            REQUESTED,
+            TAKEOVER_TIMEOUT / 1000,
+        )
+        .await?;
+
+    let started = Instant::now();
+    loop {
+        let status: Option<String> = conn.get(get_tenant_key(TAKEOVER_KEY)).await?;
+        if status.as_deref() == Some(DONE) {
+            info!("Took over sessions from the running instance");
+            break;
+        }
+
+        if started.elapsed().as_millis() as usize > TAKEOVER_TIMEOUT {
+            let _: () = conn.del(get_tenant_key(TAKEOVER_KEY)).await?;
+            return Err(ApiError::TakeoverTimeout);
+        }
+
+        sleep(Duration::from_millis(TAKEOVER_INTERVAL as u64)).await;
+    }
+
+    let _: () = conn.del(get_tenant_key(TAKEOVER_KEY)).await?;
+    start_dedup(CONFIG.dedup_window);
+
+    Ok(())
+}
+
+async fn hand_over(conn: &mut redis::aio::Connection, clusters: &[Arc<Cluster>]) -> ApiResult<()> {
+    info!("Handing over sessions to the new instance");
+
+    start_dedup((TAKEOVER_TIMEOUT + TAKEOVER_DRAIN) as u64);
+    HANDED_OVER.store(true, Ordering::Relaxed);
+    save_sessions(conn, clusters).await?;
+    cache::flush(conn).await?;
+    lock::release(conn).await?;
+
+    let _: () = conn
+        .set_ex(get_tenant_key(TAKEOVER_KEY), DONE, TAKEOVER_TIMEOUT / 1000)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection, clusters: &[Arc<Cluster>]) {
+    if recorder::is_replaying() {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(TAKEOVER_INTERVAL as u64)).await;
+
+        let status: Option<String> = match conn.get(get_tenant_key(TAKEOVER_KEY)).await {
+            Ok(status) => status,
+            Err(err) => {
+                warn!("Failed to get takeover status: {:?}", err);
+                continue;
+            }
+        };
+
+        if status.as_deref() != Some(REQUESTED) {
+            continue;
+        }
+
+        if let Err(err) = hand_over(conn, clusters).await {
+            HANDED_OVER.store(false, Ordering::Relaxed);
+            warn!("Failed to hand over sessions: {:?}", err);
+            continue;
+        }
+
+        sleep(Duration::from_millis(TAKEOVER_DRAIN as u64)).await;
+
+        info!("Shutting down after takeover");
+        request_shutdown();
+
+        return;
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    commands,
+    config::CONFIG,
+    constants::{DEGRADED_COLOR, FAILOVER_INTERVAL, RESUME_COLOR},
+    metrics::GATEWAY_FAILOVER,
+    utils::{get_activity, log_discord},
+};
+
+use lapin::Channel;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+use twilight_gateway::Cluster;
+use twilight_model::gateway::{payload::outgoing::UpdatePresence, presence::Status};
+
+static FAILING_SINCE: AtomicU64 = AtomicU64::new(0);
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+pub fn record(success: bool) {
+    if success {
+        if FAILING_SINCE.load(Ordering::Relaxed) != 0 {
+            FAILING_SINCE.store(0, Ordering::Relaxed);
+        }
+    } else if FAILING_SINCE.load(Ordering::Relaxed) == 0 {
+        let _ = FAILING_SINCE.compare_exchange(0, now(), Ordering::Relaxed, Ordering::Relaxed);
+    }
+}
+
+async fn set_presence(clusters: &[Arc<Cluster>], status: Status, name: String) {
+    let presence = match UpdatePresence::new(vec![get_activity(name)], false, None, status) {
+        Ok(presence) => presence,
+        Err(err) => {
+            warn!("Failed to create presence: {:?}", err);
+            return;
+        }
+    };
+
+    for cluster in clusters {
+        for (shard, _) in cluster.info() {
+            commands::reserve(shard).await;
+            if let Err(err) = cluster.command(shard, &presence).await {
+                warn!("[Shard {}] Failed to update presence: {:?}", shard, err);
+            }
+        }
+    }
+}
+
+pub async fn run_jobs(channel: Channel, clusters: Vec<Arc<Cluster>>) {
+    if CONFIG.degraded_presence_after == 0 {
+        return;
+    }
+
+    let mut degraded = false;
+
+    loop {
+        sleep(Duration::from_millis(FAILOVER_INTERVAL as u64)).await;
+
+        if !channel.status().connected() {
+            record(false);
+        }
+
+        let since = FAILING_SINCE.load(Ordering::Relaxed);
+        let failing =
+            since != 0 && now().saturating_sub(since) / 1000 >= CONFIG.degraded_presence_after;
+
+        if failing && !degraded {
+            degraded = true;
+            GATEWAY_FAILOVER.set(1);
+            warn!("Broker unreachable, switching to degraded presence");
+            log_discord(
+                DEGRADED_COLOR,
+                "Broker unreachable, switched to degraded presence",
+            );
+            set_presence(
+                clusters.as_slice(),
+                CONFIG.degraded_status,
+                CONFIG.degraded_activity_name.clone(),
+            )
+            .await;
+        } else if !failing && degraded {
+            degraded = false;
+            GATEWAY_FAILOVER.set(0);
+            info!("Broker reachable, restoring presence");
+            log_discord(RESUME_COLOR, "Broker reachable, restored presence");
+            set_presence(
+                clusters.as_slice(),
+                CONFIG.status,
+                CONFIG.activity_name.clone(),
+            )
+            .await;
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{
+    deploy,
+    models::ApiResult,
+    utils::{publish_event, to_value},
+};
+
+use lapin::Channel;
+use simd_json::{json, owned::Value, ValueAccess};
+use std::collections::BTreeSet;
+use tracing::warn;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+fn get_names(features: &Value) -> BTreeSet<String> {
+    features
+        .as_array()
+        .map(|features| {
+            features
+                .iter()
+                .filter_map(|feature| feature.as_str().map(|feature| feature.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn publish_changes(
+    channel: &Channel,
+    guild_id: Id<GuildMarker>,
+    old: &Value,
+    features: &[String],
+) -> ApiResult<()> {
+    let old = get_names(old);
+    let new: BTreeSet<String> = features.iter().cloned().collect();
+
+    let added: Vec<&String> = new.difference(&old).collect();
+    let removed: Vec<&String> = old.difference(&new).collect();
+    if added.is_empty() && removed.is_empty() {
+        return Ok(());
+    }
+
+    let data = json!({
+        "guild_id": guild_id.to_string(),
+        "added": to_value(&added)?,
+        "removed": to_value(&removed)?,
+    });
+
+    publish_event(channel, "GUILD_FEATURES_CHANGED", data).await
+}
+
+pub async fn publish(
+    channel: &Channel,
+    guild_id: Id<GuildMarker>,
+    old: Option<&Value>,
+    features: &[String],
+) {
+    let old = match old {
+        Some(old) if !deploy::is_draining() => old,
+        _ => return,
+    };
+
+    if let Err(err) = publish_changes(channel, guild_id, old, features).await {
+        warn!(
+            "Failed to publish feature changes of guild {}: {:?}",
+            guild_id, err
+        );
+    }
+}
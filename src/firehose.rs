@@ -0,0 +1,137 @@
+use crate::{
+    config::CONFIG,
+    constants::{FIREHOSE_BUFFER_SIZE, FIREHOSE_SUBSCRIBER_LIMIT, FIREHOSE_TOKEN_TTL},
+    models::FirehoseTokenInfo,
+    utils::{constant_time_eq, sign},
+};
+
+use hyper::{header::AUTHORIZATION, Body, Request};
+use lazy_static::lazy_static;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast::{self, error::RecvError, Sender};
+use tracing::warn;
+
+lazy_static! {
+    static ref SENDER: Sender<(u64, Arc<Vec<u8>>)> = broadcast::channel(FIREHOSE_BUFFER_SIZE).0;
+    static ref SUBSCRIBERS: AtomicUsize = AtomicUsize::new(0);
+}
+
+struct Subscription;
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        SUBSCRIBERS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn get_signature(guild_id: u64, expires: u64) -> String {
+    sign(
+        CONFIG.firehose_secret.as_str(),
+        format!("{}.{}", guild_id, expires).as_bytes(),
+    )
+}
+
+pub fn get_token(guild_id: u64) -> FirehoseTokenInfo {
+    let expires = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        + FIREHOSE_TOKEN_TTL;
+
+    FirehoseTokenInfo {
+        token: format!("{}.{}", expires, get_signature(guild_id, expires)),
+        expires,
+    }
+}
+
+pub fn is_authorized(req: &Request<Body>, guild_id: u64) -> bool {
+    if CONFIG.firehose_secret.is_empty() {
+        return false;
+    }
+
+    let token = match req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        Some(token) => token,
+        None => return false,
+    };
+
+    let (expires, signature) = match token.split_once('.') {
+        Some((expires, signature)) => (expires, signature),
+        None => return false,
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    match expires.parse::<u64>() {
+        Ok(expires) if expires >= now => constant_time_eq(
+            get_signature(guild_id, expires).as_bytes(),
+            signature.as_bytes(),
+        ),
+        _ => false,
+    }
+}
+
+pub fn publish(guild_id: Option<u64>, payload: &[u8]) {
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+
+    if SENDER.receiver_count() == 0 {
+        return;
+    }
+
+    let _ = SENDER.send((guild_id, Arc::new(payload.to_vec())));
+}
+
+pub fn subscribe(guild_id: u64) -> Option<Body> {
+    if SUBSCRIBERS.fetch_add(1, Ordering::Relaxed) >= FIREHOSE_SUBSCRIBER_LIMIT {
+        SUBSCRIBERS.fetch_sub(1, Ordering::Relaxed);
+        return None;
+    }
+
+    let subscription = Subscription;
+    let (mut sender, body) = Body::channel();
+    let mut rx = SENDER.subscribe();
+
+    tokio::spawn(async move {
+        let _subscription = subscription;
+
+        loop {
+            match rx.recv().await {
+                Ok((id, payload)) if id == guild_id => {
+                    let mut data = b"data: ".to_vec();
+                    data.extend_from_slice(payload.as_slice());
+                    data.extend_from_slice(b"\n\n");
+
+                    if sender.send_data(data.into()).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(amount)) => {
+                    warn!(
+                        "Firehose client of guild {} lagged behind, skipped {} events",
+                        guild_id, amount
+                    );
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+
+    Some(body)
+}
@@ -0,0 +1,94 @@
+use crate::{config::CONFIG, keys::forum_settings_key, models::ApiResult};
+
+use redis::AsyncCommands;
+use simd_json::{json, owned::Value, ValueAccess};
+use twilight_model::id::Id;
+
+const FORUM_CHANNEL_TYPES: [u64; 2] = [15, 16];
+
+const FORUM_FIELDS: [&str; 9] = [
+    "id",
+    "name",
+    "parent_id",
+    "available_tags",
+    "default_reaction_emoji",
+    "default_thread_rate_limit_per_user",
+    "default_sort_order",
+    "default_forum_layout",
+    "flags",
+];
+
+fn get_settings(channel: &Value) -> Option<(String, String)> {
+    if !FORUM_CHANNEL_TYPES.contains(&channel.get_u64("type")?) {
+        return None;
+    }
+
+    let mut settings = json!({});
+    if let Value::Object(object) = &mut settings {
+        for field in FORUM_FIELDS {
+            if let Some(value) = channel.get(field) {
+                object.insert(field.to_owned(), value.clone());
+            }
+        }
+    }
+
+    Some((
+        channel.get_str("id")?.to_owned(),
+        simd_json::to_string(&settings).ok()?,
+    ))
+}
+
+pub fn is_cached(kind: &str) -> bool {
+    CONFIG.state_forums
+        && matches!(
+            kind,
+            "GUILD_CREATE" | "CHANNEL_CREATE" | "CHANNEL_UPDATE" | "CHANNEL_DELETE"
+        )
+}
+
+pub async fn update(conn: &mut redis::aio::Connection, kind: &str, data: &Value) -> ApiResult<()> {
+    let guild_field = match kind {
+        "GUILD_CREATE" => "id",
+        "CHANNEL_CREATE" | "CHANNEL_UPDATE" | "CHANNEL_DELETE" => "guild_id",
+        _ => return Ok(()),
+    };
+
+    let guild_id = match data
+        .get_str(guild_field)
+        .and_then(|guild_id| guild_id.parse().ok())
+        .and_then(Id::new_checked)
+    {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let key = forum_settings_key(guild_id);
+
+    match kind {
+        "GUILD_CREATE" => {
+            let settings: Vec<(String, String)> = data
+                .get_array("channels")
+                .map(|channels| channels.iter().filter_map(get_settings).collect())
+                .unwrap_or_default();
+
+            let mut pipe = redis::pipe();
+            pipe.atomic().del(&key).ignore();
+            if !settings.is_empty() {
+                pipe.hset_multiple(&key, settings.as_slice()).ignore();
+            }
+            let _: () = pipe.query_async(conn).await?;
+        }
+        "CHANNEL_CREATE" | "CHANNEL_UPDATE" => {
+            if let Some((id, settings)) = get_settings(data) {
+                let _: () = conn.hset(key, id, settings).await?;
+            }
+        }
+        "CHANNEL_DELETE" => {
+            if let Some(id) = data.get_str("id") {
+                let _: () = conn.hdel(key, id).await?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
@@ -1,40 +1,413 @@
 use crate::{
-    cache,
+    activity, anomaly,
+    authz::{self, Credentials},
+    automod, backpressure, budget, cache, checkpoint, chunks, commands,
     config::CONFIG,
+    conflict,
     constants::{
-        CONNECT_COLOR, DISCONNECT_COLOR, EXCHANGE, JOIN_COLOR, LEAVE_COLOR, QUEUE_SEND,
-        READY_COLOR, RESUME_COLOR,
+        AUTHZ_EXPIRES_HEADER, AUTHZ_SIGNATURE_HEADER, AUTHZ_TOKEN_HEADER, BACKPRESSURE_INTERVAL,
+        CACHE_DETACH_LIMIT, COMMAND_BUFFER_SIZE, CONNECT_COLOR, DISCONNECT_COLOR,
+        EVENT_BUFFER_SIZE, EXCHANGE, GUILD_CREATE_MARKER, HALT_COLOR, JOIN_COLOR, LEAVE_COLOR,
+        PAYLOAD_PEEK_LENGTH, QUEUE_SEND, QUEUE_SEND_RESULTS, READY_COLOR, RESUME_COLOR,
     },
-    metrics::{GATEWAY_EVENTS, GUILD_EVENTS, SHARD_EVENTS},
-    models::{DeliveryInfo, DeliveryOpcode, PayloadInfo},
-    utils::{log_discord, log_discord_guild},
+    dedup, deploy, failover, features, firehose, incident, intents, ipc,
+    logging::{capture_payload, set_tap, tap_payload},
+    metrics::{
+        GATEWAY_DEDUPLICATED_EVENTS, GATEWAY_EVENTS, GATEWAY_PAYLOAD_SIZES, GATEWAY_SHED_EVENTS,
+        GUILD_EVENTS, SHARD_EVENTS, STATE_DEADLINE_EXCEEDED, STATE_DETACHED_UPDATES,
+    },
+    mirror::{self, Mirror},
+    models::{
+        ApiError, ApiResult, DeliveryInfo, DeliveryOpcode, DeliveryPriority, DeliveryResult,
+        GatewayCommand, PayloadInfo, TapInfo,
+    },
+    outage, policy, prune, resume, sampler, shed,
+    startup::{get_progress, is_backfill, set_ready},
+    threshold, timestamps, typing, usage,
+    utils::{
+        get_envelopes, get_properties, get_redis_connection, get_redis_info, log_discord,
+        log_discord_guild, publish_event,
+    },
+    validator, watermark, webhook,
 };
 
+#[cfg(feature = "chaos")]
+use crate::chaos;
+#[cfg(feature = "shm")]
+use crate::shm;
+
 use futures_util::{Stream, StreamExt};
 use lapin::{
-    options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions},
+    message::Delivery,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicQosOptions},
     types::FieldTable,
-    BasicProperties, Channel,
+    Channel,
+};
+use lazy_static::lazy_static;
+use simd_json::{json, owned::Value, Value as _, ValueAccess};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    select,
+    sync::{mpsc, Mutex as AsyncMutex, Semaphore},
+    time::{sleep, timeout},
+};
+use tracing::{error, info, warn};
+use twilight_gateway::{
+    shard::raw_message::{CloseFrame, Message},
+    Cluster, Event,
 };
-use simd_json::{json, ValueAccess};
-use std::{sync::Arc, time::Duration};
-use tokio::time::timeout;
-use tracing::{info, warn};
-use twilight_gateway::{shard::raw_message::Message, Cluster, Event};
+use twilight_model::id::{marker::UserMarker, Id};
+
+lazy_static! {
+    static ref DETACHED: Arc<Semaphore> = Arc::new(Semaphore::new(CACHE_DETACH_LIMIT));
+    static ref DETACHED_SCOPES: Mutex<HashMap<u64, Arc<AsyncMutex<()>>>> =
+        Mutex::new(HashMap::new());
+    static ref DETACHED_CONNECTIONS: Mutex<Vec<redis::aio::Connection>> = Mutex::new(vec![]);
+}
+
+async fn record_history(
+    conn: &mut redis::aio::Connection,
+    shard: usize,
+    kind: &str,
+    details: Option<String>,
+) {
+    if let Err(err) = cache::push_history(conn, shard as u64, kind, details).await {
+        warn!("[Shard {}] Failed to record history: {:?}", shard, err);
+    }
+}
+
+async fn handle_close_code(cluster: &Cluster, shard: u64, code: u16) {
+    if CONFIG.close_codes_halt.contains(&code) {
+        error!("[Shard {}] Halting (code: {})", shard, code);
+        log_discord(
+            HALT_COLOR,
+            format!("[Shard {}] Halted due to close code {}", shard, code),
+        );
+        SHARD_EVENTS.with_label_values(&["Halted"]).inc();
+        if let Some(shard) = cluster.shard(shard) {
+            shard.shutdown();
+        }
+    } else if CONFIG.close_codes_reidentify.contains(&code) {
+        info!("[Shard {}] Re-identifying (code: {})", shard, code);
+        SHARD_EVENTS.with_label_values(&["Reidentifying"]).inc();
+        let message = Message::Close(Some(CloseFrame::from((1000, ""))));
+        if let Err(err) = cluster.send(shard, message).await {
+            warn!("[Shard {}] Failed to invalidate session: {:?}", shard, err);
+        }
+    }
+}
+
+fn is_deferred(event: &Event) -> bool {
+    if !CONFIG.startup_priority {
+        return false;
+    }
+
+    let deferred = match event {
+        Event::GuildCreate(_) => true,
+        Event::ShardPayload(data) => data
+            .bytes
+            .windows(GUILD_CREATE_MARKER.len())
+            .take(PAYLOAD_PEEK_LENGTH)
+            .any(|window| window == GUILD_CREATE_MARKER),
+        _ => false,
+    };
+
+    if !deferred {
+        return false;
+    }
+
+    let progress = get_progress();
+    progress.ready < progress.total
+}
+
+fn get_scope(event: &Event) -> u64 {
+    match event {
+        Event::BanAdd(data) => data.guild_id.get(),
+        Event::BanRemove(data) => data.guild_id.get(),
+        Event::ChannelCreate(data) => data.guild_id.map_or(data.id.get(), Id::get),
+        Event::ChannelDelete(data) => data.guild_id.map_or(data.id.get(), Id::get),
+        Event::ChannelPinsUpdate(data) => data.guild_id.map_or(data.channel_id.get(), Id::get),
+        Event::ChannelUpdate(data) => data.guild_id.map_or(data.id.get(), Id::get),
+        Event::GuildCreate(data) => data.id.get(),
+        Event::GuildDelete(data) => data.id.get(),
+        Event::GuildEmojisUpdate(data) => data.guild_id.get(),
+        Event::GuildUpdate(data) => data.id.get(),
+        Event::MemberAdd(data) => data.guild_id.get(),
+        Event::MemberChunk(data) => data.guild_id.get(),
+        Event::MemberRemove(data) => data.guild_id.get(),
+        Event::MemberUpdate(data) => data.guild_id.get(),
+        Event::MessageCreate(data) => data.guild_id.map_or(data.channel_id.get(), Id::get),
+        Event::MessageDelete(data) => data.guild_id.map_or(data.channel_id.get(), Id::get),
+        Event::MessageDeleteBulk(data) => data.guild_id.map_or(data.channel_id.get(), Id::get),
+        Event::MessageUpdate(data) => data.guild_id.map_or(data.channel_id.get(), Id::get),
+        Event::PresenceUpdate(data) => data.guild_id.get(),
+        Event::RoleCreate(data) => data.guild_id.get(),
+        Event::RoleDelete(data) => data.guild_id.get(),
+        Event::RoleUpdate(data) => data.guild_id.get(),
+        Event::UnavailableGuild(data) => data.id.get(),
+        Event::VoiceStateUpdate(data) => data.0.guild_id.map_or(0, Id::get),
+        _ => 0,
+    }
+}
+
+async fn update_detached(
+    conn: &mut Option<redis::aio::Connection>,
+    event: &Event,
+    bot_id: Id<UserMarker>,
+) -> Option<ApiResult<(Option<Value>, Option<Value>)>> {
+    let scope = get_scope(event);
+    let pending = DETACHED_SCOPES.lock().unwrap().get(&scope).cloned();
+    if let Some(pending) = pending {
+        drop(pending.lock().await);
+    }
+
+    let pooled = conn
+        .take()
+        .or_else(|| DETACHED_CONNECTIONS.lock().unwrap().pop());
+    let mut detached_conn = match pooled {
+        Some(conn) => conn,
+        None => match redis::Client::open(get_redis_info()) {
+            Ok(client) => match get_redis_connection(&client).await {
+                Ok(conn) => conn,
+                Err(err) => return Some(Err(err)),
+            },
+            Err(err) => return Some(Err(err.into())),
+        },
+    };
+
+    let event = event.clone();
+    let mut update = Box::pin(async move {
+        let result = cache::update(&mut detached_conn, &event, bot_id).await;
+        (detached_conn, result)
+    });
+
+    let deadline = Duration::from_millis(CONFIG.cache_update_deadline);
+    if let Ok((detached_conn, result)) = timeout(deadline, &mut update).await {
+        *conn = Some(detached_conn);
+        return Some(result);
+    }
+
+    let permit = match DETACHED.clone().try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            warn!(
+                "Awaiting state update as {} are already detached",
+                CACHE_DETACH_LIMIT
+            );
+            let (detached_conn, result) = update.await;
+            *conn = Some(detached_conn);
+            return Some(result);
+        }
+    };
+
+    let lock = Arc::new(AsyncMutex::new(()));
+    let guard = lock.clone().try_lock_owned().ok();
+    DETACHED_SCOPES.lock().unwrap().insert(scope, lock.clone());
+
+    STATE_DETACHED_UPDATES.inc();
+    tokio::spawn(async move {
+        let (detached_conn, result) = update.await;
+        if let Err(err) = result {
+            warn!("Failed to finish detached state update: {:?}", err);
+        }
+
+        {
+            let mut scopes = DETACHED_SCOPES.lock().unwrap();
+            if scopes
+                .get(&scope)
+                .is_some_and(|other| Arc::ptr_eq(other, &lock))
+            {
+                scopes.remove(&scope);
+            }
+        }
+        drop(guard);
+
+        let mut connections = DETACHED_CONNECTIONS.lock().unwrap();
+        if connections.len() < CACHE_DETACH_LIMIT {
+            connections.push(detached_conn);
+        }
+        drop(connections);
+
+        STATE_DETACHED_UPDATES.dec();
+        drop(permit);
+    });
+
+    None
+}
+
+async fn publish(
+    channel: &Channel,
+    mirror: Option<&Mirror>,
+    envelopes: &[(String, u64)],
+    shard: u64,
+    kind: &str,
+    mut payload: PayloadInfo,
+) {
+    let reply_key = chunks::get_reply_key(kind, &payload);
+    let sequence = payload.s;
+    let mut published = false;
+
+    let mirrored = mirror.filter(|_| mirror::is_mirrored(&payload));
+
+    let guild_id = payload
+        .d
+        .get_str("guild_id")
+        .and_then(|guild_id| guild_id.parse().ok());
+
+    for (exchange, version) in envelopes.iter() {
+        let version = *version;
+        if version > 1 {
+            payload.v = Some(version);
+            payload.shard = Some(shard);
+        } else {
+            payload.v = None;
+            payload.shard = None;
+        }
+
+        match simd_json::to_vec(&payload) {
+            Ok(payload) => {
+                if let Some(mirror) = mirrored {
+                    mirror.publish(exchange, kind, payload.clone());
+                }
+
+                if exchange == EXCHANGE {
+                    ipc::publish(payload.as_slice());
+                    firehose::publish(guild_id, payload.as_slice());
+
+                    #[cfg(feature = "shm")]
+                    shm::publish(payload.as_slice());
+
+                    if webhook::is_enabled(kind) {
+                        webhook::dispatch(payload.clone());
+                    }
+                }
+
+                if shed::is_shed(kind) {
+                    GATEWAY_SHED_EVENTS.with_label_values(&[kind]).inc();
+                    continue;
+                }
+
+                #[cfg(feature = "chaos")]
+                if chaos::should_fail() {
+                    failover::record(false);
+                    warn!(
+                        "[Shard {}] Failed to publish event: injected failure",
+                        shard
+                    );
+                    continue;
+                }
+
+                let result = channel
+                    .basic_publish(
+                        exchange,
+                        reply_key.as_deref().unwrap_or(kind),
+                        BasicPublishOptions::default(),
+                        &payload,
+                        get_properties(payload.as_slice()),
+                    )
+                    .await;
+
+                failover::record(result.is_ok());
+
+                match result {
+                    Ok(_) if exchange == EXCHANGE => {
+                        published = true;
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        warn!("[Shard {}] Failed to publish event: {:?}", shard, err);
+                    }
+                }
+            }
+            Err(err) => {
+                warn!("[Shard {}] Failed to serialize payload: {:?}", shard, err);
+            }
+        }
+    }
+
+    watermark::acknowledge(shard, sequence, published);
+}
 
 pub async fn outgoing(
     conn: &mut redis::aio::Connection,
     cluster: &Cluster,
-    channel: &lapin::Channel,
+    channel: Option<&Channel>,
+    mirror: Option<Mirror>,
     mut events: impl Stream<Item = (u64, Event)> + Send + Sync + Unpin + 'static,
 ) {
     let shard_strings: Vec<String> = (0..CONFIG.shards_total).map(|x| x.to_string()).collect();
+    let envelopes = get_envelopes();
 
     let mut bot_id = None;
+    let mut cache_conn = None;
+    let mut backfilled = HashSet::new();
+
+    let (events_tx, mut events_rx) = mpsc::channel(EVENT_BUFFER_SIZE);
+    let (paused_tx, mut paused_rx) = mpsc::channel(EVENT_BUFFER_SIZE);
+    let (deferred_tx, mut deferred_rx) =
+        mpsc::channel::<(u64, String, PayloadInfo)>(EVENT_BUFFER_SIZE);
+
+    let paused = Arc::new(AtomicUsize::new(0));
+    let paused_clone = paused.clone();
+
+    tokio::spawn(async move {
+        while let Some(event) = events.next().await {
+            if backpressure::is_pausable(event.0)
+                && (backpressure::is_active() || paused_clone.load(Ordering::Relaxed) > 0)
+            {
+                paused_clone.fetch_add(1, Ordering::Relaxed);
+                if paused_tx.send(event).await.is_err() {
+                    break;
+                }
+            } else if events_tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    if let Some(channel) = channel {
+        let channel_clone = channel.clone();
+        let mirror_clone = mirror.clone();
+        let envelopes_clone = envelopes.clone();
+        tokio::spawn(async move {
+            while let Some((shard, kind, payload)) = deferred_rx.recv().await {
+                publish(
+                    &channel_clone,
+                    mirror_clone.as_ref(),
+                    envelopes_clone.as_slice(),
+                    shard,
+                    kind.as_str(),
+                    payload,
+                )
+                .await;
+            }
+        });
+    }
+
+    loop {
+        let (shard, event) = select! {
+            biased;
+            Some(event) = events_rx.recv() => event,
+            Some(event) = paused_rx.recv(), if !backpressure::is_active() => {
+                paused.fetch_sub(1, Ordering::Relaxed);
+                event
+            },
+            _ = sleep(Duration::from_millis(BACKPRESSURE_INTERVAL as u64)),
+                if backpressure::is_active() => continue,
+            else => break,
+        };
 
-    while let Some((shard, event)) = events.next().await {
         let mut old = None;
+        let mut old_features = None;
         let shard = shard as usize;
+        let deferred = is_deferred(&event);
 
         if CONFIG.state_enabled {
             if let Event::Ready(data) = &event {
@@ -44,36 +417,63 @@ pub async fn outgoing(
             }
 
             if let Some(bot_id) = bot_id {
-                match timeout(
-                    Duration::from_millis(10000),
-                    cache::update(conn, &event, bot_id),
-                )
-                .await
-                {
-                    Ok(Ok(value)) => {
+                let started = Instant::now();
+                let result = if CONFIG.cache_deadline_detach {
+                    update_detached(&mut cache_conn, &event, bot_id).await
+                } else {
+                    timeout(
+                        Duration::from_millis(CONFIG.cache_update_deadline),
+                        cache::update(conn, &event, bot_id),
+                    )
+                    .await
+                    .ok()
+                };
+                backpressure::record(started.elapsed());
+
+                match result {
+                    Some(Ok((value, features))) => {
                         old = value;
+                        old_features = features;
                     }
-                    Ok(Err(err)) => {
+                    Some(Err(err)) => {
                         warn!("[Shard {}] Failed to update state: {:?}", shard, err);
                     }
-                    Err(_) => {
+                    None => {
+                        STATE_DEADLINE_EXCEEDED
+                            .with_label_values(&[format!("{:?}", event.kind()).as_str()])
+                            .inc();
                         warn!("[Shard {}] Timed out while updating state", shard);
                     }
                 }
             }
         }
 
+        let recovered = match channel {
+            Some(channel) => outage::update(conn, channel, &event).await,
+            None => false,
+        };
+
         match event {
             Event::GatewayHello(data) => {
                 info!("[Shard {}] Hello (heartbeat interval: {})", shard, data);
             }
             Event::GatewayInvalidateSession(data) => {
                 info!("[Shard {}] Invalid Session (resumable: {})", shard, data);
+                conflict::record(conn, cluster, shard as u64).await;
             }
             Event::Ready(data) => {
                 info!("[Shard {}] Ready (session: {})", shard, data.session_id);
-                log_discord(READY_COLOR, format!("[Shard {}] Ready", shard));
+                incident::log_shard(READY_COLOR, format!("[Shard {}] Ready", shard));
                 SHARD_EVENTS.with_label_values(&["Ready"]).inc();
+                set_ready(shard as u64);
+                conflict::record_ready(shard as u64);
+                record_history(
+                    conn,
+                    shard,
+                    "Ready",
+                    Some(format!("session: {}", data.session_id)),
+                )
+                .await;
             }
             Event::Resumed => {
                 if let Some(Ok(info)) = cluster.shard(shard as u64).map(|s| s.info()) {
@@ -85,19 +485,23 @@ pub async fn outgoing(
                 } else {
                     info!("[Shard {}] Resumed", shard);
                 }
-                log_discord(RESUME_COLOR, format!("[Shard {}] Resumed", shard));
+                incident::log_shard(RESUME_COLOR, format!("[Shard {}] Resumed", shard));
                 SHARD_EVENTS.with_label_values(&["Resumed"]).inc();
+                set_ready(shard as u64);
+                record_history(conn, shard, "Resumed", None).await;
             }
             Event::ShardConnected(_) => {
                 info!("[Shard {}] Connected", shard);
-                log_discord(CONNECT_COLOR, format!("[Shard {}] Connected", shard));
+                incident::log_shard(CONNECT_COLOR, format!("[Shard {}] Connected", shard));
                 SHARD_EVENTS.with_label_values(&["Connected"]).inc();
+                record_history(conn, shard, "Connected", None).await;
             }
             Event::ShardConnecting(data) => {
                 info!("[Shard {}] Connecting (url: {})", shard, data.gateway);
                 SHARD_EVENTS.with_label_values(&["Connecting"]).inc();
             }
             Event::ShardDisconnected(data) => {
+                let mut details = None;
                 if let Some(code) = data.code {
                     let reason = data.reason.unwrap_or_default();
                     if !reason.is_empty() {
@@ -105,62 +509,164 @@ pub async fn outgoing(
                             "[Shard {}] Disconnected (code: {}, reason: {})",
                             shard, code, reason
                         );
+                        details = Some(format!("code: {}, reason: {}", code, reason));
                     } else {
                         info!("[Shard {}] Disconnected (code: {})", shard, code);
+                        details = Some(format!("code: {}", code));
                     }
                 } else {
                     info!("[Shard {}] Disconnected", shard);
                 }
-                log_discord(DISCONNECT_COLOR, format!("[Shard {}] Disconnected", shard));
+                record_history(conn, shard, "Disconnected", details).await;
+                incident::record_disconnect();
+                incident::log_shard(DISCONNECT_COLOR, format!("[Shard {}] Disconnected", shard));
                 SHARD_EVENTS.with_label_values(&["Disconnected"]).inc();
+
+                if let Some(code) = data.code {
+                    if intents::is_rejected(code) {
+                        intents::fallback(conn, shard as u64, code).await;
+                    }
+                    handle_close_code(cluster, shard as u64, code).await;
+                }
             }
             Event::ShardIdentifying(_) => {
                 info!("[Shard {}] Identifying", shard);
+                budget::record_identify();
                 SHARD_EVENTS.with_label_values(&["Identifying"]).inc();
+                record_history(conn, shard, "Identifying", None).await;
             }
             Event::ShardReconnecting(_) => {
                 info!("[Shard {}] Reconnecting", shard);
                 SHARD_EVENTS.with_label_values(&["Reconnecting"]).inc();
+                record_history(conn, shard, "Reconnecting", None).await;
             }
             Event::ShardResuming(data) => {
                 info!("[Shard {}] Resuming (sequence: {})", shard, data.seq);
                 SHARD_EVENTS.with_label_values(&["Resuming"]).inc();
+                record_history(
+                    conn,
+                    shard,
+                    "Resuming",
+                    Some(format!("sequence: {}", data.seq)),
+                )
+                .await;
             }
             Event::ShardPayload(mut data) => {
+                let size = data.bytes.len();
                 match simd_json::from_slice::<PayloadInfo>(data.bytes.as_mut_slice()) {
                     Ok(mut payload) => {
                         if let Some(kind) = payload.t.as_deref() {
+                            if let Some(sequence) = payload.s {
+                                if deploy::is_duplicate(conn, cluster, shard as u64, sequence).await
+                                {
+                                    continue;
+                                }
+
+                                if let Err(err) =
+                                    checkpoint::record(conn, cluster, shard as u64, sequence).await
+                                {
+                                    warn!("[Shard {}] Failed to save sequence: {:?}", shard, err);
+                                }
+                            }
+
                             GATEWAY_EVENTS
                                 .with_label_values(&[kind, shard_strings[shard as usize].as_str()])
                                 .inc();
+                            GATEWAY_PAYLOAD_SIZES
+                                .with_label_values(&[kind])
+                                .observe(size as f64);
 
-                            payload.old = old;
+                            if CONFIG.payload_warn_size != 0
+                                && size as u64 > CONFIG.payload_warn_size
+                            {
+                                let guild_id = payload.d.get_str("guild_id").or_else(|| {
+                                    payload
+                                        .d
+                                        .get_str("id")
+                                        .filter(|_| kind.starts_with("GUILD_"))
+                                });
+                                warn!(
+                                    "[Shard {}] Oversized {} payload (size: {}, guild: {})",
+                                    shard,
+                                    kind,
+                                    size,
+                                    guild_id.unwrap_or_default()
+                                );
+                            }
 
-                            match simd_json::to_vec(&payload) {
-                                Ok(payload) => {
-                                    let result = channel
-                                        .basic_publish(
-                                            EXCHANGE,
-                                            kind,
-                                            BasicPublishOptions::default(),
-                                            &payload,
-                                            BasicProperties::default(),
-                                        )
-                                        .await;
-
-                                    if let Err(err) = result {
-                                        warn!(
-                                            "[Shard {}] Failed to publish event: {:?}",
-                                            shard, err
-                                        );
-                                    }
+                            capture_payload(kind, &payload);
+                            sampler::record(kind, &payload);
+                            validator::record(kind, &payload);
+                            resume::record(shard as u64, kind, &payload);
+                            if is_backfill(shard as u64, kind, &payload) {
+                                payload.startup = Some(true);
+                                if let Some(id) = payload.d.get_str("id") {
+                                    backfilled.insert(id.to_owned());
                                 }
-                                Err(err) => {
-                                    warn!(
-                                        "[Shard {}] Failed to serialize payload: {:?}",
-                                        shard, err
-                                    );
+                            }
+                            chunks::record(kind, &payload);
+                            prune::record(shard as u64, kind, &payload);
+                            policy::record(kind, &payload);
+                            threshold::record(shard as u64, kind, size);
+                            usage::record(kind, &payload);
+                            anomaly::record(kind, shard as u64);
+
+                            if let Err(err) = activity::record(conn, kind, &payload).await {
+                                warn!("[Shard {}] Failed to record activity: {:?}", shard, err);
+                            }
+
+                            tap_payload(kind, &payload);
+
+                            if let Err(err) = automod::attach_rule(conn, kind, &mut payload.d).await
+                            {
+                                warn!("[Shard {}] Failed to attach automod rule: {:?}", shard, err);
+                            }
+
+                            if deploy::is_draining() {
+                                continue;
+                            }
+
+                            if dedup::is_repeated(kind, &payload) {
+                                GATEWAY_DEDUPLICATED_EVENTS.with_label_values(&[kind]).inc();
+                                continue;
+                            }
+
+                            if typing::is_aggregated(kind, &payload) {
+                                continue;
+                            }
+
+                            payload.old = old;
+                            timestamps::normalize(kind, &mut payload.d);
+                            if let Some(old) = payload.old.as_mut() {
+                                timestamps::normalize(kind, old);
+                            }
+
+                            let kind = kind.to_owned();
+                            let channel = match channel {
+                                Some(channel) => channel,
+                                None => continue,
+                            };
+                            let sequence = payload.s;
+                            watermark::receive(shard as u64, sequence);
+                            if deferred {
+                                if deferred_tx
+                                    .send((shard as u64, kind, payload))
+                                    .await
+                                    .is_err()
+                                {
+                                    warn!("[Shard {}] Failed to defer event", shard);
+                                    watermark::acknowledge(shard as u64, sequence, false);
                                 }
+                            } else {
+                                publish(
+                                    channel,
+                                    mirror.as_ref(),
+                                    envelopes.as_slice(),
+                                    shard as u64,
+                                    kind.as_str(),
+                                    payload,
+                                )
+                                .await;
                             }
                         }
                     }
@@ -170,13 +676,48 @@ pub async fn outgoing(
                 }
             }
             Event::GuildCreate(data) => {
-                if old.is_none() {
+                let startup = backfilled.remove(data.id.to_string().as_str());
+                if old.is_none() && !recovered && !startup {
                     GUILD_EVENTS.with_label_values(&["Join"]).inc();
                     log_discord_guild(
                         JOIN_COLOR,
                         "Guild Join",
                         format!("{} ({})", data.name, data.id),
                     );
+
+                    match channel {
+                        Some(channel) if CONFIG.state_enabled && !deploy::is_draining() => {
+                            let data = json!({
+                                "guild_id": data.id.to_string(),
+                                "name": data.name.clone(),
+                            });
+                            if let Err(err) = publish_event(channel, "GUILD_JOIN", data).await {
+                                warn!("[Shard {}] Failed to publish guild join: {:?}", shard, err);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                if let Some(channel) = channel {
+                    features::publish(
+                        channel,
+                        data.id,
+                        old.as_ref().and_then(|old| old.get("features")),
+                        data.features.as_slice(),
+                    )
+                    .await;
+                }
+            }
+            Event::GuildUpdate(data) => {
+                if let Some(channel) = channel {
+                    features::publish(
+                        channel,
+                        data.id,
+                        old_features.as_ref(),
+                        data.features.as_slice(),
+                    )
+                    .await;
                 }
             }
             Event::GuildDelete(data) => {
@@ -203,7 +744,229 @@ pub async fn outgoing(
     }
 }
 
-pub async fn incoming(clusters: &[Arc<Cluster>], channel: &Channel) {
+fn get_cluster(clusters: &[Arc<Cluster>], shard: u64) -> ApiResult<&Arc<Cluster>> {
+    clusters
+        .iter()
+        .find(|cluster| cluster.shard(shard).is_some())
+        .ok_or(ApiError::InvalidShard(shard))
+}
+
+async fn replay(conn: &mut redis::aio::Connection, channel: &Channel) -> ApiResult<()> {
+    let payloads = cache::get_tap(conn).await?;
+    info!("Replaying {} tapped payloads", payloads.len());
+
+    for payload in payloads {
+        if let Some(kind) = payload.t.as_deref() {
+            let payload = simd_json::to_vec(&payload)?;
+            channel
+                .basic_publish(
+                    EXCHANGE,
+                    kind,
+                    BasicPublishOptions::default(),
+                    &payload,
+                    get_properties(payload.as_slice()),
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn get_header<'a>(delivery: &'a Delivery, name: &str) -> Option<&'a str> {
+    delivery
+        .properties
+        .headers()
+        .as_ref()?
+        .inner()
+        .iter()
+        .find(|(key, _)| key.as_str() == name)?
+        .1
+        .as_long_string()
+        .and_then(|value| std::str::from_utf8(value.as_bytes()).ok())
+}
+
+async fn send_command(clusters: &[Arc<Cluster>], payload: DeliveryInfo) -> ApiResult<()> {
+    let cluster = get_cluster(clusters, payload.shard)?;
+    let command = GatewayCommand::from_value(&payload.data.unwrap_or_default())?;
+    if let GatewayCommand::RequestGuildMembers(request) = &command {
+        if let Some(nonce) = request.d.nonce.as_deref() {
+            chunks::track(nonce, request.d.guild_id.get());
+        }
+    }
+
+    commands::reserve(payload.shard).await;
+    cluster
+        .send(payload.shard, Message::Binary(simd_json::to_vec(&command)?))
+        .await
+        .map_err(|err| ApiError::ClusterSend(format!("{}", err)))?;
+
+    Ok(())
+}
+
+async fn execute(
+    clusters: &[Arc<Cluster>],
+    conn: &mut redis::aio::Connection,
+    channel: &Channel,
+    payload: DeliveryInfo,
+) -> ApiResult<()> {
+    match payload.op {
+        DeliveryOpcode::Send => {
+            send_command(clusters, payload).await?;
+        }
+        DeliveryOpcode::Reconnect => {
+            let cluster = get_cluster(clusters, payload.shard)?;
+            info!("Shutting down shard {}", payload.shard);
+            cluster.shard(payload.shard).unwrap().shutdown();
+        }
+        DeliveryOpcode::Tap => match payload.data {
+            Some(data) if !data.is_null() => {
+                let mut bytes = simd_json::to_vec(&data)?;
+                let tap: TapInfo = simd_json::from_slice(bytes.as_mut_slice())?;
+                info!("Tapping payloads (kinds: {:?})", tap.kinds);
+                set_tap(Some(tap))?;
+            }
+            _ => {
+                info!("Stopped tapping payloads");
+                set_tap(None)?;
+            }
+        },
+        DeliveryOpcode::Replay => {
+            replay(conn, channel).await?;
+        }
+        DeliveryOpcode::Chaos => {
+            #[cfg(feature = "chaos")]
+            chaos::execute(clusters, payload)?;
+
+            #[cfg(not(feature = "chaos"))]
+            warn!("Ignoring chaos command as the chaos feature is disabled");
+        }
+        DeliveryOpcode::Shutdown => {
+            deploy::shutdown(conn, clusters).await?;
+        }
+        DeliveryOpcode::Drain => {
+            deploy::drain(conn, clusters).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn publish_result(channel: &Channel, result: &DeliveryResult) {
+    match simd_json::to_vec(result) {
+        Ok(payload) => {
+            let result = channel
+                .basic_publish(
+                    "",
+                    QUEUE_SEND_RESULTS,
+                    BasicPublishOptions::default(),
+                    &payload,
+                    get_properties(payload.as_slice()),
+                )
+                .await;
+
+            if let Err(err) = result {
+                warn!("Failed to publish delivery result: {:?}", err);
+            }
+        }
+        Err(err) => {
+            warn!("Failed to serialize delivery result: {:?}", err);
+        }
+    }
+}
+
+async fn ack(channel: &Channel, delivery_tag: u64) {
+    if let Err(err) = channel
+        .basic_ack(delivery_tag, BasicAckOptions::default())
+        .await
+    {
+        warn!("Failed to acknowledge delivery: {:?}", err);
+    }
+}
+
+async fn finish(
+    channel: &Channel,
+    shard: u64,
+    correlation_id: Option<String>,
+    result: &ApiResult<()>,
+    delivery_tag: u64,
+) {
+    if let Err(err) = result {
+        warn!("Failed to execute delivery: {:?}", err);
+    }
+
+    if let Some(correlation_id) = correlation_id {
+        let result = DeliveryResult {
+            correlation_id,
+            shard,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|err| err.to_string()),
+        };
+        publish_result(channel, &result).await;
+    }
+
+    ack(channel, delivery_tag).await;
+}
+
+type QueuedDelivery = (DeliveryInfo, u64);
+
+fn priority_channel(
+    size: usize,
+) -> (
+    [mpsc::Sender<QueuedDelivery>; 3],
+    [mpsc::Receiver<QueuedDelivery>; 3],
+) {
+    let (high_tx, high_rx) = mpsc::channel(size);
+    let (normal_tx, normal_rx) = mpsc::channel(size);
+    let (low_tx, low_rx) = mpsc::channel(size);
+
+    ([high_tx, normal_tx, low_tx], [high_rx, normal_rx, low_rx])
+}
+
+async fn recv_priority(
+    receivers: &mut [mpsc::Receiver<QueuedDelivery>; 3],
+) -> Option<QueuedDelivery> {
+    let [high_rx, normal_rx, low_rx] = receivers;
+
+    select! {
+        biased;
+        Some(delivery) = high_rx.recv() => Some(delivery),
+        Some(delivery) = normal_rx.recv() => Some(delivery),
+        Some(delivery) = low_rx.recv() => Some(delivery),
+        else => None,
+    }
+}
+
+fn spawn_shard(clusters: Vec<Arc<Cluster>>, channel: Channel) -> [mpsc::Sender<QueuedDelivery>; 3] {
+    let (senders, mut receivers) = priority_channel(COMMAND_BUFFER_SIZE * 3);
+
+    tokio::spawn(async move {
+        while let Some((payload, delivery_tag)) = recv_priority(&mut receivers).await {
+            let shard = payload.shard;
+            let correlation_id = payload.correlation_id.clone();
+
+            let result = send_command(clusters.as_slice(), payload).await;
+            finish(&channel, shard, correlation_id, &result, delivery_tag).await;
+        }
+    });
+
+    senders
+}
+
+pub async fn incoming(
+    clusters: &[Arc<Cluster>],
+    mut conn: redis::aio::Connection,
+    conn_activity: &mut redis::aio::Connection,
+    channel: &Channel,
+) {
+    if let Err(err) = channel
+        .basic_qos(COMMAND_BUFFER_SIZE as u16 * 3, BasicQosOptions::default())
+        .await
+    {
+        warn!("Failed to limit unacknowledged deliveries: {:?}", err);
+        return;
+    }
+
     let mut consumer = match channel
         .basic_consume(
             QUEUE_SEND,
@@ -220,46 +983,95 @@ pub async fn incoming(clusters: &[Arc<Cluster>], channel: &Channel) {
         }
     };
 
+    let (senders, mut receivers) = priority_channel(COMMAND_BUFFER_SIZE);
+    let mut shards = HashMap::new();
+
+    let clusters = clusters.to_vec();
+    let clusters_clone = clusters.clone();
+    let channel_clone = channel.clone();
+    tokio::spawn(async move {
+        while let Some((payload, delivery_tag)) = recv_priority(&mut receivers).await {
+            let shard = payload.shard;
+            let correlation_id = payload.correlation_id.clone();
+            let shutdown = matches!(payload.op, DeliveryOpcode::Shutdown);
+
+            let result = execute(
+                clusters_clone.as_slice(),
+                &mut conn,
+                &channel_clone,
+                payload,
+            )
+            .await;
+            let exit = shutdown && result.is_ok();
+
+            finish(&channel_clone, shard, correlation_id, &result, delivery_tag).await;
+
+            if exit {
+                deploy::request_shutdown();
+                break;
+            }
+        }
+    });
+
     while let Some(message) = consumer.next().await {
         match message {
             Ok(mut delivery) => {
-                let _ = channel
-                    .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
-                    .await;
+                let credentials = Credentials {
+                    token: get_header(&delivery, AUTHZ_TOKEN_HEADER),
+                    signature: get_header(&delivery, AUTHZ_SIGNATURE_HEADER),
+                    expires: get_header(&delivery, AUTHZ_EXPIRES_HEADER),
+                    user: delivery
+                        .properties
+                        .user_id()
+                        .as_ref()
+                        .map(|user| user.as_str()),
+                };
+                if !authz::authorize("amqp", &credentials, delivery.data.as_slice()) {
+                    ack(channel, delivery.delivery_tag).await;
+                    continue;
+                }
+
                 match simd_json::from_slice::<DeliveryInfo>(delivery.data.as_mut_slice()) {
                     Ok(payload) => {
-                        let cluster = clusters
-                            .iter()
-                            .find(|cluster| cluster.shard(payload.shard).is_some());
-                        if let Some(cluster) = cluster {
-                            match payload.op {
-                                DeliveryOpcode::Send => {
-                                    if let Err(err) = cluster
-                                        .send(
-                                            payload.shard,
-                                            Message::Binary(
-                                                simd_json::to_vec(
-                                                    &payload.data.unwrap_or_default(),
-                                                )
-                                                .unwrap_or_default(),
-                                            ),
-                                        )
-                                        .await
-                                    {
-                                        warn!("Failed to send gateway command: {:?}", err);
-                                    }
-                                }
-                                DeliveryOpcode::Reconnect => {
-                                    info!("Shutting down shard {}", payload.shard);
-                                    cluster.shard(payload.shard).unwrap().shutdown();
-                                }
+                        let priority = match activity::get_priority(conn_activity, &payload).await {
+                            Ok(priority) => priority,
+                            Err(err) => {
+                                warn!("Failed to get guild activity: {:?}", err);
+                                payload.priority
                             }
-                        } else {
-                            warn!("Delivery received for invalid shard: {}", payload.shard)
+                        };
+                        if matches!(payload.op, DeliveryOpcode::Send) {
+                            if let Err(err) = get_cluster(clusters.as_slice(), payload.shard) {
+                                finish(
+                                    channel,
+                                    payload.shard,
+                                    payload.correlation_id,
+                                    &Err(err),
+                                    delivery.delivery_tag,
+                                )
+                                .await;
+                                continue;
+                            }
+                        }
+                        let senders = match payload.op {
+                            DeliveryOpcode::Send => &*shards
+                                .entry(payload.shard)
+                                .or_insert_with(|| spawn_shard(clusters.clone(), channel.clone())),
+                            _ => &senders,
+                        };
+                        let sender = match priority {
+                            DeliveryPriority::High => &senders[0],
+                            DeliveryPriority::Normal => &senders[1],
+                            DeliveryPriority::Low => &senders[2],
+                        };
+                        if let Err(err) = sender.send((payload, delivery.delivery_tag)).await {
+                            warn!("Failed to queue delivery: {:?}", err);
+                            break;
                         }
                     }
                     Err(err) => {
                         warn!("Failed to deserialize payload: {:?}", err);
+                        ack(channel, delivery.delivery_tag).await;
                     }
                 }
             }
@@ -269,3 +1081,56 @@ pub async fn incoming(clusters: &[Arc<Cluster>], channel: &Channel) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        keys::channel_key,
+        recorder,
+        utils::{get_redis_connection, get_redis_info},
+    };
+
+    use std::{env, io::Cursor, slice};
+    use twilight_gateway::{cluster::ShardScheme, Intents};
+    use twilight_model::channel::Channel as GuildChannel;
+
+    const FIXTURE: &str = r#"{"shard":0,"timestamp":1,"payload":{"op":0,"s":1,"t":"READY","d":{"v":9,"user":{"id":"10","username":"bot","discriminator":"0001","avatar":null,"bot":true,"mfa_enabled":false,"verified":true,"flags":0},"guilds":[],"session_id":"replay","shard":[0,1],"application":{"id":"10","flags":0}}}}
+{"shard":0,"timestamp":2,"payload":{"op":0,"s":2,"t":"CHANNEL_CREATE","d":{"id":"2","type":0,"guild_id":"1","name":"general","position":0,"permission_overwrites":[],"nsfw":false}}}
+{"shard":0,"timestamp":3,"payload":{"op":0,"s":3,"t":"CHANNEL_UPDATE","d":{"id":"2","type":0,"guild_id":"1","name":"renamed","position":0,"permission_overwrites":[],"nsfw":false}}}
+"#;
+
+    #[tokio::test]
+    #[ignore = "requires a .env and a Redis instance"]
+    async fn replay_updates_cache_in_order() {
+        env::set_var("STATE_ENABLED", "true");
+        dotenv::dotenv().ok();
+
+        let redis = redis::Client::open(get_redis_info()).unwrap();
+        let mut conn = get_redis_connection(&redis).await.unwrap();
+
+        let (cluster, _) = Cluster::builder(CONFIG.bot_token.clone(), Intents::empty())
+            .gateway_url(Some("wss://gateway.discord.gg".to_owned()))
+            .shard_scheme(ShardScheme::Range {
+                from: 0,
+                to: 0,
+                total: 1,
+            })
+            .build()
+            .await
+            .unwrap();
+        let cluster = Arc::new(cluster);
+
+        let mut streams = recorder::replay(Cursor::new(FIXTURE), slice::from_ref(&cluster));
+        outgoing(&mut conn, &cluster, None, None, streams.remove(0)).await;
+
+        let channel: Option<GuildChannel> =
+            cache::get(&mut conn, channel_key(Id::new(1), Id::new(2)))
+                .await
+                .unwrap();
+        assert_eq!(
+            channel.and_then(|channel| channel.name).as_deref(),
+            Some("renamed")
+        );
+    }
+}
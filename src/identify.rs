@@ -0,0 +1,74 @@
+use crate::{
+    metrics::GATEWAY_IDENTIFY_QUEUE,
+    models::{IdentifyBucketInfo, IdentifyWaitInfo},
+};
+
+use lazy_static::lazy_static;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+struct Bucket {
+    shards: VecDeque<u64>,
+    next: Instant,
+    interval: Duration,
+}
+
+lazy_static! {
+    static ref BUCKETS: Mutex<BTreeMap<u64, Bucket>> = Mutex::new(BTreeMap::new());
+}
+
+pub fn record_waiting(bucket: u64, shard: u64, interval: Duration) {
+    let mut buckets = BUCKETS.lock().unwrap();
+    let entry = buckets.entry(bucket).or_insert_with(|| Bucket {
+        shards: VecDeque::new(),
+        next: Instant::now(),
+        interval,
+    });
+
+    entry.shards.push_back(shard);
+    GATEWAY_IDENTIFY_QUEUE
+        .with_label_values(&[bucket.to_string().as_str()])
+        .set(entry.shards.len() as i64);
+}
+
+pub fn record_identified(bucket: u64, shard: u64) {
+    let mut buckets = BUCKETS.lock().unwrap();
+    if let Some(entry) = buckets.get_mut(&bucket) {
+        entry.shards.retain(|waiting| *waiting != shard);
+        entry.next = Instant::now() + entry.interval;
+        GATEWAY_IDENTIFY_QUEUE
+            .with_label_values(&[bucket.to_string().as_str()])
+            .set(entry.shards.len() as i64);
+    }
+}
+
+pub fn get_info() -> Vec<IdentifyBucketInfo> {
+    let now = Instant::now();
+
+    BUCKETS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(bucket, entry)| {
+            let wait = entry.next.saturating_duration_since(now);
+
+            IdentifyBucketInfo {
+                bucket: *bucket,
+                depth: entry.shards.len() as u64,
+                shards: entry
+                    .shards
+                    .iter()
+                    .enumerate()
+                    .map(|(position, shard)| IdentifyWaitInfo {
+                        shard: *shard,
+                        position: position as u64,
+                        estimate: (wait + entry.interval * position as u32).as_millis() as u64,
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
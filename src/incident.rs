@@ -0,0 +1,147 @@
+use crate::{
+    config::CONFIG,
+    constants::{INCIDENT_COLOR, INCIDENT_WINDOW, RESUME_COLOR},
+    metrics::DISCORD_INCIDENT,
+    models::{ApiResult, StatusIncidents},
+    utils::log_discord,
+};
+
+use hyper::{body::to_bytes, client::HttpConnector, header::USER_AGENT, Body, Client, Request};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use lazy_static::lazy_static;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+lazy_static! {
+    static ref CLIENT: Client<HttpsConnector<HttpConnector>> = Client::builder().build(
+        HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_only()
+            .enable_http1()
+            .build()
+    );
+    static ref DISCONNECTS: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+    static ref ACTIVE: AtomicBool = AtomicBool::new(false);
+    static ref SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+}
+
+fn get_recent_disconnects() -> usize {
+    let mut disconnects = DISCONNECTS.lock().unwrap();
+    while let Some(first) = disconnects.front() {
+        if first.elapsed().as_millis() as usize > INCIDENT_WINDOW {
+            disconnects.pop_front();
+        } else {
+            break;
+        }
+    }
+
+    disconnects.len()
+}
+
+pub fn record_disconnect() {
+    if CONFIG.status_interval == 0 {
+        return;
+    }
+
+    DISCONNECTS.lock().unwrap().push_back(Instant::now());
+    get_recent_disconnects();
+}
+
+pub fn log_shard(color: usize, message: impl Into<String>) {
+    if ACTIVE.load(Ordering::Relaxed) {
+        SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    log_discord(color, message);
+}
+
+async fn get_incidents() -> ApiResult<StatusIncidents> {
+    let request = Request::get(CONFIG.status_url.as_str())
+        .header(USER_AGENT, "twilight-dispatch")
+        .body(Body::empty())?;
+
+    let response = CLIENT.request(request).await?;
+    let mut body = to_bytes(response.into_body()).await?.to_vec();
+
+    Ok(simd_json::from_slice(body.as_mut_slice())?)
+}
+
+pub async fn run_jobs() {
+    if CONFIG.status_interval == 0 {
+        return;
+    }
+
+    let mut current: Option<String> = None;
+
+    loop {
+        match get_incidents().await {
+            Ok(status) => {
+                let incident = status
+                    .incidents
+                    .iter()
+                    .find(|incident| incident.impact != "none");
+
+                match (incident, current.as_ref()) {
+                    (Some(incident), None) => {
+                        let disconnects = get_recent_disconnects();
+                        warn!(
+                            "Discord incident: {} ({} recent disconnects)",
+                            incident.name, disconnects
+                        );
+                        log_discord(
+                            INCIDENT_COLOR,
+                            format!(
+                                "Discord incident: {} (impact: {}, {} shard disconnects in the last {} minutes){}",
+                                incident.name,
+                                incident.impact,
+                                disconnects,
+                                INCIDENT_WINDOW / 60000,
+                                incident
+                                    .shortlink
+                                    .as_ref()
+                                    .map(|link| format!(" {}", link))
+                                    .unwrap_or_default()
+                            ),
+                        );
+
+                        SUPPRESSED.store(0, Ordering::Relaxed);
+                        ACTIVE.store(true, Ordering::Relaxed);
+                        DISCORD_INCIDENT.set(1);
+                        current = Some(incident.name.clone());
+                    }
+                    (None, Some(name)) => {
+                        ACTIVE.store(false, Ordering::Relaxed);
+                        DISCORD_INCIDENT.set(0);
+
+                        let suppressed = SUPPRESSED.swap(0, Ordering::Relaxed);
+                        info!("Discord incident resolved: {}", name);
+                        log_discord(
+                            RESUME_COLOR,
+                            format!(
+                                "Discord incident resolved: {} ({} shard alerts suppressed)",
+                                name, suppressed
+                            ),
+                        );
+
+                        current = None;
+                    }
+                    _ => {}
+                }
+            }
+            Err(err) => {
+                warn!("Failed to get Discord status: {:?}", err);
+            }
+        }
+
+        sleep(Duration::from_millis(CONFIG.status_interval)).await;
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{
+    cache,
+    config::CONFIG,
+    keys::{command_permissions_key, integrations_key},
+    models::ApiResult,
+};
+
+use redis::AsyncCommands;
+use simd_json::{owned::Value, ValueAccess};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+pub fn is_cached(kind: &str) -> bool {
+    CONFIG.state_integrations
+        && matches!(
+            kind,
+            "INTEGRATION_CREATE"
+                | "INTEGRATION_UPDATE"
+                | "INTEGRATION_DELETE"
+                | "APPLICATION_COMMAND_PERMISSIONS_UPDATE"
+        )
+}
+
+pub async fn update(conn: &mut redis::aio::Connection, kind: &str, data: &Value) -> ApiResult<()> {
+    let (guild_id, id) = match (
+        data.get_str("guild_id")
+            .and_then(|guild_id| guild_id.parse().ok())
+            .and_then(Id::new_checked),
+        data.get_str("id"),
+    ) {
+        (Some(guild_id), Some(id)) => (guild_id, id),
+        _ => return Ok(()),
+    };
+
+    match kind {
+        "INTEGRATION_CREATE" | "INTEGRATION_UPDATE" => {
+            let _: () = conn
+                .hset(integrations_key(guild_id), id, simd_json::to_string(data)?)
+                .await?;
+        }
+        "INTEGRATION_DELETE" => {
+            let _: () = conn.hdel(integrations_key(guild_id), id).await?;
+        }
+        "APPLICATION_COMMAND_PERMISSIONS_UPDATE" => {
+            let key = command_permissions_key(guild_id);
+            if data.get_array("permissions").is_none_or(Vec::is_empty) {
+                let _: () = conn.hdel(key, id).await?;
+            } else {
+                let _: () = conn.hset(key, id, simd_json::to_string(data)?).await?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub async fn get_integrations(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<Vec<Value>> {
+    cache::get_hashmap_values(conn, integrations_key(guild_id)).await
+}
+
+pub async fn get_command_permissions(
+    conn: &mut redis::aio::Connection,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<Vec<Value>> {
+    cache::get_hashmap_values(conn, command_permissions_key(guild_id)).await
+}
@@ -0,0 +1,94 @@
+use crate::{
+    config::CONFIG,
+    constants::{
+        AUTOMOD_INTENTS, HALT_COLOR, INTENTS_FALLBACK_KEY, INTENTS_FALLBACK_TTL, POLL_INTENTS,
+    },
+    deploy,
+    metrics::GATEWAY_INTENTS_DEGRADED,
+    models::ApiResult,
+    utils::{get_tenant_key, log_discord},
+};
+
+use redis::AsyncCommands;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{error, warn};
+use twilight_gateway::Intents;
+
+static DEGRADED: AtomicBool = AtomicBool::new(false);
+static RESTARTING: AtomicBool = AtomicBool::new(false);
+
+pub fn from_bits(bits: u64) -> Option<Intents> {
+    let extra = AUTOMOD_INTENTS | POLL_INTENTS;
+    let intents = Intents::from_bits(bits & !extra)?;
+
+    // The auto moderation and poll intents are not known to twilight yet
+    // SAFETY: This only keeps bits without a named flag, which bitflags handles like any other
+    // bits, so there are no memory safety requirements. The unknown bits are passed through to
+    // Discord in the identify payload
+    Some(unsafe { Intents::from_bits_unchecked(intents.bits() | (bits & extra)) })
+}
+
+pub fn get() -> Intents {
+    if DEGRADED.load(Ordering::Relaxed) {
+        from_bits(CONFIG.intents_fallback).unwrap()
+    } else {
+        from_bits(CONFIG.intents).unwrap()
+    }
+}
+
+pub fn is_restarting() -> bool {
+    RESTARTING.load(Ordering::Relaxed)
+}
+
+pub fn is_rejected(code: u16) -> bool {
+    CONFIG.intents_fallback != 0 && !DEGRADED.load(Ordering::Relaxed) && matches!(code, 4013 | 4014)
+}
+
+pub async fn load(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    if CONFIG.intents_fallback == 0 {
+        return Ok(());
+    }
+
+    let code: Option<u16> = conn.get(get_tenant_key(INTENTS_FALLBACK_KEY)).await?;
+    if let Some(code) = code {
+        DEGRADED.store(true, Ordering::Relaxed);
+        GATEWAY_INTENTS_DEGRADED.set(1);
+
+        warn!(
+            "Running with fallback intents {} after close code {}",
+            CONFIG.intents_fallback, code
+        );
+        log_discord(
+            HALT_COLOR,
+            format!(
+                "Running with fallback intents {} after close code {}",
+                CONFIG.intents_fallback, code
+            ),
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn fallback(conn: &mut redis::aio::Connection, shard: u64, code: u16) {
+    error!(
+        "[Shard {}] Intents rejected (code: {}), restarting with fallback intents",
+        shard, code
+    );
+
+    let result: redis::RedisResult<()> = conn
+        .set_ex(
+            get_tenant_key(INTENTS_FALLBACK_KEY),
+            code,
+            INTENTS_FALLBACK_TTL,
+        )
+        .await;
+
+    if let Err(err) = result {
+        error!("Failed to save intents fallback: {:?}", err);
+        return;
+    }
+
+    RESTARTING.store(true, Ordering::Relaxed);
+    deploy::request_shutdown();
+}
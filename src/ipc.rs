@@ -0,0 +1,64 @@
+use crate::{config::CONFIG, constants::IPC_BUFFER_SIZE, models::ApiResult};
+
+use lazy_static::lazy_static;
+use std::{fs, path::Path, sync::Arc};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{UnixListener, UnixStream},
+    sync::broadcast::{self, error::RecvError, Sender},
+};
+use tracing::{info, warn};
+
+lazy_static! {
+    static ref SENDER: Sender<Arc<Vec<u8>>> = broadcast::channel(IPC_BUFFER_SIZE).0;
+}
+
+pub fn publish(payload: &[u8]) {
+    if SENDER.receiver_count() == 0 {
+        return;
+    }
+
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+
+    let _ = SENDER.send(Arc::new(frame));
+}
+
+async fn handle_client(mut stream: UnixStream) {
+    let mut rx = SENDER.subscribe();
+
+    loop {
+        match rx.recv().await {
+            Ok(frame) => {
+                if let Err(err) = stream.write_all(frame.as_slice()).await {
+                    info!("Socket client disconnected: {:?}", err);
+                    return;
+                }
+            }
+            Err(RecvError::Lagged(amount)) => {
+                warn!("Socket client lagged behind, skipped {} events", amount);
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+pub async fn run_server() -> ApiResult<()> {
+    if CONFIG.socket_path.is_empty() {
+        return Ok(());
+    }
+
+    let path = Path::new(CONFIG.socket_path.as_str());
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    info!("Listening for socket clients on {}", CONFIG.socket_path);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_client(stream));
+    }
+}
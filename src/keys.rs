@@ -0,0 +1,305 @@
+use crate::constants::{
+    AUTOMOD_RULES_KEY, BANS_KEY, CHANNEL_KEY, CHANNEL_TREE_KEY, COMMAND_PERMISSIONS_KEY, EMOJI_KEY,
+    FORUM_SETTINGS_KEY, GUILD_ITEM_KEYS, GUILD_KEY, INTEGRATIONS_KEY, KEYS_SUFFIX, MEMBER_KEY,
+    MESSAGE_KEY, PRESENCE_KEY, ROLE_KEY, ROLE_POSITIONS_KEY, TIMEOUTS_KEY, VOICE_KEY,
+};
+
+use std::fmt::{self, Display, Formatter};
+use twilight_model::id::{
+    marker::{ChannelMarker, EmojiMarker, GuildMarker, MessageMarker, RoleMarker, UserMarker},
+    Id,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheKey<'a> {
+    pub kind: &'a str,
+    pub parent: Option<&'a str>,
+    pub id: Option<&'a str>,
+}
+
+impl<'a> CacheKey<'a> {
+    pub fn parse(key: &'a str) -> Self {
+        let mut parts = key.splitn(3, ':');
+        let kind = parts.next().unwrap_or_default();
+
+        match (parts.next(), parts.next()) {
+            (Some(parent), Some(id)) => Self {
+                kind,
+                parent: Some(parent),
+                id: Some(id),
+            },
+            (id, _) => Self {
+                kind,
+                parent: None,
+                id,
+            },
+        }
+    }
+
+    pub fn index_key(&self) -> Option<String> {
+        self.id.map(|_| format!("{}{}", self.kind, KEYS_SUFFIX))
+    }
+
+    pub fn parent_set(&self) -> Option<String> {
+        let parent = self.parent?;
+
+        if self.kind == MESSAGE_KEY {
+            Some(format!("{}{}:{}", CHANNEL_KEY, KEYS_SUFFIX, parent))
+        } else {
+            Some(format!("{}{}:{}", GUILD_KEY, KEYS_SUFFIX, parent))
+        }
+    }
+
+    pub fn legacy_key(&self) -> String {
+        match (self.kind, self.parent, self.id) {
+            (CHANNEL_KEY, Some(_), Some(id)) => format!("{}:{}", CHANNEL_KEY, id),
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn hash_field(&self) -> Option<(String, String)> {
+        let (parent, id) = (self.parent?, self.id?);
+
+        if self.kind == MESSAGE_KEY {
+            Some((hash_key(CHANNEL_KEY, parent, MESSAGE_KEY), id.to_owned()))
+        } else if GUILD_ITEM_KEYS.contains(&self.kind) {
+            Some((hash_key(GUILD_KEY, parent, self.kind), id.to_owned()))
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for CacheKey<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.kind)?;
+        if let Some(parent) = self.parent {
+            write!(f, ":{}", parent)?;
+        }
+        if let Some(id) = self.id {
+            write!(f, ":{}", id)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn guild_key(guild: Id<GuildMarker>) -> String {
+    format!("{}:{}", GUILD_KEY, guild)
+}
+
+pub fn channel_key(guild: Id<GuildMarker>, channel: Id<ChannelMarker>) -> String {
+    format!("{}:{}:{}", CHANNEL_KEY, guild, channel)
+}
+
+pub fn private_channel_key(channel: Id<ChannelMarker>) -> String {
+    format!("{}:{}", CHANNEL_KEY, channel)
+}
+
+pub fn message_key(channel: Id<ChannelMarker>, message: Id<MessageMarker>) -> String {
+    format!("{}:{}:{}", MESSAGE_KEY, channel, message)
+}
+
+pub fn role_key(guild: Id<GuildMarker>, role: Id<RoleMarker>) -> String {
+    format!("{}:{}:{}", ROLE_KEY, guild, role)
+}
+
+pub fn emoji_key(guild: Id<GuildMarker>, emoji: Id<EmojiMarker>) -> String {
+    format!("{}:{}:{}", EMOJI_KEY, guild, emoji)
+}
+
+pub fn member_key(guild: Id<GuildMarker>, member: Id<UserMarker>) -> String {
+    format!("{}:{}:{}", MEMBER_KEY, guild, member)
+}
+
+pub fn presence_key(guild: Id<GuildMarker>, member: Id<UserMarker>) -> String {
+    format!("{}:{}:{}", PRESENCE_KEY, guild, member)
+}
+
+pub fn voice_key(guild: Id<GuildMarker>, member: Id<UserMarker>) -> String {
+    format!("{}:{}:{}", VOICE_KEY, guild, member)
+}
+
+pub fn role_positions_key(guild: Id<GuildMarker>) -> String {
+    format!("{}:{}", ROLE_POSITIONS_KEY, guild)
+}
+
+pub fn channel_tree_key(guild: Id<GuildMarker>) -> String {
+    format!("{}:{}", CHANNEL_TREE_KEY, guild)
+}
+
+pub fn bans_key(guild: Id<GuildMarker>) -> String {
+    format!("{}:{}", BANS_KEY, guild)
+}
+
+pub fn timeouts_key(guild: Id<GuildMarker>) -> String {
+    format!("{}:{}", TIMEOUTS_KEY, guild)
+}
+
+pub fn automod_rules_key(guild: Id<GuildMarker>) -> String {
+    format!("{}:{}", AUTOMOD_RULES_KEY, guild)
+}
+
+pub fn integrations_key(guild: Id<GuildMarker>) -> String {
+    format!("{}:{}", INTEGRATIONS_KEY, guild)
+}
+
+pub fn command_permissions_key(guild: Id<GuildMarker>) -> String {
+    format!("{}:{}", COMMAND_PERMISSIONS_KEY, guild)
+}
+
+pub fn forum_settings_key(guild: Id<GuildMarker>) -> String {
+    format!("{}:{}", FORUM_SETTINGS_KEY, guild)
+}
+
+pub fn guild_aux_keys(guild: Id<GuildMarker>) -> Vec<String> {
+    vec![
+        role_positions_key(guild),
+        channel_tree_key(guild),
+        bans_key(guild),
+        timeouts_key(guild),
+        automod_rules_key(guild),
+        integrations_key(guild),
+        command_permissions_key(guild),
+        forum_settings_key(guild),
+    ]
+}
+
+pub fn hash_key(parent: &str, id: impl Display, kind: &str) -> String {
+    format!("{}:{}:{}s", parent, id, kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: u64 = 1;
+    const CHANNEL: u64 = 2;
+    const OTHER: u64 = 3;
+
+    fn item_keys() -> Vec<(&'static str, String)> {
+        vec![
+            (CHANNEL_KEY, channel_key(Id::new(GUILD), Id::new(OTHER))),
+            (ROLE_KEY, role_key(Id::new(GUILD), Id::new(OTHER))),
+            (EMOJI_KEY, emoji_key(Id::new(GUILD), Id::new(OTHER))),
+            (MEMBER_KEY, member_key(Id::new(GUILD), Id::new(OTHER))),
+            (PRESENCE_KEY, presence_key(Id::new(GUILD), Id::new(OTHER))),
+            (VOICE_KEY, voice_key(Id::new(GUILD), Id::new(OTHER))),
+        ]
+    }
+
+    fn guild_keys() -> Vec<(&'static str, String)> {
+        vec![
+            (GUILD_KEY, guild_key(Id::new(GUILD))),
+            (ROLE_POSITIONS_KEY, role_positions_key(Id::new(GUILD))),
+            (CHANNEL_TREE_KEY, channel_tree_key(Id::new(GUILD))),
+            (BANS_KEY, bans_key(Id::new(GUILD))),
+            (TIMEOUTS_KEY, timeouts_key(Id::new(GUILD))),
+            (AUTOMOD_RULES_KEY, automod_rules_key(Id::new(GUILD))),
+            (INTEGRATIONS_KEY, integrations_key(Id::new(GUILD))),
+            (
+                COMMAND_PERMISSIONS_KEY,
+                command_permissions_key(Id::new(GUILD)),
+            ),
+            (FORUM_SETTINGS_KEY, forum_settings_key(Id::new(GUILD))),
+        ]
+    }
+
+    #[test]
+    fn guild_items_roundtrip() {
+        for (kind, key) in item_keys() {
+            let parsed = CacheKey::parse(key.as_str());
+            assert_eq!(parsed.kind, kind);
+            assert_eq!(parsed.parent, Some("1"));
+            assert_eq!(parsed.id, Some("3"));
+            assert_eq!(parsed.to_string(), key);
+            assert_eq!(
+                parsed.hash_field(),
+                Some((format!("guild:1:{}s", kind), "3".to_owned()))
+            );
+            assert_eq!(parsed.parent_set(), Some("guild_keys:1".to_owned()));
+        }
+    }
+
+    #[test]
+    fn guild_keys_roundtrip() {
+        for (kind, key) in guild_keys() {
+            let parsed = CacheKey::parse(key.as_str());
+            assert_eq!(parsed.kind, kind);
+            assert_eq!(parsed.parent, None);
+            assert_eq!(parsed.id, Some("1"));
+            assert_eq!(parsed.to_string(), key);
+            assert_eq!(parsed.legacy_key(), key);
+            assert_eq!(parsed.hash_field(), None);
+        }
+    }
+
+    #[test]
+    fn guild_aux_keys_cover_guild_keys() {
+        let aux = guild_aux_keys(Id::new(GUILD));
+        let expected: Vec<_> = guild_keys()
+            .into_iter()
+            .filter(|(kind, _)| *kind != GUILD_KEY)
+            .map(|(_, key)| key)
+            .collect();
+
+        assert_eq!(aux, expected);
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let key = message_key(Id::new(CHANNEL), Id::new(OTHER));
+        let parsed = CacheKey::parse(key.as_str());
+
+        assert_eq!(parsed.kind, MESSAGE_KEY);
+        assert_eq!(parsed.parent, Some("2"));
+        assert_eq!(parsed.id, Some("3"));
+        assert_eq!(parsed.legacy_key(), key);
+        assert_eq!(
+            parsed.hash_field(),
+            Some(("channel:2:messages".to_owned(), "3".to_owned()))
+        );
+        assert_eq!(parsed.parent_set(), Some("channel_keys:2".to_owned()));
+    }
+
+    #[test]
+    fn channel_legacy_keys() {
+        let guild =
+            CacheKey::parse(channel_key(Id::new(GUILD), Id::new(CHANNEL)).as_str()).legacy_key();
+        let private = private_channel_key(Id::new(CHANNEL));
+
+        assert_eq!(guild, private);
+        assert_eq!(CacheKey::parse(private.as_str()).legacy_key(), private);
+        assert_eq!(CacheKey::parse(private.as_str()).hash_field(), None);
+    }
+
+    #[test]
+    fn hash_fields_do_not_collide() {
+        let mut hashes: Vec<_> = item_keys()
+            .iter()
+            .filter_map(|(_, key)| CacheKey::parse(key.as_str()).hash_field())
+            .map(|(hash, _)| hash)
+            .collect();
+        hashes.push(
+            CacheKey::parse(message_key(Id::new(1), Id::new(OTHER)).as_str())
+                .hash_field()
+                .map(|(hash, _)| hash)
+                .unwrap_or_default(),
+        );
+
+        let builders: Vec<_> = item_keys()
+            .into_iter()
+            .chain(guild_keys())
+            .map(|(_, key)| key)
+            .chain([
+                private_channel_key(Id::new(1)),
+                message_key(Id::new(1), Id::new(CHANNEL)),
+            ])
+            .collect();
+
+        for (i, hash) in hashes.iter().enumerate() {
+            assert!(!hashes[i + 1..].contains(hash), "{} collides", hash);
+            assert!(!builders.contains(hash), "{} collides with a key", hash);
+        }
+    }
+}
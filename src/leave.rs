@@ -0,0 +1,157 @@
+use crate::{
+    cache,
+    chunks::get_nonce,
+    constants::{GUILD_KEY, KEYS_SUFFIX, LEAVE_STATUS_TTL},
+    keys::{guild_key, CacheKey},
+    models::{ApiResult, GuildLeaveInfo, GuildLeaveStatus},
+    rest::{self, CLIENT},
+    utils::get_redis_connection,
+};
+
+use lazy_static::lazy_static;
+use simd_json::{owned::Value, ValueAccess};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+lazy_static! {
+    static ref LEAVES: Mutex<HashMap<String, (Instant, GuildLeaveStatus)>> =
+        Mutex::new(HashMap::new());
+}
+
+async fn get_guilds(
+    conn: &mut redis::aio::Connection,
+    info: &GuildLeaveInfo,
+) -> ApiResult<Vec<Id<GuildMarker>>> {
+    let guilds = if info.guild_ids.is_empty() {
+        let keys: Vec<String> =
+            cache::get_members(conn, format!("{}{}", GUILD_KEY, KEYS_SUFFIX)).await?;
+
+        keys.iter()
+            .filter_map(|key| CacheKey::parse(key).id.and_then(|id| id.parse().ok()))
+            .filter_map(Id::new_checked)
+            .collect()
+    } else {
+        info.guild_ids.clone()
+    };
+
+    let max_members = match info.max_members {
+        Some(max_members) => max_members,
+        None => return Ok(guilds),
+    };
+
+    let keys: Vec<String> = guilds.iter().map(|guild| guild_key(*guild)).collect();
+    let values: Vec<Option<Value>> = cache::get_all(conn, keys.as_slice()).await?;
+
+    Ok(guilds
+        .into_iter()
+        .zip(values)
+        .filter(|(_, value)| {
+            value
+                .as_ref()
+                .and_then(|value| value.get_u64("member_count"))
+                .is_some_and(|count| count < max_members)
+        })
+        .map(|(guild, _)| guild)
+        .collect())
+}
+
+async fn leave(conn: &mut redis::aio::Connection, guild_id: Id<GuildMarker>) -> ApiResult<()> {
+    rest::wait().await;
+    CLIENT.leave_guild(guild_id).exec().await?;
+
+    cache::purge_guild(conn, guild_id).await?;
+
+    Ok(())
+}
+
+async fn run(mut conn: redis::aio::Connection, nonce: String, guilds: Vec<Id<GuildMarker>>) {
+    for guild_id in guilds {
+        let cancelled = LEAVES
+            .lock()
+            .unwrap()
+            .get(nonce.as_str())
+            .is_none_or(|(_, status)| status.cancelled);
+        if cancelled {
+            break;
+        }
+
+        let result = leave(&mut conn, guild_id).await;
+        if let Err(err) = &result {
+            warn!("Failed to leave guild {}: {:?}", guild_id, err);
+        }
+
+        if let Some((_, status)) = LEAVES.lock().unwrap().get_mut(nonce.as_str()) {
+            match result {
+                Ok(_) => status.left += 1,
+                Err(_) => status.failed.push(guild_id.to_string()),
+            }
+        }
+    }
+
+    if let Some((_, status)) = LEAVES.lock().unwrap().get_mut(nonce.as_str()) {
+        status.complete = true;
+
+        info!(
+            "Left {} of {} guilds ({} failed, cancelled: {})",
+            status.left,
+            status.total,
+            status.failed.len(),
+            status.cancelled
+        );
+    }
+}
+
+pub fn get(nonce: &str) -> Option<GuildLeaveStatus> {
+    LEAVES
+        .lock()
+        .unwrap()
+        .get(nonce)
+        .map(|(_, status)| status.clone())
+}
+
+pub fn cancel(nonce: &str) -> Option<GuildLeaveStatus> {
+    LEAVES.lock().unwrap().get_mut(nonce).map(|(_, status)| {
+        if !status.complete {
+            status.cancelled = true;
+        }
+        status.clone()
+    })
+}
+
+pub async fn start(redis: &redis::Client, info: GuildLeaveInfo) -> ApiResult<GuildLeaveStatus> {
+    let mut conn = get_redis_connection(redis).await?;
+    let guilds = get_guilds(&mut conn, &info).await?;
+
+    if info.dry_run {
+        return Ok(GuildLeaveStatus {
+            total: guilds.len() as u64,
+            complete: true,
+            guild_ids: guilds.iter().map(|guild| guild.to_string()).collect(),
+            ..GuildLeaveStatus::default()
+        });
+    }
+
+    let status = GuildLeaveStatus {
+        nonce: get_nonce(),
+        total: guilds.len() as u64,
+        ..GuildLeaveStatus::default()
+    };
+
+    let mut leaves = LEAVES.lock().unwrap();
+    leaves.retain(|_, (created, _)| {
+        created.elapsed() < Duration::from_millis(LEAVE_STATUS_TTL as u64)
+    });
+    leaves.insert(status.nonce.clone(), (Instant::now(), status.clone()));
+    drop(leaves);
+
+    info!("Leaving {} guilds", guilds.len());
+
+    tokio::spawn(run(conn, status.nonce.clone(), guilds));
+
+    Ok(status)
+}
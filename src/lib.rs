@@ -0,0 +1,8 @@
+#![deny(clippy::all, nonstandard_style, rust_2018_idioms, unused, warnings)]
+
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "client")]
+mod delivery;
+#[cfg(feature = "client")]
+mod ring;
@@ -0,0 +1,157 @@
+use crate::{
+    config::CONFIG,
+    constants::{LOCK_KEY, LOCK_REFRESH_SCRIPT_SOURCE, LOCK_RELEASE_SCRIPT_SOURCE},
+    models::{ApiError, ApiResult},
+    recorder,
+    utils::get_tenant_key,
+};
+
+use lazy_static::lazy_static;
+use redis::Script;
+use std::process;
+use time::OffsetDateTime;
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+lazy_static! {
+    static ref OWNER: String = format!(
+        "{}:{}",
+        process::id(),
+        OffsetDateTime::now_utc().unix_timestamp_nanos()
+    );
+    static ref REFRESH_SCRIPT: Script = Script::new(LOCK_REFRESH_SCRIPT_SOURCE);
+    static ref RELEASE_SCRIPT: Script = Script::new(LOCK_RELEASE_SCRIPT_SOURCE);
+}
+
+fn get_lock_keys() -> Vec<String> {
+    (CONFIG.shards_start..=CONFIG.shards_end)
+        .map(|shard| get_tenant_key(format!("{}:{}", LOCK_KEY, shard).as_str()))
+        .collect()
+}
+
+pub async fn acquire(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    if CONFIG.shard_lock_ttl == 0 {
+        return Ok(());
+    }
+
+    let keys = get_lock_keys();
+
+    let mut pipe = redis::pipe();
+    for key in keys.iter() {
+        pipe.cmd("SET")
+            .arg(key)
+            .arg(OWNER.as_str())
+            .arg("NX")
+            .arg("PX")
+            .arg(CONFIG.shard_lock_ttl);
+    }
+
+    let results: Vec<Option<String>> = pipe.query_async(conn).await?;
+
+    let locked: Vec<u64> = (CONFIG.shards_start..=CONFIG.shards_end)
+        .zip(results.iter())
+        .filter(|(_, result)| result.is_none())
+        .map(|(shard, _)| shard)
+        .collect();
+
+    if !locked.is_empty() {
+        release(conn).await?;
+        return Err(ApiError::ShardsLocked(locked));
+    }
+
+    info!("Acquired lock for {} shards", keys.len());
+
+    Ok(())
+}
+
+pub async fn release(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    if CONFIG.shard_lock_ttl == 0 {
+        return Ok(());
+    }
+
+    let mut invocation = RELEASE_SCRIPT.prepare_invoke();
+    invocation.arg(OWNER.as_str());
+
+    for key in get_lock_keys() {
+        invocation.key(key);
+    }
+
+    let _: u64 = invocation.invoke_async(conn).await?;
+
+    Ok(())
+}
+
+pub async fn try_acquire_key(
+    conn: &mut redis::aio::Connection,
+    key: &str,
+    ttl: u64,
+) -> ApiResult<bool> {
+    let result: Option<String> = redis::cmd("SET")
+        .arg(key)
+        .arg(OWNER.as_str())
+        .arg("NX")
+        .arg("PX")
+        .arg(ttl)
+        .query_async(conn)
+        .await?;
+
+    Ok(result.is_some())
+}
+
+pub async fn refresh_key(
+    conn: &mut redis::aio::Connection,
+    key: &str,
+    ttl: u64,
+) -> ApiResult<bool> {
+    let lost: u64 = REFRESH_SCRIPT
+        .key(key)
+        .arg(OWNER.as_str())
+        .arg(ttl)
+        .invoke_async(conn)
+        .await?;
+
+    Ok(lost == 0)
+}
+
+pub async fn release_key(conn: &mut redis::aio::Connection, key: &str) -> ApiResult<()> {
+    let _: u64 = RELEASE_SCRIPT
+        .key(key)
+        .arg(OWNER.as_str())
+        .invoke_async(conn)
+        .await?;
+
+    Ok(())
+}
+
+async fn refresh(conn: &mut redis::aio::Connection) -> ApiResult<u64> {
+    let mut invocation = REFRESH_SCRIPT.prepare_invoke();
+    invocation.arg(OWNER.as_str()).arg(CONFIG.shard_lock_ttl);
+
+    for key in get_lock_keys() {
+        invocation.key(key);
+    }
+
+    let lost = invocation.invoke_async(conn).await?;
+
+    Ok(lost)
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection) {
+    if CONFIG.shard_lock_ttl == 0 || recorder::is_replaying() {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(CONFIG.shard_lock_ttl / 3)).await;
+
+        match refresh(conn).await {
+            Ok(0) => {}
+            Ok(lost) => {
+                warn!("Lock of {} shards is held by another instance", lost);
+            }
+            Err(err) => {
+                warn!("Failed to refresh shard lock: {:?}", err);
+            }
+        }
+    }
+}
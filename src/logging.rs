@@ -0,0 +1,194 @@
+use crate::{
+    config::CONFIG,
+    constants::CAPTURE_FLUSH_INTERVAL,
+    models::{ApiError, ApiResult, CaptureInfo, PayloadInfo, TapInfo},
+};
+
+use lazy_static::lazy_static;
+use simd_json::ValueAccess;
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    mem,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tokio::time::{sleep, Duration};
+use tracing::warn;
+use tracing_subscriber::{fmt::Formatter, reload::Handle, EnvFilter};
+
+lazy_static! {
+    static ref FILTER_HANDLE: Mutex<Option<Handle<EnvFilter, Formatter>>> = Mutex::new(None);
+    static ref CAPTURE: Mutex<Option<(PathBuf, CaptureInfo)>> = Mutex::new(None);
+    static ref CAPTURE_BUFFER: Mutex<HashMap<PathBuf, Vec<u8>>> = Mutex::new(HashMap::new());
+    static ref TAP: Mutex<Option<TapInfo>> = Mutex::new(None);
+    static ref TAP_BUFFER: Mutex<Vec<String>> = Mutex::new(vec![]);
+}
+
+pub fn init() {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(CONFIG.rust_log.as_str()))
+        .with_filter_reloading();
+
+    *FILTER_HANDLE.lock().unwrap() = Some(builder.reload_handle());
+
+    builder.init();
+}
+
+pub fn set_filter(directives: &str) -> ApiResult<()> {
+    let filter =
+        EnvFilter::try_new(directives).map_err(|err| ApiError::InvalidCommand(err.to_string()))?;
+
+    if let Some(handle) = FILTER_HANDLE.lock().unwrap().as_ref() {
+        handle
+            .reload(filter)
+            .map_err(|err| ApiError::InvalidCommand(err.to_string()))?;
+    }
+
+    Ok(())
+}
+
+pub fn set_capture(capture: CaptureInfo) -> ApiResult<()> {
+    if capture.file.is_empty()
+        || capture.file.starts_with('.')
+        || !capture
+            .file
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        return Err(ApiError::InvalidCommand(format!(
+            "Invalid capture file: {}",
+            capture.file
+        )));
+    }
+
+    let path = Path::new(CONFIG.capture_path.as_str()).join(capture.file.as_str());
+    *CAPTURE.lock().unwrap() = Some((path, capture));
+
+    Ok(())
+}
+
+pub fn capture_payload(kind: &str, payload: &PayloadInfo) {
+    let mut capture = CAPTURE.lock().unwrap();
+
+    let (path, info) = match capture.as_mut() {
+        Some((path, info)) if info.kind == kind => (path, info),
+        _ => return,
+    };
+
+    let mut payload = match simd_json::to_vec(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            warn!("Failed to serialize captured payload: {:?}", err);
+            return;
+        }
+    };
+    payload.push(b'\n');
+
+    CAPTURE_BUFFER
+        .lock()
+        .unwrap()
+        .entry(path.clone())
+        .or_default()
+        .extend_from_slice(payload.as_slice());
+
+    info.count = info.count.saturating_sub(1);
+    if info.count == 0 {
+        *capture = None;
+    }
+}
+
+fn write(path: &Path, data: &[u8]) -> ApiResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(data)?;
+
+    Ok(())
+}
+
+pub async fn run_jobs() {
+    loop {
+        sleep(Duration::from_millis(CAPTURE_FLUSH_INTERVAL as u64)).await;
+
+        let buffer = mem::take(&mut *CAPTURE_BUFFER.lock().unwrap());
+        if buffer.is_empty() {
+            continue;
+        }
+
+        let result = tokio::task::spawn_blocking(move || {
+            for (path, data) in buffer {
+                if let Err(err) = write(&path, data.as_slice()) {
+                    warn!("Failed to write capture to {}: {:?}", path.display(), err);
+                }
+            }
+        })
+        .await;
+
+        if let Err(err) = result {
+            warn!("Failed to write captures: {:?}", err);
+        }
+    }
+}
+
+pub fn set_tap(tap: Option<TapInfo>) -> ApiResult<()> {
+    if matches!(&tap, Some(tap) if tap.limit == 0) {
+        return Err(ApiError::InvalidCommand(
+            "Tap limit must be greater than 0".to_owned(),
+        ));
+    }
+
+    *TAP.lock().unwrap() = tap;
+
+    Ok(())
+}
+
+pub fn tap_payload(kind: &str, payload: &PayloadInfo) {
+    let limit = {
+        let tap = TAP.lock().unwrap();
+        let tap = match tap.as_ref() {
+            Some(tap) => tap,
+            None => return,
+        };
+
+        if !tap.kinds.is_empty() && !tap.kinds.iter().any(|value| value == kind) {
+            return;
+        }
+
+        if let Some(guild_id) = tap.guild_id.as_deref() {
+            if payload.d.get_str("guild_id") != Some(guild_id) {
+                return;
+            }
+        }
+
+        tap.limit as usize
+    };
+
+    let payload = match simd_json::to_string(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            warn!("Failed to serialize tapped payload: {:?}", err);
+            return;
+        }
+    };
+
+    let mut buffer = TAP_BUFFER.lock().unwrap();
+    buffer.push(payload);
+    if buffer.len() > limit {
+        let excess = buffer.len() - limit;
+        buffer.drain(..excess);
+    }
+}
+
+pub fn take_tap() -> Option<(Vec<String>, u64)> {
+    let limit = TAP.lock().unwrap().as_ref().map(|tap| tap.limit);
+    let buffer = mem::take(&mut *TAP_BUFFER.lock().unwrap());
+
+    match limit {
+        Some(limit) if !buffer.is_empty() => Some((buffer, limit)),
+        _ => None,
+    }
+}
@@ -5,48 +5,130 @@
 
 use crate::{
     config::CONFIG,
-    constants::{EXCHANGE, QUEUE_RECV, QUEUE_SEND, SESSIONS_KEY, SHARDS_KEY, STARTED_KEY},
-    models::{ApiResult, FormattedDateTime, SessionInfo},
-    utils::{get_clusters, get_queue, get_resume_sessions, get_shards},
+    constants::{EXCHANGE, QUEUE_RECV, QUEUE_SEND, QUEUE_SEND_RESULTS, SHARDS_KEY, STARTED_KEY},
+    models::{ApiResult, FormattedDateTime},
+    utils::{
+        get_clusters, get_envelope_exchange, get_queue, get_redis_connection, get_redis_info,
+        get_resume_sessions, get_shards, get_tenant_key,
+    },
 };
 
 use dotenv::dotenv;
+use futures_util::Stream;
 use lapin::{
     options::{ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions},
     types::FieldTable,
     ExchangeKind,
 };
-use std::collections::HashMap;
-use tokio::{join, signal::ctrl_c};
+use std::{collections::HashMap, env, fs::File, io::BufReader, pin::Pin, process};
+use tokio::{join, select, signal::ctrl_c};
 use tracing::{error, info};
+use twilight_gateway::Event;
 
+mod activity;
+mod anomaly;
+mod application;
+mod authz;
+mod automod;
+mod backpressure;
+mod budget;
 mod cache;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod checkpoint;
+mod chunks;
+mod commands;
 mod config;
+mod conflict;
 mod constants;
+mod dedup;
+mod delivery;
+mod deploy;
+mod failover;
+mod features;
+mod firehose;
+mod forum;
 mod handler;
+mod identify;
+mod incident;
+mod integrations;
+mod intents;
+mod ipc;
+mod keys;
+mod leave;
+mod lock;
+mod logging;
+mod mapping;
+mod memory;
 mod metrics;
+mod migration;
+mod milestones;
+mod mirror;
 mod models;
+mod outage;
+mod policy;
+mod prune;
+mod reconcile;
+mod recorder;
+mod rest;
+mod resume;
+mod rewind;
+#[cfg(feature = "shm")]
+mod ring;
+mod sampler;
+mod schema;
+mod server;
+mod shed;
+#[cfg(feature = "shm")]
+mod shm;
+mod startup;
+mod targets;
+mod threshold;
+mod timestamps;
+mod typing;
+mod usage;
 mod utils;
+mod validator;
+mod watermark;
+mod webhook;
+
+type EventStream = Pin<Box<dyn Stream<Item = (u64, Event)> + Send + Sync>>;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    tracing_subscriber::fmt::init();
+    logging::init();
+
+    let dry_run = env::args().any(|arg| arg == "--dry-run");
+    let migrate = env::args().any(|arg| arg == "--migrate-layout");
+    let schema = env::args().any(|arg| arg == "--generate-schema");
+    let migrate_clusters = env::args().any(|arg| arg == "--migrate-clusters");
 
-    let result = real_main().await;
+    let result = if dry_run {
+        startup::dry_run().await
+    } else if migrate {
+        startup::migrate_layout().await
+    } else if schema {
+        schema::generate()
+    } else if migrate_clusters {
+        startup::migrate_clusters().await
+    } else {
+        real_main().await
+    };
 
     if let Err(err) = result {
         error!("{:?}", err);
+
+        if dry_run || migrate || schema || migrate_clusters {
+            process::exit(1);
+        }
     }
 }
 
 async fn real_main() -> ApiResult<()> {
-    let redis = redis::Client::open(format!(
-        "redis://{}:{}/",
-        CONFIG.redis_host, CONFIG.redis_port
-    ))?;
+    let redis = redis::Client::open(get_redis_info())?;
 
-    let mut conn = redis.get_async_connection().await?;
+    let mut conn = get_redis_connection(&redis).await?;
 
     let amqp = lapin::Connection::connect(
         format!(
@@ -60,6 +142,7 @@ async fn real_main() -> ApiResult<()> {
 
     let channel = amqp.create_channel().await?;
     let channel_send = amqp.create_channel().await?;
+    let channel_shed = amqp.create_channel().await?;
 
     channel
         .exchange_declare(
@@ -75,6 +158,22 @@ async fn real_main() -> ApiResult<()> {
             FieldTable::default(),
         )
         .await?;
+    if CONFIG.envelope_dual_version != 0 {
+        channel
+            .exchange_declare(
+                get_envelope_exchange(CONFIG.envelope_dual_version).as_str(),
+                ExchangeKind::Topic,
+                ExchangeDeclareOptions {
+                    passive: false,
+                    durable: true,
+                    auto_delete: false,
+                    internal: false,
+                    nowait: false,
+                },
+                FieldTable::default(),
+            )
+            .await?;
+    }
     channel_send
         .queue_declare(
             QUEUE_SEND,
@@ -88,6 +187,19 @@ async fn real_main() -> ApiResult<()> {
             FieldTable::default(),
         )
         .await?;
+    channel_send
+        .queue_declare(
+            QUEUE_SEND_RESULTS,
+            QueueDeclareOptions {
+                passive: false,
+                durable: true,
+                exclusive: false,
+                auto_delete: false,
+                nowait: false,
+            },
+            FieldTable::default(),
+        )
+        .await?;
 
     if CONFIG.default_queue {
         channel
@@ -115,73 +227,188 @@ async fn real_main() -> ApiResult<()> {
             .await?;
     }
 
+    let mirror = mirror::connect().await?;
+
+    #[cfg(feature = "shm")]
+    shm::init()?;
+
+    let replay = match env::args().skip_while(|arg| arg != "--replay").nth(1) {
+        Some(path) => Some(BufReader::new(File::open(path)?)),
+        None => None,
+    };
+    let replaying = replay.is_some();
+
+    if !replaying && env::args().any(|arg| arg == "--takeover") {
+        deploy::request_takeover(&mut conn).await?;
+    }
+
     let shards = get_shards();
-    let resumes = get_resume_sessions(&mut conn).await?;
+    let resumes = if replaying {
+        HashMap::new()
+    } else {
+        get_resume_sessions(&mut conn).await?
+    };
     let resumes_len = resumes.len();
+
+    if !replaying {
+        budget::check(resumes_len as u64).await?;
+        lock::acquire(&mut conn).await?;
+    }
+
+    migration::load(&mut conn).await?;
+    intents::load(&mut conn).await?;
+    threshold::load(&mut conn).await?;
+    mapping::load(&mut conn).await?;
     let queue = get_queue();
     let (clusters, events) = get_clusters(resumes, queue).await?;
 
+    let events: Vec<EventStream> = match replay {
+        Some(replay) => recorder::replay(replay, clusters.as_slice())
+            .into_iter()
+            .map(|events| Box::pin(events) as _)
+            .collect(),
+        None => events
+            .into_iter()
+            .map(|events| Box::pin(recorder::record(events)) as _)
+            .collect(),
+    };
+
     info!("Starting up {} clusters", clusters.len());
     info!("Starting up {} shards", shards);
     info!("Resuming {} sessions", resumes_len);
 
-    cache::set(&mut conn, STARTED_KEY, &FormattedDateTime::now()).await?;
-    cache::set(&mut conn, SHARDS_KEY, &CONFIG.shards_total).await?;
+    let mut status_conn = targets::status(&mut conn).await?;
+    cache::set(
+        &mut status_conn,
+        get_tenant_key(STARTED_KEY),
+        &FormattedDateTime::now(),
+    )
+    .await?;
+    cache::set(
+        &mut status_conn,
+        get_tenant_key(SHARDS_KEY),
+        &CONFIG.shards_total,
+    )
+    .await?;
+    drop(status_conn);
+
+    let clusters_clone = clusters.clone();
+    let redis_clone = redis.clone();
+    tokio::spawn(async move {
+        let _ = server::run_server(clusters_clone, redis_clone).await;
+    });
+
+    tokio::spawn(metrics::run_push());
+    tokio::spawn(anomaly::run_jobs(channel.clone()));
+    tokio::spawn(shed::run_jobs(channel.clone(), channel_shed));
+    tokio::spawn(backpressure::run_jobs());
+    tokio::spawn(milestones::run_jobs(channel.clone()));
+    tokio::spawn(incident::run_jobs());
+    tokio::spawn(dedup::run_jobs());
+    tokio::spawn(typing::run_jobs(channel.clone()));
+    tokio::spawn(sampler::run_jobs());
+    tokio::spawn(recorder::run_jobs());
+    tokio::spawn(rewind::run_jobs());
+    tokio::spawn(logging::run_jobs());
+    if !replaying {
+        tokio::spawn(budget::run_jobs());
+    }
+    tokio::spawn(failover::run_jobs(channel.clone(), clusters.clone()));
 
     tokio::spawn(async {
-        let _ = metrics::run_server().await;
+        if let Err(err) = ipc::run_server().await {
+            error!("Failed to run socket server: {:?}", err);
+        }
     });
 
-    let mut conn_clone = redis.get_async_connection().await?;
-    let mut conn_clone_two = redis.get_async_connection().await?;
-    let mut conn_clone_three = redis.get_async_connection().await?;
+    let mut conn_clone = get_redis_connection(&redis).await?;
+    let mut conn_clone_two = get_redis_connection(&redis).await?;
+    let mut conn_clone_three = get_redis_connection(&redis).await?;
+    let mut conn_clone_four = get_redis_connection(&redis).await?;
+    let mut conn_clone_five = get_redis_connection(&redis).await?;
+    let mut conn_clone_six = get_redis_connection(&redis).await?;
+    let mut conn_clone_seven = get_redis_connection(&redis).await?;
+    let mut conn_clone_eight = get_redis_connection(&redis).await?;
+    let mut conn_clone_nine = get_redis_connection(&redis).await?;
+    let mut conn_clone_ten = get_redis_connection(&redis).await?;
+    let mut conn_clone_eleven = get_redis_connection(&redis).await?;
+    let mut conn_clone_twelve = get_redis_connection(&redis).await?;
+    let mut conn_clone_thirteen = get_redis_connection(&redis).await?;
+    let mut conn_clone_fourteen = get_redis_connection(&redis).await?;
     let clusters_clone = clusters.clone();
+    let channel_clone = channel.clone();
     tokio::spawn(async move {
         join!(
             cache::run_jobs(&mut conn_clone, clusters_clone.as_slice()),
             cache::run_cleanups(&mut conn_clone_two),
             metrics::run_jobs(&mut conn_clone_three, clusters_clone.as_slice()),
+            usage::run_jobs(&mut conn_clone_four),
+            cache::run_flushes(&mut conn_clone_five),
+            lock::run_jobs(&mut conn_clone_six),
+            deploy::run_jobs(&mut conn_clone_seven, clusters_clone.as_slice()),
+            policy::run_jobs(&mut conn_clone_eight),
+            migration::run_jobs(&mut conn_clone_nine),
+            reconcile::run_jobs(&mut conn_clone_ten),
+            threshold::run_jobs(&mut conn_clone_eleven),
+            prune::run_jobs(&mut conn_clone_twelve),
+            memory::run_jobs(&mut conn_clone_thirteen, channel_clone),
+            cache::run_taps(&mut conn_clone_fourteen),
         )
     });
 
     for (cluster, events) in clusters.clone().into_iter().zip(events.into_iter()) {
-        let cluster_clone = cluster.clone();
-        tokio::spawn(async move {
-            cluster_clone.up().await;
-        });
-
-        let mut conn_clone = redis.get_async_connection().await?;
+        let mut conn_clone = get_redis_connection(&redis).await?;
         let cluster_clone = cluster.clone();
         let channel_clone = channel.clone();
+        let mirror_clone = mirror.clone();
         tokio::spawn(async move {
-            handler::outgoing(&mut conn_clone, &cluster_clone, &channel_clone, events).await;
+            handler::outgoing(
+                &mut conn_clone,
+                &cluster_clone,
+                (!replaying).then_some(&channel_clone),
+                mirror_clone,
+                events,
+            )
+            .await;
         });
     }
 
-    let channel_clone = channel_send.clone();
-    let clusters_clone = clusters.clone();
+    if !replaying {
+        tokio::spawn(startup::run_clusters(clusters.clone()));
+    }
+
+    let mut conn_clone = get_redis_connection(&redis).await?;
+    let channel_clone = channel.clone();
     tokio::spawn(async move {
-        handler::incoming(clusters_clone.as_slice(), &channel_clone).await;
+        application::run_jobs(&mut conn_clone, channel_clone).await;
     });
 
-    ctrl_c().await?;
-
-    info!("Shutting down");
+    if !replaying {
+        let conn_clone = get_redis_connection(&redis).await?;
+        let mut conn_clone_two = get_redis_connection(&redis).await?;
+        let channel_clone = channel_send.clone();
+        let clusters_clone = clusters.clone();
+        tokio::spawn(async move {
+            handler::incoming(
+                clusters_clone.as_slice(),
+                conn_clone,
+                &mut conn_clone_two,
+                &channel_clone,
+            )
+            .await;
+        });
+    }
 
-    let mut sessions = HashMap::new();
-    for cluster in clusters {
-        for (key, value) in cluster.down_resumable().into_iter() {
-            sessions.insert(
-                key.to_string(),
-                SessionInfo {
-                    session_id: value.session_id,
-                    sequence: value.sequence,
-                },
-            );
-        }
+    select! {
+        result = ctrl_c() => result?,
+        _ = deploy::wait_shutdown() => {}
     }
 
-    cache::set(&mut conn, SESSIONS_KEY, &sessions).await?;
+    deploy::shutdown(&mut conn, clusters.as_slice()).await?;
+
+    if intents::is_restarting() {
+        process::exit(1);
+    }
 
     Ok(())
 }
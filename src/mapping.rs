@@ -0,0 +1,105 @@
+use crate::{
+    config::CONFIG,
+    constants::CLUSTERS_KEY,
+    models::{ApiError, ApiResult},
+    utils::{get_default_cluster_ranges, get_shards, get_tenant_key},
+};
+
+use lazy_static::lazy_static;
+use redis::AsyncCommands;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+lazy_static! {
+    static ref RANGES: Mutex<Option<Vec<(u64, u64)>>> = Mutex::new(None);
+}
+
+fn is_valid(ranges: &[(u64, u64)]) -> bool {
+    let mut next = CONFIG.shards_start;
+
+    for (from, to) in ranges {
+        if *from != next || from > to {
+            return false;
+        }
+        next = to + 1;
+    }
+
+    !ranges.is_empty() && next == CONFIG.shards_end + 1
+}
+
+fn get_key() -> String {
+    get_tenant_key(
+        format!(
+            "{}:{}-{}",
+            CLUSTERS_KEY, CONFIG.shards_start, CONFIG.shards_end
+        )
+        .as_str(),
+    )
+}
+
+pub fn validate() -> ApiResult<()> {
+    if CONFIG.clusters == 0 || CONFIG.clusters > get_shards() {
+        return Err(ApiError::InvalidConfig(vec![
+            "Number of clusters must be between 1 and the number of shards".to_owned(),
+        ]));
+    }
+
+    Ok(())
+}
+
+async fn store(conn: &mut redis::aio::Connection, ranges: &[(u64, u64)]) -> ApiResult<()> {
+    let _: () = conn.set(get_key(), simd_json::to_string(ranges)?).await?;
+
+    Ok(())
+}
+
+pub fn get() -> Option<Vec<(u64, u64)>> {
+    RANGES.lock().unwrap().clone()
+}
+
+pub async fn load(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    let stored: Option<String> = conn.get(get_key()).await?;
+    let stored: Option<Vec<(u64, u64)>> =
+        stored.and_then(|mut value| simd_json::from_str(value.as_mut_str()).ok());
+
+    let ranges = match stored {
+        Some(ranges) if is_valid(ranges.as_slice()) => {
+            if ranges.len() as u64 != CONFIG.clusters {
+                warn!(
+                    "Keeping {} clusters from the stored mapping instead of {} (see --migrate-clusters)",
+                    ranges.len(),
+                    CONFIG.clusters
+                );
+            }
+            ranges
+        }
+        stored => {
+            if stored.is_some() {
+                warn!("Stored cluster mapping does not match the shards, replacing it");
+            }
+            validate()?;
+            let ranges = get_default_cluster_ranges();
+            store(conn, ranges.as_slice()).await?;
+            ranges
+        }
+    };
+
+    *RANGES.lock().unwrap() = Some(ranges);
+
+    Ok(())
+}
+
+pub async fn migrate(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    validate()?;
+
+    let ranges = get_default_cluster_ranges();
+    store(conn, ranges.as_slice()).await?;
+
+    info!(
+        "Mapped the shards to {} clusters: {:?}",
+        ranges.len(),
+        ranges
+    );
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use crate::{
+    cache,
+    config::CONFIG,
+    constants::{
+        DEGRADED_COLOR, MEMBER_KEY, MEMORY_DISABLE_PERIOD, MEMORY_INTERVAL, MEMORY_RECOVERY,
+        MESSAGE_KEY, PRESENCE_KEY, RESUME_COLOR,
+    },
+    metrics::{STATE_MEMORY_TIER, STATE_MEMORY_USED},
+    models::ApiResult,
+    utils::{log_discord, publish_event},
+};
+
+use lapin::Channel;
+use redis::InfoDict;
+use simd_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{info, warn};
+
+const TIERS: [&str; 3] = [MESSAGE_KEY, MEMBER_KEY, PRESENCE_KEY];
+
+static TIER: AtomicUsize = AtomicUsize::new(0);
+
+fn is_enabled(kind: &str) -> bool {
+    TIERS
+        .iter()
+        .position(|tier| *tier == kind)
+        .is_none_or(|position| position >= TIER.load(Ordering::Relaxed))
+}
+
+pub fn is_message_cached() -> bool {
+    CONFIG.state_message && is_enabled(MESSAGE_KEY)
+}
+
+pub fn is_member_cached() -> bool {
+    CONFIG.state_member && is_enabled(MEMBER_KEY)
+}
+
+pub fn is_presence_cached() -> bool {
+    CONFIG.state_presence && is_enabled(PRESENCE_KEY)
+}
+
+async fn get_used_memory(conn: &mut redis::aio::Connection) -> ApiResult<u64> {
+    let info: InfoDict = redis::cmd("INFO").arg("memory").query_async(conn).await?;
+
+    Ok(info.get("used_memory").unwrap_or_default())
+}
+
+async fn publish(channel: &Channel, tier: usize, used: u64) -> ApiResult<()> {
+    let data = json!({
+        "disabled": TIERS[..tier].to_vec(),
+        "used": used,
+        "budget": CONFIG.state_memory_budget,
+    });
+
+    publish_event(channel, "CACHE_DEGRADED", data).await
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection, channel: Channel) {
+    if CONFIG.state_memory_budget == 0 {
+        return;
+    }
+
+    let mut disabled_at = Instant::now();
+
+    loop {
+        sleep(Duration::from_millis(MEMORY_INTERVAL as u64)).await;
+
+        let used = match get_used_memory(conn).await {
+            Ok(used) => used,
+            Err(err) => {
+                warn!("Failed to get Redis memory usage: {:?}", err);
+                continue;
+            }
+        };
+
+        STATE_MEMORY_USED.set(used as i64);
+
+        let tier = TIER.load(Ordering::Relaxed);
+
+        if used > CONFIG.state_memory_budget && tier < TIERS.len() {
+            let kind = TIERS[tier];
+            TIER.store(tier + 1, Ordering::Relaxed);
+            STATE_MEMORY_TIER.set(tier as i64 + 1);
+            disabled_at = Instant::now();
+            warn!("Disabled {} cache (memory used: {})", kind, used);
+            log_discord(
+                DEGRADED_COLOR,
+                format!("Disabled {} cache (memory used: {})", kind, used),
+            );
+
+            match cache::evict_kind(conn, kind).await {
+                Ok(evicted) => info!("Evicted {} cached {} keys", evicted, kind),
+                Err(err) => warn!("Failed to evict {} cache: {:?}", kind, err),
+            }
+        } else if (used as f64) < CONFIG.state_memory_budget as f64 * MEMORY_RECOVERY
+            && tier > 0
+            && disabled_at.elapsed() >= Duration::from_millis(MEMORY_DISABLE_PERIOD as u64)
+        {
+            let kind = TIERS[tier - 1];
+            TIER.store(tier - 1, Ordering::Relaxed);
+            STATE_MEMORY_TIER.set(tier as i64 - 1);
+            info!("Enabled {} cache (memory used: {})", kind, used);
+            log_discord(
+                RESUME_COLOR,
+                format!("Enabled {} cache (memory used: {})", kind, used),
+            );
+        } else {
+            continue;
+        }
+
+        if let Err(err) = publish(&channel, TIER.load(Ordering::Relaxed), used).await {
+            warn!("Failed to publish cache degradation notice: {:?}", err);
+        }
+    }
+}
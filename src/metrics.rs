@@ -1,33 +1,37 @@
 use crate::{
-    cache,
+    cache, commands,
     config::CONFIG,
     constants::{
-        CHANNEL_KEY, EMOJI_KEY, GUILD_KEY, KEYS_SUFFIX, MEMBER_KEY, MESSAGE_KEY,
-        METRICS_DUMP_INTERVAL, PRESENCE_KEY, ROLE_KEY, VOICE_KEY,
+        CHANNEL_KEY, DEGRADED_COLOR, EMOJI_KEY, GUILD_KEY, KEYS_SUFFIX, LATENCY_QUANTILES,
+        LATENCY_WINDOW, MEMBER_KEY, MESSAGE_KEY, METRICS_DUMP_INTERVAL, PRESENCE_KEY, RESUME_COLOR,
+        ROLE_KEY, VOICE_KEY,
     },
+    incident,
     models::ApiResult,
+    utils::get_shard_quality,
+    watermark,
 };
 
-use hyper::{
-    header::CONTENT_TYPE,
-    server::Server,
-    service::{make_service_fn, service_fn},
-    Body, Method, Request, Response, StatusCode,
-};
+use hyper::{client::HttpConnector, header::CONTENT_TYPE, Body, Client, Method, Request};
 use lazy_static::lazy_static;
 use prometheus::{
-    register_int_counter_vec, register_int_gauge, register_int_gauge_vec, Encoder, IntCounterVec,
-    IntGauge, IntGaugeVec, TextEncoder,
+    exponential_buckets,
+    proto::{LabelPair, MetricFamily},
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, TextEncoder,
 };
 use std::{
-    collections::HashMap,
-    net::{IpAddr, SocketAddr},
-    str::FromStr,
+    collections::{HashMap, HashSet, VecDeque},
     sync::Arc,
+    time::Instant,
 };
 use tokio::time::{sleep, Duration};
 use tracing::warn;
-use twilight_gateway::{shard::Stage, Cluster};
+use twilight_gateway::{
+    shard::{Information, Stage},
+    Cluster,
+};
 
 lazy_static! {
     pub static ref GATEWAY_EVENTS: IntCounterVec = register_int_counter_vec!(
@@ -36,6 +40,13 @@ lazy_static! {
         &["type", "shard"]
     )
     .unwrap();
+    pub static ref GATEWAY_PAYLOAD_SIZES: HistogramVec = register_histogram_vec!(
+        "gateway_payload_sizes",
+        "Size of the payloads received through the Discord gateway in bytes",
+        &["type"],
+        exponential_buckets(256.0, 4.0, 10).unwrap()
+    )
+    .unwrap();
     pub static ref SHARD_EVENTS: IntCounterVec = register_int_counter_vec!(
         "gateway_shard_events",
         "Discord shard connection events",
@@ -53,18 +64,157 @@ lazy_static! {
         "Number of gateway connections with Discord"
     )
     .unwrap();
+    pub static ref GATEWAY_SHARDS_READY: IntGauge = register_int_gauge!(
+        "gateway_shards_ready",
+        "Number of gateway connections that finished starting up"
+    )
+    .unwrap();
     pub static ref GATEWAY_STATUSES: IntGaugeVec = register_int_gauge_vec!(
         "gateway_statuses",
         "Status of the gateway connections",
-        &["type"]
+        &["cluster", "type"]
     )
     .unwrap();
     pub static ref GATEWAY_LATENCIES: IntGaugeVec = register_int_gauge_vec!(
         "gateway_latencies",
         "API latency with the Discord gateway",
+        &["cluster", "shard"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_LATENCY_QUANTILES: IntGaugeVec = register_int_gauge_vec!(
+        "gateway_latency_quantiles",
+        "API latency percentiles with the Discord gateway",
+        &["cluster", "shard", "quantile"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_QUALITIES: IntGaugeVec = register_int_gauge_vec!(
+        "gateway_qualities",
+        "Connection quality score of the gateway connections",
+        &["cluster", "shard"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_COMMAND_CAPACITY: IntGaugeVec = register_int_gauge_vec!(
+        "gateway_command_capacity",
+        "Number of gateway commands that can still be sent in the current window",
         &["shard"]
     )
     .unwrap();
+    pub static ref GATEWAY_CLUSTERS: IntGaugeVec = register_int_gauge_vec!(
+        "gateway_clusters",
+        "Whether all shards of the gateway cluster are connected",
+        &["cluster"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_ANOMALIES: IntCounterVec = register_int_counter_vec!(
+        "gateway_anomalies",
+        "Event rate anomalies",
+        &["scope", "name"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_SHEDDING: IntGauge = register_int_gauge!(
+        "gateway_shedding",
+        "Whether low priority events are being shed"
+    )
+    .unwrap();
+    pub static ref DISPATCH_BACKPRESSURE_ACTIVE: IntGauge = register_int_gauge!(
+        "dispatch_backpressure_active",
+        "Whether shards are paused due to cache latency"
+    )
+    .unwrap();
+    pub static ref GATEWAY_IDENTIFY_QUEUE: IntGaugeVec = register_int_gauge_vec!(
+        "gateway_identify_queue",
+        "Number of shards waiting to identify in each bucket",
+        &["bucket"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_SESSIONS_REMAINING: IntGauge = register_int_gauge!(
+        "gateway_sessions_remaining",
+        "Number of sessions that can still be started today"
+    )
+    .unwrap();
+    pub static ref GATEWAY_INTENTS_DEGRADED: IntGauge = register_int_gauge!(
+        "gateway_intents_degraded",
+        "Whether the shards are running with the fallback intents"
+    )
+    .unwrap();
+    pub static ref GATEWAY_FAILOVER: IntGauge = register_int_gauge!(
+        "gateway_presence_failover",
+        "Whether the degraded presence is shown"
+    )
+    .unwrap();
+    pub static ref STATE_DRIFT: IntCounterVec = register_int_counter_vec!(
+        "state_drift",
+        "Cached objects found to differ from the REST API",
+        &["type"]
+    )
+    .unwrap();
+    pub static ref STATE_PRUNED_KEYS: IntCounter = register_int_counter!(
+        "state_pruned_keys",
+        "Cached keys removed for guilds the bot is not in"
+    )
+    .unwrap();
+    pub static ref GATEWAY_DEDUPLICATED_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "gateway_deduplicated_events",
+        "Events not published due to being identical to the previous one",
+        &["type"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_PAYLOAD_DIVERGENCES: IntCounterVec = register_int_counter_vec!(
+        "gateway_payload_divergences",
+        "Sampled payloads with fields unknown to the models or failing to deserialize",
+        &["type", "field"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_AGGREGATED_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "gateway_aggregated_events",
+        "Events not published due to being aggregated into a summary",
+        &["type"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_SHED_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "gateway_shed_events",
+        "Events not published due to shedding",
+        &["type"]
+    )
+    .unwrap();
+    pub static ref DISCORD_INCIDENT: IntGauge = register_int_gauge!(
+        "discord_incident",
+        "Whether there is an ongoing incident on the Discord status page"
+    )
+    .unwrap();
+    pub static ref SHARD_LAST_EVENT_AGE: IntGaugeVec = register_int_gauge_vec!(
+        "shard_last_event_age_seconds",
+        "Seconds since the last event of the shard was published",
+        &["shard"]
+    )
+    .unwrap();
+    pub static ref GATEWAY_UNAUTHORIZED: IntCounterVec = register_int_counter_vec!(
+        "gateway_unauthorized",
+        "Control requests rejected by authorization",
+        &["source"]
+    )
+    .unwrap();
+    pub static ref MIRROR_PENDING: IntGauge = register_int_gauge!(
+        "gateway_mirror_pending",
+        "Number of events waiting to be published to the mirror"
+    )
+    .unwrap();
+    pub static ref MIRROR_FAILURES: IntCounter = register_int_counter!(
+        "gateway_mirror_failures",
+        "Number of events that failed to be published to the mirror"
+    )
+    .unwrap();
+    pub static ref WEBHOOK_DROPPED: IntCounterVec = register_int_counter_vec!(
+        "gateway_webhook_dropped",
+        "Number of events dropped because a webhook fell behind",
+        &["url"]
+    )
+    .unwrap();
+    pub static ref RECORD_DROPPED: IntCounter = register_int_counter!(
+        "gateway_record_dropped",
+        "Number of payloads dropped from the recording because the writer fell behind"
+    )
+    .unwrap();
     pub static ref STATE_GUILDS: IntGauge =
         register_int_gauge!("state_guilds", "Number of guilds in state cache").unwrap();
     pub static ref STATE_CHANNELS: IntGauge =
@@ -81,43 +231,29 @@ lazy_static! {
         register_int_gauge!("state_presences", "Number of presences in state cache").unwrap();
     pub static ref STATE_VOICES: IntGauge =
         register_int_gauge!("state_voices", "Number of voices in state cache").unwrap();
-}
-
-async fn serve(req: Request<Body>) -> ApiResult<Response<Body>> {
-    if req.method() == Method::GET && req.uri().path() == "/metrics" {
-        let mut buffer = vec![];
-        let metrics = prometheus::gather();
-
-        let encoder = TextEncoder::new();
-        encoder.encode(metrics.as_slice(), &mut buffer)?;
-
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header(CONTENT_TYPE, encoder.format_type())
-            .body(Body::from(buffer))?)
-    } else if req.method() == Method::GET && req.uri().path() == "/healthcheck" {
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header(CONTENT_TYPE, "application/json")
-            .body(Body::from("{\"status\":\"OK\"}"))?)
-    } else {
-        Ok(Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())?)
-    }
-}
-
-pub async fn run_server() -> ApiResult<()> {
-    let addr = SocketAddr::new(
-        IpAddr::from_str(CONFIG.prometheus_host.as_str())?,
-        CONFIG.prometheus_port as u16,
-    );
-
-    let make_svc = make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(serve)) });
-
-    Server::bind(&addr).serve(make_svc).await?;
-
-    Err(().into())
+    pub static ref STATE_DEADLINE_EXCEEDED: IntCounterVec = register_int_counter_vec!(
+        "state_deadline_exceeded",
+        "Cache updates that exceeded the deadline",
+        &["type"]
+    )
+    .unwrap();
+    pub static ref STATE_DETACHED_UPDATES: IntGauge = register_int_gauge!(
+        "state_detached_updates",
+        "Number of cache updates finishing in the background after the deadline"
+    )
+    .unwrap();
+    pub static ref STATE_MEMORY_USED: IntGauge =
+        register_int_gauge!("state_memory_used", "Bytes of memory used by Redis").unwrap();
+    pub static ref STATE_MEMORY_TIER: IntGauge = register_int_gauge!(
+        "state_memory_tier",
+        "Number of cache tiers disabled due to the memory budget"
+    )
+    .unwrap();
+    pub static ref STATE_DIRTY: IntGauge = register_int_gauge!(
+        "state_dirty",
+        "Number of cache entries not yet written to Redis"
+    )
+    .unwrap();
 }
 
 struct StateStats {
@@ -154,7 +290,56 @@ async fn get_state_stats(conn: &mut redis::aio::Connection) -> ApiResult<StateSt
     })
 }
 
+#[derive(Default)]
+struct LatencyWindow {
+    heartbeats: u32,
+    samples: VecDeque<(Instant, i64)>,
+}
+
+impl LatencyWindow {
+    fn update(&mut self, info: &Information) {
+        let recent = info.latency().recent();
+        let heartbeats = info.latency().heartbeats();
+
+        let new = if heartbeats >= self.heartbeats {
+            (heartbeats - self.heartbeats) as usize
+        } else {
+            heartbeats as usize
+        };
+        self.heartbeats = heartbeats;
+
+        let now = Instant::now();
+        for value in recent.iter().skip(recent.len().saturating_sub(new)) {
+            self.samples.push_back((now, value.as_millis() as i64));
+        }
+
+        while let Some((received, _)) = self.samples.front() {
+            if received.elapsed() < Duration::from_millis(LATENCY_WINDOW as u64) {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    fn get_quantiles(&self) -> Vec<(f64, i64)> {
+        let mut values: Vec<i64> = self.samples.iter().map(|(_, value)| *value).collect();
+        values.sort_unstable();
+
+        LATENCY_QUANTILES
+            .iter()
+            .map(|quantile| {
+                let index = ((values.len() as f64 * quantile).ceil() as usize).saturating_sub(1);
+                (*quantile, values.get(index).copied().unwrap_or_default())
+            })
+            .collect()
+    }
+}
+
 pub async fn run_jobs(conn: &mut redis::aio::Connection, clusters: &[Arc<Cluster>]) {
+    let mut latencies: HashMap<u64, LatencyWindow> = HashMap::new();
+    let mut spikes = HashSet::new();
+    let mut degraded = HashSet::new();
+
     loop {
         GATEWAY_SHARDS.set(
             clusters
@@ -162,37 +347,106 @@ pub async fn run_jobs(conn: &mut redis::aio::Connection, clusters: &[Arc<Cluster
                 .fold(0, |acc, cluster| acc + cluster.shards().len() as i64),
         );
 
-        let mut statuses = HashMap::new();
-        statuses.insert(format!("{}", Stage::Connected), 0);
-        statuses.insert(format!("{}", Stage::Disconnected), 0);
-        statuses.insert(format!("{}", Stage::Handshaking), 0);
-        statuses.insert(format!("{}", Stage::Identifying), 0);
-        statuses.insert(format!("{}", Stage::Resuming), 0);
+        for (index, cluster) in clusters.iter().enumerate() {
+            let cluster_string = index.to_string();
+
+            let mut statuses = HashMap::new();
+            statuses.insert(format!("{}", Stage::Connected), 0);
+            statuses.insert(format!("{}", Stage::Disconnected), 0);
+            statuses.insert(format!("{}", Stage::Handshaking), 0);
+            statuses.insert(format!("{}", Stage::Identifying), 0);
+            statuses.insert(format!("{}", Stage::Resuming), 0);
 
-        for cluster in clusters {
             for shard in cluster.shards() {
                 if let Ok(info) = shard.info() {
+                    let latency = info
+                        .latency()
+                        .recent()
+                        .back()
+                        .map(|value| value.as_millis() as i64)
+                        .unwrap_or_default();
+
                     GATEWAY_LATENCIES
+                        .with_label_values(&[
+                            cluster_string.as_str(),
+                            info.id().to_string().as_str(),
+                        ])
+                        .set(latency);
+
+                    let window = latencies.entry(info.id()).or_default();
+                    window.update(&info);
+
+                    for (quantile, value) in window.get_quantiles() {
+                        GATEWAY_LATENCY_QUANTILES
+                            .with_label_values(&[
+                                cluster_string.as_str(),
+                                info.id().to_string().as_str(),
+                                quantile.to_string().as_str(),
+                            ])
+                            .set(value);
+                    }
+
+                    if latency as u64 > CONFIG.history_latency {
+                        if spikes.insert(info.id()) {
+                            let details = Some(format!("latency: {}ms", latency));
+                            if let Err(err) =
+                                cache::push_history(conn, info.id(), "LatencySpike", details).await
+                            {
+                                warn!("Failed to record shard history: {:?}", err);
+                            }
+                        }
+                    } else {
+                        spikes.remove(&info.id());
+                    }
+
+                    GATEWAY_COMMAND_CAPACITY
                         .with_label_values(&[info.id().to_string().as_str()])
-                        .set(
-                            info.latency()
-                                .recent()
-                                .back()
-                                .map(|value| value.as_millis() as i64)
-                                .unwrap_or_default(),
+                        .set(commands::get_capacity(info.id()) as i64);
+
+                    let quality = get_shard_quality(&info);
+
+                    GATEWAY_QUALITIES
+                        .with_label_values(&[
+                            cluster_string.as_str(),
+                            info.id().to_string().as_str(),
+                        ])
+                        .set(quality);
+
+                    if quality < CONFIG.quality_threshold {
+                        if degraded.insert(info.id()) {
+                            warn!("[Shard {}] Degraded (quality: {})", info.id(), quality);
+                            incident::log_shard(
+                                DEGRADED_COLOR,
+                                format!("[Shard {}] Degraded (quality: {})", info.id(), quality),
+                            );
+                        }
+                    } else if degraded.remove(&info.id()) {
+                        incident::log_shard(
+                            RESUME_COLOR,
+                            format!("[Shard {}] Recovered (quality: {})", info.id(), quality),
                         );
+                    }
 
                     if let Some(count) = statuses.get_mut(&info.stage().to_string()) {
                         *count += 1;
                     }
                 }
             }
-        }
 
-        for (stage, amount) in statuses {
-            GATEWAY_STATUSES
-                .with_label_values(&[stage.as_str()])
-                .set(amount);
+            let connected = statuses
+                .get(&Stage::Connected.to_string())
+                .copied()
+                .unwrap_or_default();
+
+            GATEWAY_CLUSTERS
+                .with_label_values(&[cluster_string.as_str()])
+                .set((connected == cluster.shards().len() as i64) as i64);
+
+            for (stage, amount) in statuses {
+                GATEWAY_STATUSES
+                    .with_label_values(&[cluster_string.as_str(), stage.as_str()])
+                    .set(amount);
+            }
         }
 
         match get_state_stats(conn).await {
@@ -211,6 +465,77 @@ pub async fn run_jobs(conn: &mut redis::aio::Connection, clusters: &[Arc<Cluster
             }
         }
 
+        STATE_DIRTY.set(cache::get_pending_len() as i64);
+
+        for (shard, age) in watermark::get_ages() {
+            SHARD_LAST_EVENT_AGE
+                .with_label_values(&[shard.to_string().as_str()])
+                .set(age as i64);
+        }
+
         sleep(Duration::from_millis(METRICS_DUMP_INTERVAL as u64)).await;
     }
 }
+
+pub fn gather() -> Vec<MetricFamily> {
+    let mut metrics = prometheus::gather();
+
+    if !CONFIG.tenant.is_empty() {
+        for family in metrics.iter_mut() {
+            for metric in family.mut_metric().iter_mut() {
+                let mut label = LabelPair::default();
+                label.set_name("tenant".to_owned());
+                label.set_value(CONFIG.tenant.clone());
+
+                let mut labels = metric.get_label().to_vec();
+                labels.push(label);
+                metric.set_label(labels);
+            }
+        }
+    }
+
+    metrics
+}
+
+async fn push_metrics(client: &Client<HttpConnector>, uri: &str) -> ApiResult<()> {
+    let mut buffer = vec![];
+    let metrics = gather();
+
+    let encoder = TextEncoder::new();
+    encoder.encode(metrics.as_slice(), &mut buffer)?;
+
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri(uri)
+        .header(CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))?;
+
+    let response = client.request(request).await?;
+    if !response.status().is_success() {
+        warn!("Push gateway responded with status {}", response.status());
+    }
+
+    Ok(())
+}
+
+pub async fn run_push() {
+    if CONFIG.pushgateway_url.is_empty() {
+        return;
+    }
+
+    let client = Client::new();
+    let uri = format!(
+        "{}/metrics/job/{}/instance/{}",
+        CONFIG.pushgateway_url.trim_end_matches('/'),
+        CONFIG.pushgateway_job,
+        CONFIG.pushgateway_instance
+    );
+
+    loop {
+        if let Err(err) = push_metrics(&client, uri.as_str()).await {
+            warn!("Failed to push metrics: {:?}", err);
+        }
+
+        sleep(Duration::from_millis(CONFIG.pushgateway_interval)).await;
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    cache,
+    config::CONFIG,
+    constants::{
+        BOT_USER_KEY, CACHE_SCHEMA_VERSION, GUILD_ITEM_KEYS, KEYS_SUFFIX, MEMBER_KEY, MESSAGE_KEY,
+        SCHEMA_KEY, SCHEMA_LOCK_KEY, SCHEMA_LOCK_TTL, SCHEMA_MIGRATE_INTERVAL,
+        SCHEMA_RETRY_INTERVAL,
+    },
+    keys::CacheKey,
+    lock,
+    models::{ApiError, ApiResult},
+};
+
+use lazy_static::lazy_static;
+use redis::AsyncCommands;
+use simd_json::owned::Value;
+use std::{
+    iter,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+struct Migration {
+    version: u64,
+    kind: &'static str,
+    apply: fn(&CacheKey<'_>, &mut Value) -> bool,
+}
+
+const MIGRATIONS: [Migration; 1] = [Migration {
+    version: 1,
+    kind: MEMBER_KEY,
+    apply: set_member_guild_id,
+}];
+
+lazy_static! {
+    static ref LAYOUT: AtomicU64 = AtomicU64::new(CONFIG.state_layout);
+    static ref VERSION: AtomicU64 = AtomicU64::new(CACHE_SCHEMA_VERSION);
+}
+
+fn set_member_guild_id(key: &CacheKey<'_>, value: &mut Value) -> bool {
+    match (value, key.parent) {
+        (Value::Object(object), Some(guild_id)) if !object.contains_key("guild_id") => {
+            object.insert("guild_id".to_owned(), Value::from(guild_id));
+            true
+        }
+        _ => false,
+    }
+}
+
+pub fn get_layout() -> u64 {
+    LAYOUT.load(Ordering::Relaxed)
+}
+
+pub fn is_pending() -> bool {
+    get_layout() != CONFIG.state_layout || VERSION.load(Ordering::Relaxed) < CACHE_SCHEMA_VERSION
+}
+
+pub fn get_legacy_key(key: &str) -> Option<String> {
+    if get_layout() == CONFIG.state_layout {
+        return None;
+    }
+
+    Some(CacheKey::parse(key).legacy_key())
+}
+
+pub fn apply(key: &str, value: &mut Value) -> bool {
+    let version = VERSION.load(Ordering::Relaxed);
+    let key = CacheKey::parse(key);
+
+    let mut changed = false;
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > version && migration.kind == key.kind)
+    {
+        changed |= (migration.apply)(&key, value);
+    }
+
+    changed
+}
+
+async fn read(conn: &mut redis::aio::Connection) -> ApiResult<Option<(u64, u64)>> {
+    let (layout, version): (Option<u64>, Option<u64>) = redis::cmd("HMGET")
+        .arg(SCHEMA_KEY)
+        .arg("layout")
+        .arg("version")
+        .query_async(conn)
+        .await?;
+
+    Ok(layout.zip(version))
+}
+
+async fn detect_layout(conn: &mut redis::aio::Connection) -> ApiResult<u64> {
+    for kind in GUILD_ITEM_KEYS.iter().chain(iter::once(&MESSAGE_KEY)) {
+        let key: Option<String> = conn.srandmember(format!("{}{}", kind, KEYS_SUFFIX)).await?;
+        let key = match key {
+            Some(key) => key,
+            None => continue,
+        };
+
+        let exists: bool = conn.exists(&key).await?;
+        if exists {
+            return Ok(1);
+        }
+
+        if let Some((hash, field)) = CacheKey::parse(&key).hash_field() {
+            let exists: bool = conn.hexists(hash, field).await?;
+            if exists {
+                return Ok(2);
+            }
+        }
+    }
+
+    Ok(1)
+}
+
+pub async fn load(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    let (layout, version) = match read(conn).await? {
+        Some(schema) => schema,
+        None => {
+            let exists: bool = conn.exists(BOT_USER_KEY).await?;
+            if exists {
+                (detect_layout(conn).await?, 0)
+            } else {
+                (CONFIG.state_layout, CACHE_SCHEMA_VERSION)
+            }
+        }
+    };
+
+    if layout > CONFIG.state_layout {
+        return Err(ApiError::InvalidConfig(vec![format!(
+            "Cache uses layout {}, converting back to layout {} is not supported",
+            layout, CONFIG.state_layout
+        )]));
+    }
+
+    if version > CACHE_SCHEMA_VERSION {
+        return Err(ApiError::InvalidConfig(vec![format!(
+            "Cache schema version {} is newer than the supported version {}",
+            version, CACHE_SCHEMA_VERSION
+        )]));
+    }
+
+    LAYOUT.store(layout, Ordering::Relaxed);
+    VERSION.store(version, Ordering::Relaxed);
+
+    if is_pending() {
+        info!(
+            "Cache schema migration pending (layout {} to {}, version {} to {})",
+            layout, CONFIG.state_layout, version, CACHE_SCHEMA_VERSION
+        );
+    } else {
+        save(conn).await?;
+    }
+
+    Ok(())
+}
+
+async fn save(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    let _: () = conn
+        .hset_multiple(
+            SCHEMA_KEY,
+            &[
+                ("layout", CONFIG.state_layout),
+                ("version", CACHE_SCHEMA_VERSION),
+            ],
+        )
+        .await?;
+
+    LAYOUT.store(CONFIG.state_layout, Ordering::Relaxed);
+    VERSION.store(CACHE_SCHEMA_VERSION, Ordering::Relaxed);
+
+    Ok(())
+}
+
+async fn reload(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    if let Some((layout, version)) = read(conn).await? {
+        LAYOUT.store(layout, Ordering::Relaxed);
+        VERSION.store(version, Ordering::Relaxed);
+    }
+
+    Ok(())
+}
+
+pub async fn refresh_lock(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    if lock::refresh_key(conn, SCHEMA_LOCK_KEY, SCHEMA_LOCK_TTL).await? {
+        Ok(())
+    } else {
+        Err(ApiError::MigrationLocked)
+    }
+}
+
+pub async fn migrate(conn: &mut redis::aio::Connection, throttle: Duration) -> ApiResult<u64> {
+    if !lock::try_acquire_key(conn, SCHEMA_LOCK_KEY, SCHEMA_LOCK_TTL).await? {
+        return Err(ApiError::MigrationLocked);
+    }
+
+    let result = match cache::migrate_schema(conn, get_layout(), throttle).await {
+        Ok(migrated) => save(conn).await.map(|_| migrated),
+        Err(err) => Err(err),
+    };
+
+    lock::release_key(conn, SCHEMA_LOCK_KEY).await?;
+
+    result
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection) {
+    if !is_pending() {
+        return;
+    }
+
+    info!("Migrating cache schema in the background");
+
+    loop {
+        match migrate(conn, Duration::from_millis(SCHEMA_MIGRATE_INTERVAL as u64)).await {
+            Ok(migrated) => {
+                info!("Migrated {} cache keys", migrated);
+                return;
+            }
+            Err(ApiError::MigrationLocked) => {
+                if let Err(err) = reload(conn).await {
+                    warn!("Failed to reload cache schema: {:?}", err);
+                }
+                if !is_pending() {
+                    info!("Cache schema was migrated by another instance");
+                    return;
+                }
+            }
+            Err(err) => {
+                warn!("Failed to migrate cache schema: {:?}", err);
+            }
+        }
+
+        sleep(Duration::from_millis(SCHEMA_RETRY_INTERVAL as u64)).await;
+    }
+}
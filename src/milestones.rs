@@ -0,0 +1,70 @@
+use crate::{
+    config::CONFIG,
+    constants::{DEGRADED_COLOR, MILESTONE_COLOR, MILESTONE_INTERVAL},
+    metrics::STATE_GUILDS,
+    startup::get_progress,
+    utils::{log_discord, publish_event},
+};
+
+use lapin::Channel;
+use simd_json::json;
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+pub async fn run_jobs(channel: Channel) {
+    if CONFIG.guild_milestones.is_empty() && CONFIG.guild_drop_percent == 0 {
+        return;
+    }
+
+    let mut previous: Option<u64> = None;
+    let mut peak = 0;
+
+    loop {
+        sleep(Duration::from_millis(MILESTONE_INTERVAL as u64)).await;
+
+        let progress = get_progress();
+        if progress.ready < progress.total {
+            continue;
+        }
+
+        let guilds = STATE_GUILDS.get() as u64;
+        let last = match previous.replace(guilds) {
+            Some(last) => last,
+            None => {
+                peak = guilds;
+                continue;
+            }
+        };
+
+        for milestone in CONFIG.guild_milestones.iter().copied() {
+            if last < milestone && guilds >= milestone {
+                info!("Reached {} guilds", milestone);
+                log_discord(MILESTONE_COLOR, format!("Reached {} guilds", milestone));
+
+                let data = json!({ "milestone": milestone, "guilds": guilds });
+                if let Err(err) = publish_event(&channel, "GATEWAY_GUILD_MILESTONE", data).await {
+                    warn!("Failed to publish guild milestone: {:?}", err);
+                }
+            }
+        }
+
+        peak = peak.max(guilds);
+
+        if CONFIG.guild_drop_percent > 0
+            && guilds < peak * (100 - CONFIG.guild_drop_percent.min(100)) / 100
+        {
+            warn!("Guild count dropped from {} to {}", peak, guilds);
+            log_discord(
+                DEGRADED_COLOR,
+                format!("Guild count dropped from {} to {}", peak, guilds),
+            );
+
+            let data = json!({ "peak": peak, "guilds": guilds });
+            if let Err(err) = publish_event(&channel, "GATEWAY_GUILD_DROP", data).await {
+                warn!("Failed to publish guild drop: {:?}", err);
+            }
+
+            peak = guilds;
+        }
+    }
+}
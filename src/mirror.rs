@@ -0,0 +1,167 @@
+use crate::{
+    config::CONFIG,
+    constants::{EXCHANGE, MIRROR_BUFFER_SIZE, MIRROR_RECONNECT_INTERVAL},
+    metrics::{MIRROR_FAILURES, MIRROR_PENDING},
+    models::{ApiResult, PayloadInfo},
+    utils::{get_envelopes, get_properties},
+};
+
+use lapin::{
+    options::{BasicPublishOptions, ExchangeDeclareOptions},
+    types::FieldTable,
+    Channel, Connection, ConnectionProperties, ExchangeKind,
+};
+use lazy_static::lazy_static;
+use simd_json::ValueAccess;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::{sleep, Duration},
+};
+use tracing::{info, warn};
+
+lazy_static! {
+    static ref COUNTER: AtomicU64 = AtomicU64::new(0);
+}
+
+#[derive(Clone, Debug)]
+pub struct Mirror(Sender<(String, String, Vec<u8>)>);
+
+impl Mirror {
+    pub fn publish(&self, exchange: &str, kind: &str, payload: Vec<u8>) {
+        match self
+            .0
+            .try_send((get_exchange(exchange).to_owned(), kind.to_owned(), payload))
+        {
+            Ok(()) => MIRROR_PENDING.inc(),
+            Err(_) => MIRROR_FAILURES.inc(),
+        }
+    }
+}
+
+fn get_exchange(exchange: &str) -> &str {
+    if exchange == EXCHANGE {
+        CONFIG.mirror_exchange.as_str()
+    } else {
+        exchange
+    }
+}
+
+pub fn is_mirrored(payload: &PayloadInfo) -> bool {
+    if !CONFIG.mirror_guilds.is_empty() {
+        let guild_id = payload
+            .d
+            .get_str("guild_id")
+            .and_then(|guild_id| guild_id.parse().ok());
+
+        match guild_id {
+            Some(guild_id) if CONFIG.mirror_guilds.contains(&guild_id) => {}
+            _ => return false,
+        }
+    }
+
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed) as f64;
+    let rate = CONFIG.mirror_sample_rate.min(100.0) / 100.0;
+
+    ((count + 1.0) * rate).floor() > (count * rate).floor()
+}
+
+async fn open() -> ApiResult<(Connection, Channel)> {
+    let amqp = Connection::connect(
+        format!(
+            "amqp://{}:{}@{}:{}/{}",
+            CONFIG.mirror_rabbit_username,
+            CONFIG.mirror_rabbit_password,
+            CONFIG.mirror_rabbit_host,
+            CONFIG.mirror_rabbit_port,
+            CONFIG.mirror_rabbit_vhost.replace('/', "%2f")
+        )
+        .as_str(),
+        ConnectionProperties::default(),
+    )
+    .await?;
+
+    let channel = amqp.create_channel().await?;
+
+    for (exchange, _) in get_envelopes() {
+        channel
+            .exchange_declare(
+                get_exchange(exchange.as_str()),
+                ExchangeKind::Topic,
+                ExchangeDeclareOptions {
+                    passive: false,
+                    durable: true,
+                    auto_delete: false,
+                    internal: false,
+                    nowait: false,
+                },
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    Ok((amqp, channel))
+}
+
+async fn run(mut conn: Option<(Connection, Channel)>, mut rx: Receiver<(String, String, Vec<u8>)>) {
+    while let Some((exchange, kind, payload)) = rx.recv().await {
+        MIRROR_PENDING.dec();
+
+        let connected = conn
+            .as_ref()
+            .is_some_and(|(_, channel)| channel.status().connected());
+        if !connected {
+            conn = None;
+            match open().await {
+                Ok(opened) => {
+                    info!("Reconnected to the mirror");
+                    conn = Some(opened);
+                }
+                Err(err) => {
+                    MIRROR_FAILURES.inc();
+                    warn!("Failed to reconnect to the mirror: {:?}", err);
+                    sleep(Duration::from_millis(MIRROR_RECONNECT_INTERVAL as u64)).await;
+                    continue;
+                }
+            }
+        }
+
+        let channel = match &conn {
+            Some((_, channel)) => channel,
+            None => continue,
+        };
+
+        let result = channel
+            .basic_publish(
+                exchange.as_str(),
+                kind.as_str(),
+                BasicPublishOptions::default(),
+                &payload,
+                get_properties(payload.as_slice()),
+            )
+            .await;
+
+        if let Err(err) = result {
+            MIRROR_FAILURES.inc();
+            warn!("Failed to publish event to mirror: {:?}", err);
+        }
+    }
+}
+
+pub async fn connect() -> ApiResult<Option<Mirror>> {
+    if CONFIG.mirror_rabbit_host.is_empty() {
+        return Ok(None);
+    }
+
+    let conn = open().await?;
+
+    info!(
+        "Mirroring events to {} (exchange: {})",
+        CONFIG.mirror_rabbit_host, CONFIG.mirror_exchange
+    );
+
+    let (tx, rx) = mpsc::channel(MIRROR_BUFFER_SIZE);
+    tokio::spawn(run(Some(conn), rx));
+
+    Ok(Some(Mirror(tx)))
+}
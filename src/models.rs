@@ -1,11 +1,19 @@
+use crate::constants::{
+    CHANNEL_KEY, EMOJI_KEY, MEMBER_KEY, MESSAGE_KEY, PRESENCE_KEY, ROLE_KEY, VOICE_KEY,
+};
+
+pub use crate::delivery::{
+    DeliveryInfo, DeliveryOpcode, DeliveryPriority, DeliveryResult, TapInfo,
+};
+
 use hyper::{http::Error as HyperHTTPError, Error as HyperError};
 use lapin::Error as LapinError;
 use prometheus::Error as PrometheusError;
 use redis::RedisError;
 use serde::{de::Error as SerdeDeError, Deserialize, Deserializer, Serialize, Serializer};
-use serde_repr::{Deserialize_repr, Serialize_repr};
-use simd_json::{owned::Value, Error as SimdJsonError};
+use simd_json::{owned::Value, Error as SimdJsonError, ValueAccess};
 use std::{
+    collections::{BTreeMap, HashMap},
     env::VarError,
     error::Error,
     fmt::{self, Display, Formatter},
@@ -15,11 +23,25 @@ use std::{
     ops::{Add, Sub},
 };
 use time::{format_description, Duration, OffsetDateTime};
+use tokio::time::error::Elapsed;
+use tokio_rustls::rustls::Error as RustlsError;
 use twilight_gateway::{cluster::ClusterStartError, shard::LargeThresholdError};
+use twilight_http::{response::DeserializeBodyError, Error as TwilightHttpError};
 use twilight_model::{
-    channel::Channel,
-    gateway::{payload::incoming::GuildCreate, presence::Presence, OpCode},
+    channel::{Channel, ChannelType},
+    gateway::{
+        payload::{
+            incoming::GuildCreate,
+            outgoing::{RequestGuildMembers, Resume, UpdatePresence, UpdateVoiceState},
+        },
+        presence::Presence,
+        OpCode,
+    },
     guild::{Emoji, Member, Role},
+    id::{
+        marker::{ChannelMarker, GuildMarker, RoleMarker},
+        Id,
+    },
     voice::VoiceState,
 };
 
@@ -103,41 +125,363 @@ impl<'de> Deserialize<'de> for FormattedDateTime {
     }
 }
 
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DriftInfo {
+    pub channels: u64,
+    pub roles: u64,
+    pub members: u64,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct SessionInfo {
     pub session_id: String,
     pub sequence: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume_gateway_url: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct StatusInfo {
+    pub cluster: u64,
     pub shard: u64,
     pub status: String,
     pub latency: u64,
     pub last_ack: FormattedDateTime,
+    pub updated: FormattedDateTime,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CaptureInfo {
+    pub kind: String,
+    pub count: u64,
+    pub file: String,
+}
+
+#[cfg(feature = "chaos")]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChaosInfo {
+    #[serde(default)]
+    pub redis_latency: u64,
+    #[serde(default)]
+    pub publish_failure_rate: f64,
+    #[serde(default)]
+    pub disconnect: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HistoryInfo {
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    pub timestamp: FormattedDateTime,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PayloadInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<u64>,
     pub op: OpCode,
+    #[serde(default, skip_serializing)]
+    pub s: Option<u64>,
     pub t: Option<String>,
     pub d: Value,
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub old: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup: Option<bool>,
 }
 
-#[derive(Clone, Debug, Deserialize_repr, Serialize_repr)]
-#[repr(u8)]
-pub enum DeliveryOpcode {
-    Send,
-    Reconnect,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StatusIncident {
+    pub name: String,
+    pub impact: String,
+    #[serde(default)]
+    pub shortlink: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct DeliveryInfo {
-    pub op: DeliveryOpcode,
+pub struct StatusIncidents {
+    pub incidents: Vec<StatusIncident>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GuildShardInfo {
+    pub guild_id: u64,
     pub shard: u64,
-    pub data: Option<Value>,
+    pub cluster: Option<u64>,
+    pub local: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct GuildExport {
+    pub guild: Option<Value>,
+    pub channels: Vec<Value>,
+    pub roles: Vec<Value>,
+    pub emojis: Vec<Value>,
+    pub members: Vec<Value>,
+    pub presences: Vec<Value>,
+    pub voices: Vec<Value>,
+    pub messages: Vec<Value>,
+}
+
+impl GuildExport {
+    pub fn push(&mut self, kind: &str, value: Value) {
+        match kind {
+            CHANNEL_KEY => self.channels.push(value),
+            ROLE_KEY => self.roles.push(value),
+            EMOJI_KEY => self.emojis.push(value),
+            MEMBER_KEY => self.members.push(value),
+            PRESENCE_KEY => self.presences.push(value),
+            VOICE_KEY => self.voices.push(value),
+            MESSAGE_KEY => self.messages.push(value),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PurgeInfo {
+    pub keys: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IdentifyBucketInfo {
+    pub bucket: u64,
+    pub depth: u64,
+    pub shards: Vec<IdentifyWaitInfo>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IdentifyWaitInfo {
+    pub shard: u64,
+    pub position: u64,
+    pub estimate: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MemberRequestInfo {
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u64>,
+    #[serde(default)]
+    pub user_ids: Vec<u64>,
+    #[serde(default)]
+    pub presences: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MemberRequestStatus {
+    pub nonce: String,
+    pub guild_id: String,
+    pub chunks: u64,
+    pub chunk_count: u64,
+    pub members: u64,
+    pub not_found: Vec<String>,
+    pub complete: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GuildLeaveInfo {
+    #[serde(default)]
+    pub guild_ids: Vec<Id<GuildMarker>>,
+    #[serde(default)]
+    pub max_members: Option<u64>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct GuildLeaveStatus {
+    pub nonce: String,
+    pub total: u64,
+    pub left: u64,
+    pub failed: Vec<String>,
+    pub complete: bool,
+    pub cancelled: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub guild_ids: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GuildMigrationInfo {
+    #[serde(default)]
+    pub from: String,
+    #[serde(default)]
+    pub to: String,
+    #[serde(default)]
+    pub remove: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RoleHierarchyInfo {
+    pub role_id: Id<RoleMarker>,
+    pub position: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ListenerInfo {
+    pub addr: String,
+    #[serde(default)]
+    pub endpoints: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FirehoseTokenInfo {
+    pub token: String,
+    pub expires: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ModerationInfo {
+    pub banned: bool,
+    pub timeout_until: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MemberActionInfo {
+    pub allowed: bool,
+    pub owner: bool,
+    pub actor: RoleHierarchyInfo,
+    pub target: RoleHierarchyInfo,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChannelTreeEntry {
+    pub kind: ChannelType,
+    pub position: i64,
+    pub parent_id: Option<Id<ChannelMarker>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ChannelTreeNode {
+    pub channel_id: Id<ChannelMarker>,
+    pub kind: ChannelType,
+    pub position: i64,
+    pub children: Vec<ChannelTreeNode>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WatermarkInfo {
+    pub watermark: u64,
+    pub shards: HashMap<String, ShardWatermark>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ShardWatermark {
+    pub sequence: Option<u64>,
+    pub published: Option<u64>,
+    pub pending: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ClusterInfo {
+    pub cluster: u64,
+    pub shard_start: u64,
+    pub shard_end: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ConfigInfo {
+    pub clusters: Vec<ClusterInfo>,
+    pub intents: String,
+    pub event_types: String,
+    pub state_events: Vec<&'static str>,
+    pub config: Value,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SchemaField {
+    pub name: &'static str,
+    pub kind: &'static str,
+    pub optional: bool,
+    pub description: &'static str,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum JsonSchemaType {
+    Required(&'static str),
+    Nullable([&'static str; 2]),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct JsonSchemaProperty {
+    #[serde(rename = "type")]
+    pub kind: JsonSchemaType,
+    pub description: &'static str,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct JsonSchema {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    #[serde(rename = "$id")]
+    pub id: String,
+    pub title: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub properties: BTreeMap<&'static str, JsonSchemaProperty>,
+    pub required: Vec<&'static str>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RegistryEntry {
+    pub kind: &'static str,
+    pub name: &'static str,
+    pub pattern: &'static str,
+    pub tenant: bool,
+    pub description: &'static str,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RegistryInfo {
+    pub tenant: String,
+    pub entries: Vec<RegistryEntry>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SchemaInfo {
+    pub version: u64,
+    pub exchange: &'static str,
+    pub envelope: Vec<SchemaField>,
+    pub intents: u64,
+    pub state_events: Vec<&'static str>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum GatewayCommand {
+    RequestGuildMembers(RequestGuildMembers),
+    UpdatePresence(UpdatePresence),
+    UpdateVoiceState(UpdateVoiceState),
+    Resume(Resume),
+}
+
+impl GatewayCommand {
+    pub fn from_value(value: &Value) -> ApiResult<Self> {
+        let op = value
+            .get_u64("op")
+            .ok_or_else(|| ApiError::InvalidCommand("missing gateway opcode".to_owned()))?;
+
+        let mut bytes = simd_json::to_vec(value)?;
+        let command = match op {
+            3 => Self::UpdatePresence(simd_json::from_slice(bytes.as_mut_slice())?),
+            4 => Self::UpdateVoiceState(simd_json::from_slice(bytes.as_mut_slice())?),
+            6 => Self::Resume(simd_json::from_slice(bytes.as_mut_slice())?),
+            8 => Self::RequestGuildMembers(simd_json::from_slice(bytes.as_mut_slice())?),
+            _ => {
+                return Err(ApiError::InvalidCommand(format!(
+                    "unsupported gateway opcode {}",
+                    op
+                )))
+            }
+        };
+
+        Ok(command)
+    }
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -164,12 +508,24 @@ pub enum ApiError {
     ParseInt(ParseIntError),
     Lapin(LapinError),
     ClusterStart(ClusterStartError),
+    ClusterSend(String),
+    InvalidShard(u64),
+    InvalidCommand(String),
     LargeThreshold(LargeThresholdError),
     Hyper(HyperError),
     HyperHttp(HyperHTTPError),
     AddrParse(AddrParseError),
     Prometheus(PrometheusError),
     Io(IoError),
+    Rustls(RustlsError),
+    Timeout(Elapsed),
+    TwilightHttp(Box<TwilightHttpError>),
+    DeserializeBody(DeserializeBodyError),
+    InvalidConfig(Vec<String>),
+    ShardsLocked(Vec<u64>),
+    InvalidRecording(String),
+    TakeoverTimeout,
+    MigrationLocked,
 }
 
 impl Error for ApiError {}
@@ -257,3 +613,27 @@ impl From<IoError> for ApiError {
         Self::Io(err)
     }
 }
+
+impl From<RustlsError> for ApiError {
+    fn from(err: RustlsError) -> Self {
+        Self::Rustls(err)
+    }
+}
+
+impl From<Elapsed> for ApiError {
+    fn from(err: Elapsed) -> Self {
+        Self::Timeout(err)
+    }
+}
+
+impl From<TwilightHttpError> for ApiError {
+    fn from(err: TwilightHttpError) -> Self {
+        Self::TwilightHttp(Box::new(err))
+    }
+}
+
+impl From<DeserializeBodyError> for ApiError {
+    fn from(err: DeserializeBodyError) -> Self {
+        Self::DeserializeBody(err)
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    constants::OUTAGES_KEY,
+    models::ApiResult,
+    utils::{get_tenant_key, publish_event},
+};
+
+use lapin::Channel;
+use redis::AsyncCommands;
+use simd_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+use twilight_gateway::Event;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+async fn start(
+    conn: &mut redis::aio::Connection,
+    channel: &Channel,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<()> {
+    let started_at = now();
+    let created: bool = conn
+        .hset_nx(get_tenant_key(OUTAGES_KEY), guild_id.get(), started_at)
+        .await?;
+
+    if created {
+        let data = json!({
+            "guild_id": guild_id.to_string(),
+            "started_at": started_at,
+        });
+        publish_event(channel, "GUILD_OUTAGE_START", data).await?;
+    }
+
+    Ok(())
+}
+
+async fn end(
+    conn: &mut redis::aio::Connection,
+    channel: Option<&Channel>,
+    guild_id: Id<GuildMarker>,
+) -> ApiResult<bool> {
+    let started_at: Option<u64> = conn
+        .hget(get_tenant_key(OUTAGES_KEY), guild_id.get())
+        .await?;
+    let started_at = match started_at {
+        Some(started_at) => started_at,
+        None => return Ok(false),
+    };
+
+    let _: () = conn
+        .hdel(get_tenant_key(OUTAGES_KEY), guild_id.get())
+        .await?;
+
+    if let Some(channel) = channel {
+        let data = json!({
+            "guild_id": guild_id.to_string(),
+            "started_at": started_at,
+            "duration": now().saturating_sub(started_at),
+        });
+        publish_event(channel, "GUILD_OUTAGE_END", data).await?;
+    }
+
+    Ok(true)
+}
+
+pub async fn update(conn: &mut redis::aio::Connection, channel: &Channel, event: &Event) -> bool {
+    let result = match event {
+        Event::GuildCreate(data) if !data.unavailable => end(conn, Some(channel), data.id).await,
+        Event::GuildDelete(data) if data.unavailable => {
+            start(conn, channel, data.id).await.map(|_| false)
+        }
+        Event::GuildDelete(data) => end(conn, None, data.id).await.map(|_| false),
+        Event::UnavailableGuild(data) => start(conn, channel, data.id).await.map(|_| false),
+        _ => Ok(false),
+    };
+
+    result.unwrap_or_else(|err| {
+        warn!("Failed to update guild outage: {:?}", err);
+        false
+    })
+}
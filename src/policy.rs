@@ -0,0 +1,127 @@
+use crate::{
+    cache,
+    config::CONFIG,
+    constants::{MEMBER_ACTIVITY_KEY, POLICY_INTERVAL},
+    models::{ApiResult, PayloadInfo},
+};
+
+use lazy_static::lazy_static;
+use redis::AsyncCommands;
+use simd_json::ValueAccess;
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+use twilight_model::id::Id;
+
+lazy_static! {
+    static ref COUNTERS: Mutex<HashMap<u64, u64>> = Mutex::new(HashMap::new());
+    static ref ACTIVE: Mutex<HashSet<u64>> = Mutex::new(HashSet::new());
+}
+
+fn get_hour() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 3600
+}
+
+pub fn is_member_cached(guild_id: u64) -> bool {
+    CONFIG.member_activity_threshold == 0 || ACTIVE.lock().unwrap().contains(&guild_id)
+}
+
+pub fn record(kind: &str, payload: &PayloadInfo) {
+    if CONFIG.member_activity_threshold == 0
+        || !matches!(kind, "MESSAGE_CREATE" | "INTERACTION_CREATE")
+    {
+        return;
+    }
+
+    if let Some(guild_id) = payload
+        .d
+        .get_str("guild_id")
+        .and_then(|guild_id| guild_id.parse().ok())
+    {
+        *COUNTERS.lock().unwrap().entry(guild_id).or_insert(0) += 1;
+    }
+}
+
+async fn flush(conn: &mut redis::aio::Connection, hour: u64) -> ApiResult<()> {
+    let counters = COUNTERS.lock().unwrap().clone();
+    if counters.is_empty() {
+        return Ok(());
+    }
+
+    let key = format!("{}:{}", MEMBER_ACTIVITY_KEY, hour);
+
+    let mut pipe = redis::pipe();
+    for (guild_id, count) in counters.iter() {
+        pipe.zincr(&key, guild_id, count).ignore();
+    }
+    pipe.expire(&key, 7200).ignore();
+
+    let _: () = pipe.query_async(conn).await?;
+
+    let mut current = COUNTERS.lock().unwrap();
+    for (guild_id, count) in counters {
+        if let Some(value) = current.get_mut(&guild_id) {
+            *value -= count;
+            if *value == 0 {
+                current.remove(&guild_id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn refresh(conn: &mut redis::aio::Connection, hour: u64) -> ApiResult<()> {
+    let mut active = HashSet::new();
+
+    for hour in [hour.saturating_sub(1), hour] {
+        let guilds: Vec<u64> = conn
+            .zrangebyscore(
+                format!("{}:{}", MEMBER_ACTIVITY_KEY, hour),
+                CONFIG.member_activity_threshold,
+                "+inf",
+            )
+            .await?;
+        active.extend(guilds);
+    }
+
+    let old = mem::replace(&mut *ACTIVE.lock().unwrap(), active.clone());
+
+    for guild_id in old.difference(&active) {
+        if let Some(guild_id) = Id::new_checked(*guild_id) {
+            let evicted = cache::evict_members(conn, guild_id).await?;
+            info!("Evicted {} members of inactive guild {}", evicted, guild_id);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection) {
+    if CONFIG.member_activity_threshold == 0 {
+        return;
+    }
+
+    loop {
+        let hour = get_hour();
+
+        if let Err(err) = flush(conn, hour).await {
+            warn!("Failed to flush member activity: {:?}", err);
+        }
+
+        if let Err(err) = refresh(conn, hour).await {
+            warn!("Failed to refresh active guilds: {:?}", err);
+        }
+
+        sleep(Duration::from_millis(POLICY_INTERVAL as u64)).await;
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{
+    cache,
+    config::CONFIG,
+    constants::{GUILD_KEY, KEYS_SUFFIX},
+    keys::CacheKey,
+    metrics::STATE_PRUNED_KEYS,
+    models::{ApiResult, PayloadInfo},
+    utils::get_guild_shard,
+};
+
+use lazy_static::lazy_static;
+use redis::AsyncCommands;
+use simd_json::ValueAccess;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+use twilight_model::id::Id;
+
+lazy_static! {
+    static ref GUILDS: Mutex<HashMap<u64, HashSet<u64>>> = Mutex::new(HashMap::new());
+}
+
+fn get_id(value: &simd_json::owned::Value, field: &str) -> Option<u64> {
+    value.get_str(field).and_then(|id| id.parse().ok())
+}
+
+pub fn record(shard: u64, kind: &str, payload: &PayloadInfo) {
+    if CONFIG.prune_interval == 0 {
+        return;
+    }
+
+    let mut guilds = GUILDS.lock().unwrap();
+
+    match kind {
+        "READY" => {
+            let ids = payload
+                .d
+                .get_array("guilds")
+                .map(|guilds| {
+                    guilds
+                        .iter()
+                        .filter_map(|guild| get_id(guild, "id"))
+                        .collect()
+                })
+                .unwrap_or_default();
+            guilds.insert(shard, ids);
+        }
+        "GUILD_CREATE" => {
+            if let (Some(id), Some(shard_guilds)) =
+                (get_id(&payload.d, "id"), guilds.get_mut(&shard))
+            {
+                shard_guilds.insert(id);
+            }
+        }
+        "GUILD_DELETE" if payload.d.get_bool("unavailable") != Some(true) => {
+            if let (Some(id), Some(shard_guilds)) =
+                (get_id(&payload.d, "id"), guilds.get_mut(&shard))
+            {
+                shard_guilds.remove(&id);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_known(guild_id: u64) -> Option<bool> {
+    let shard = get_guild_shard(guild_id);
+    if shard < CONFIG.shards_start || shard > CONFIG.shards_end {
+        return None;
+    }
+
+    GUILDS
+        .lock()
+        .unwrap()
+        .get(&shard)
+        .map(|guilds| guilds.contains(&guild_id))
+}
+
+async fn prune(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    let keys: Vec<String> = conn
+        .smembers(format!("{}{}", GUILD_KEY, KEYS_SUFFIX))
+        .await?;
+
+    let mut guilds = 0;
+    let mut pruned = 0;
+
+    for key in keys {
+        let guild_id = match CacheKey::parse(&key)
+            .id
+            .and_then(|id| id.parse().ok())
+            .and_then(Id::new_checked)
+        {
+            Some(guild_id) => guild_id,
+            None => continue,
+        };
+
+        if is_known(guild_id.get()) != Some(false) {
+            continue;
+        }
+
+        let info = cache::purge_guild(conn, guild_id).await?;
+        guilds += 1;
+        pruned += info.keys;
+    }
+
+    if guilds > 0 {
+        STATE_PRUNED_KEYS.inc_by(pruned);
+        info!(
+            "Pruned {} keys of {} guilds the bot is not in",
+            pruned, guilds
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection) {
+    if !CONFIG.state_enabled || CONFIG.prune_interval == 0 {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(CONFIG.prune_interval)).await;
+
+        if let Err(err) = prune(conn).await {
+            warn!("Failed to prune guilds: {:?}", err);
+        }
+    }
+}
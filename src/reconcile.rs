@@ -0,0 +1,101 @@
+use crate::{
+    cache,
+    config::CONFIG,
+    constants::{GUILD_KEY, KEYS_SUFFIX},
+    keys::CacheKey,
+    metrics::STATE_DRIFT,
+    models::ApiResult,
+    rest::{self, CLIENT},
+};
+
+use redis::AsyncCommands;
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+async fn reconcile(conn: &mut redis::aio::Connection, guild_id: Id<GuildMarker>) -> ApiResult<()> {
+    rest::wait().await;
+    let channels = CLIENT
+        .guild_channels(guild_id)
+        .exec()
+        .await?
+        .models()
+        .await?;
+
+    rest::wait().await;
+    let roles = CLIENT.roles(guild_id).exec().await?.models().await?;
+
+    rest::wait().await;
+    let guild = CLIENT
+        .guild(guild_id)
+        .with_counts(true)
+        .exec()
+        .await?
+        .model()
+        .await?;
+
+    let drift = cache::reconcile_guild(
+        conn,
+        guild_id,
+        channels,
+        roles,
+        guild.approximate_member_count,
+    )
+    .await?;
+
+    STATE_DRIFT
+        .with_label_values(&["channel"])
+        .inc_by(drift.channels);
+    STATE_DRIFT.with_label_values(&["role"]).inc_by(drift.roles);
+    STATE_DRIFT
+        .with_label_values(&["member_count"])
+        .inc_by(drift.members);
+
+    if drift.channels > 0 || drift.roles > 0 {
+        info!(
+            "Repaired guild {} ({} channels and {} roles differed)",
+            guild_id, drift.channels, drift.roles
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection) {
+    if !CONFIG.state_enabled || CONFIG.reconcile_interval == 0 {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(CONFIG.reconcile_interval)).await;
+
+        let keys: Vec<String> = match conn
+            .srandmember_multiple(
+                format!("{}{}", GUILD_KEY, KEYS_SUFFIX),
+                CONFIG.reconcile_sample as usize,
+            )
+            .await
+        {
+            Ok(keys) => keys,
+            Err(err) => {
+                warn!("Failed to sample guilds: {:?}", err);
+                continue;
+            }
+        };
+
+        for key in keys {
+            let guild_id = match CacheKey::parse(&key)
+                .id
+                .and_then(|id| id.parse().ok())
+                .and_then(Id::new_checked)
+            {
+                Some(guild_id) => guild_id,
+                None => continue,
+            };
+
+            if let Err(err) = reconcile(conn, guild_id).await {
+                warn!("Failed to reconcile guild {}: {:?}", guild_id, err);
+            }
+        }
+    }
+}
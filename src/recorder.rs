@@ -0,0 +1,231 @@
+use crate::{
+    config::CONFIG,
+    constants::{RECORD_BUFFER_SIZE, RECORD_FLUSH_INTERVAL, REPLAY_BUFFER_SIZE},
+    metrics::RECORD_DROPPED,
+    models::{ApiError, ApiResult},
+};
+
+use futures_util::{Stream, StreamExt};
+use lazy_static::lazy_static;
+use serde::de::DeserializeSeed;
+use simd_json::ValueAccess;
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::{sleep, Duration},
+};
+use tracing::{info, warn};
+use twilight_gateway::{Cluster, Event};
+use twilight_model::gateway::event::{shard::Payload, GatewayEventDeserializer};
+
+type RecordChannel = (Sender<Vec<u8>>, Mutex<Option<Receiver<Vec<u8>>>>);
+
+lazy_static! {
+    static ref RECORDING: RecordChannel = {
+        let (tx, rx) = mpsc::channel(RECORD_BUFFER_SIZE);
+        (tx, Mutex::new(Some(rx)))
+    };
+}
+
+static REPLAYING: AtomicBool = AtomicBool::new(false);
+
+pub struct ReplayStream(Receiver<(u64, Event)>);
+
+impl Stream for ReplayStream {
+    type Item = (u64, Event);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn write(path: &Path, data: &[u8]) -> ApiResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(data)?;
+
+    Ok(())
+}
+
+pub fn is_replaying() -> bool {
+    REPLAYING.load(Ordering::Relaxed)
+}
+
+pub fn record(
+    events: impl Stream<Item = (u64, Event)> + Send + Sync + Unpin + 'static,
+) -> impl Stream<Item = (u64, Event)> + Send + Sync + Unpin + 'static {
+    events.map(|(shard, event)| {
+        if let Event::ShardPayload(data) = &event {
+            if !CONFIG.record_path.is_empty() {
+                let mut line =
+                    format!("{{\"shard\":{},\"timestamp\":{},\"payload\":", shard, now())
+                        .into_bytes();
+                line.extend_from_slice(data.bytes.as_slice());
+                line.extend_from_slice(b"}\n");
+
+                if RECORDING.0.try_send(line).is_err() {
+                    RECORD_DROPPED.inc();
+                }
+            }
+        }
+
+        (shard, event)
+    })
+}
+
+pub async fn run_jobs() {
+    if CONFIG.record_path.is_empty() {
+        return;
+    }
+
+    let mut receiver = match RECORDING.1.lock().unwrap().take() {
+        Some(receiver) => receiver,
+        None => return,
+    };
+    let path = PathBuf::from(CONFIG.record_path.as_str());
+
+    loop {
+        sleep(Duration::from_millis(RECORD_FLUSH_INTERVAL as u64)).await;
+
+        let mut buffer = vec![];
+        while let Ok(line) = receiver.try_recv() {
+            buffer.extend_from_slice(line.as_slice());
+        }
+
+        if buffer.is_empty() {
+            continue;
+        }
+
+        let path = path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            if let Err(err) = write(path.as_path(), buffer.as_slice()) {
+                warn!("Failed to write recording to {}: {:?}", path.display(), err);
+            }
+        })
+        .await;
+
+        if let Err(err) = result {
+            warn!("Failed to write recording: {:?}", err);
+        }
+    }
+}
+
+fn parse(bytes: &[u8]) -> ApiResult<Option<Event>> {
+    let json = String::from_utf8_lossy(bytes);
+    let deserializer = match GatewayEventDeserializer::from_json(json.as_ref()) {
+        Some(deserializer) => deserializer,
+        None => return Ok(None),
+    };
+
+    let mut bytes = bytes.to_vec();
+    let mut json_deserializer = simd_json::Deserializer::from_slice(bytes.as_mut_slice())?;
+    let event = deserializer
+        .deserialize(&mut json_deserializer)
+        .map_err(|err| ApiError::InvalidRecording(format!("{}", err)))?;
+
+    Ok(Some(event.into()))
+}
+
+pub fn replay(
+    reader: impl BufRead + Send + 'static,
+    clusters: &[Arc<Cluster>],
+) -> Vec<ReplayStream> {
+    REPLAYING.store(true, Ordering::Relaxed);
+
+    let (senders, streams): (Vec<_>, Vec<_>) = clusters
+        .iter()
+        .map(|_| {
+            let (tx, rx) = mpsc::channel(REPLAY_BUFFER_SIZE);
+            (tx, ReplayStream(rx))
+        })
+        .unzip();
+
+    let clusters = clusters.to_vec();
+    tokio::task::spawn_blocking(move || {
+        match send_all(reader, clusters.as_slice(), senders.as_slice()) {
+            Ok(count) => info!("Replayed {} payloads", count),
+            Err(err) => warn!("Failed to replay recording: {:?}", err),
+        }
+    });
+
+    streams
+}
+
+fn send_all(
+    reader: impl BufRead,
+    clusters: &[Arc<Cluster>],
+    senders: &[Sender<(u64, Event)>],
+) -> ApiResult<u64> {
+    let mut count = 0;
+
+    for line in reader.lines() {
+        let mut line = line?.into_bytes();
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        let value = simd_json::to_owned_value(line.as_mut_slice())?;
+
+        let shard = value
+            .get_u64("shard")
+            .ok_or_else(|| ApiError::InvalidRecording("Missing shard".to_owned()))?;
+        let bytes = simd_json::to_vec(
+            value
+                .get("payload")
+                .ok_or_else(|| ApiError::InvalidRecording("Missing payload".to_owned()))?,
+        )?;
+
+        let sender = match clusters
+            .iter()
+            .position(|cluster| cluster.shard(shard).is_some())
+        {
+            Some(index) => &senders[index],
+            None => {
+                warn!(
+                    "Skipping recorded payload of shard {} not run by any cluster",
+                    shard
+                );
+                continue;
+            }
+        };
+
+        let event = parse(bytes.as_slice())?;
+
+        if sender
+            .blocking_send((shard, Event::ShardPayload(Payload { bytes })))
+            .is_err()
+        {
+            break;
+        }
+        if let Some(event) = event {
+            if sender.blocking_send((shard, event)).is_err() {
+                break;
+            }
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
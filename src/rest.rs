@@ -0,0 +1,59 @@
+use crate::{
+    config::CONFIG,
+    constants::{RATELIMIT_KEY, RATELIMIT_SCRIPT_SOURCE},
+    models::ApiResult,
+    utils::{get_redis_connection, get_redis_info, get_tenant_key},
+};
+
+use lazy_static::lazy_static;
+use redis::Script;
+use tokio::{
+    sync::Mutex,
+    time::{sleep, Duration},
+};
+use tracing::warn;
+use twilight_http::Client;
+
+lazy_static! {
+    pub static ref CLIENT: Client = Client::new(CONFIG.bot_token.clone());
+    static ref SCRIPT: Script = Script::new(RATELIMIT_SCRIPT_SOURCE);
+    static ref CONN: Mutex<Option<redis::aio::Connection>> = Mutex::new(None);
+}
+
+async fn reserve() -> ApiResult<u64> {
+    let mut conn = CONN.lock().await;
+    if conn.is_none() {
+        let redis = redis::Client::open(get_redis_info())?;
+        *conn = Some(get_redis_connection(&redis).await?);
+    }
+
+    let result = SCRIPT
+        .key(get_tenant_key(format!("{}:global", RATELIMIT_KEY).as_str()))
+        .key(get_tenant_key(format!("{}:lock", RATELIMIT_KEY).as_str()))
+        .arg(CONFIG.rest_global_limit)
+        .invoke_async(conn.as_mut().unwrap())
+        .await;
+
+    if result.is_err() {
+        *conn = None;
+    }
+
+    Ok(result?)
+}
+
+pub async fn wait() {
+    if CONFIG.rest_global_limit == 0 {
+        return;
+    }
+
+    loop {
+        match reserve().await {
+            Ok(0) => return,
+            Ok(delay) => sleep(Duration::from_millis(delay)).await,
+            Err(err) => {
+                warn!("Failed to reserve REST request: {:?}", err);
+                return;
+            }
+        }
+    }
+}
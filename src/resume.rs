@@ -0,0 +1,75 @@
+use crate::{
+    config::CONFIG,
+    constants::DEFAULT_GATEWAY_URL,
+    models::{PayloadInfo, SessionInfo},
+};
+
+use lazy_static::lazy_static;
+use simd_json::ValueAccess;
+use std::{collections::HashMap, sync::Mutex};
+
+lazy_static! {
+    static ref RESUME_URLS: Mutex<HashMap<u64, String>> = Mutex::new(HashMap::new());
+}
+
+pub fn record(shard: u64, kind: &str, payload: &PayloadInfo) {
+    if kind != "READY" {
+        return;
+    }
+
+    let mut urls = RESUME_URLS.lock().unwrap();
+    match payload.d.get_str("resume_gateway_url") {
+        Some(url) => urls.insert(shard, url.to_owned()),
+        None => urls.remove(&shard),
+    };
+}
+
+pub fn load(sessions: &HashMap<u64, SessionInfo>) {
+    let mut urls = RESUME_URLS.lock().unwrap();
+    for (shard, session) in sessions {
+        if let Some(url) = session.resume_gateway_url.as_ref() {
+            urls.insert(*shard, url.clone());
+        }
+    }
+}
+
+pub fn get(shard: u64) -> Option<String> {
+    RESUME_URLS.lock().unwrap().get(&shard).cloned()
+}
+
+fn get_default_url(cluster: usize) -> String {
+    CONFIG
+        .gateway_cluster_urls
+        .get(cluster)
+        .filter(|url| !url.is_empty())
+        .or_else(|| Some(&CONFIG.gateway_url).filter(|url| !url.is_empty()))
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_GATEWAY_URL.to_owned())
+}
+
+pub fn get_gateway_url(
+    cluster: usize,
+    from: u64,
+    to: u64,
+    resumed: impl Fn(u64) -> bool,
+) -> String {
+    if CONFIG.gateway_proxy {
+        return get_default_url(cluster);
+    }
+
+    let urls = RESUME_URLS.lock().unwrap();
+
+    let mut gateway_url = None;
+    for shard in from..=to {
+        match urls.get(&shard) {
+            Some(url) if resumed(shard) && gateway_url.is_none_or(|other| other == url) => {
+                gateway_url = Some(url);
+            }
+            _ => return get_default_url(cluster),
+        }
+    }
+
+    gateway_url
+        .cloned()
+        .unwrap_or_else(|| get_default_url(cluster))
+}
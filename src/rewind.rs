@@ -0,0 +1,614 @@
+use crate::{
+    config::CONFIG,
+    constants::{SNAPSHOT_FIELDS, SNAPSHOT_POSITION},
+    models::{ApiError, ApiResult, GuildExport},
+    recorder,
+};
+
+use serde::{Deserialize, Serialize};
+use simd_json::{owned::Value, ValueAccess};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom},
+    mem,
+    path::{Path, PathBuf},
+};
+use tokio::time::{sleep, Duration};
+use tracing::warn;
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, UserMarker},
+    Id,
+};
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct GuildState {
+    guild: Option<Value>,
+    channels: BTreeMap<String, Value>,
+    roles: BTreeMap<String, Value>,
+    emojis: BTreeMap<String, Value>,
+    members: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Snapshot {
+    offset: u64,
+    state: GuildState,
+}
+
+#[derive(Debug, Serialize)]
+struct SnapshotRef<'a> {
+    timestamp: u64,
+    offset: u64,
+    state: &'a GuildState,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Position {
+    timestamp: u64,
+    offset: u64,
+}
+
+#[derive(Default)]
+struct Journal {
+    position: Position,
+    guilds: HashMap<String, (u64, GuildState)>,
+}
+
+struct Entry {
+    timestamp: u64,
+    guild_id: String,
+    kind: String,
+    data: Value,
+}
+
+fn get_id(value: &Value, path: &[&str]) -> Option<String> {
+    let mut value = value;
+    for key in path {
+        value = value.get(*key)?;
+    }
+
+    value.as_str().map(|id| id.to_owned())
+}
+
+fn insert_all(map: &mut BTreeMap<String, Value>, values: Option<&Value>, path: &[&str]) {
+    for value in values
+        .and_then(|values| values.as_array())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(id) = get_id(value, path) {
+            map.insert(id, value.clone());
+        }
+    }
+}
+
+fn merge(target: &mut Value, source: &Value) {
+    if let (Value::Object(target), Some(source)) = (target, source.as_object()) {
+        for (key, value) in source.iter() {
+            target.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+impl GuildState {
+    fn reset(&mut self, mut data: Value) {
+        *self = Self::default();
+
+        insert_all(&mut self.channels, data.get("channels"), &["id"]);
+        insert_all(&mut self.roles, data.get("roles"), &["id"]);
+        insert_all(&mut self.emojis, data.get("emojis"), &["id"]);
+        insert_all(&mut self.members, data.get("members"), &["user", "id"]);
+
+        if let Value::Object(object) = &mut data {
+            for field in SNAPSHOT_FIELDS {
+                object.remove(field);
+            }
+        }
+        self.guild = Some(data);
+    }
+
+    fn apply(&mut self, kind: &str, mut data: Value) {
+        match kind {
+            "GUILD_CREATE" => self.reset(data),
+            "GUILD_UPDATE" => {
+                if let Some(guild) = self.guild.as_mut() {
+                    self.roles.clear();
+                    insert_all(&mut self.roles, data.get("roles"), &["id"]);
+                    self.emojis.clear();
+                    insert_all(&mut self.emojis, data.get("emojis"), &["id"]);
+                    if let Value::Object(object) = &mut data {
+                        object.remove("roles");
+                        object.remove("emojis");
+                    }
+                    merge(guild, &data);
+                }
+            }
+            "GUILD_DELETE" if !data.get_bool("unavailable").unwrap_or_default() => {
+                *self = Self::default();
+            }
+            "CHANNEL_CREATE" | "CHANNEL_UPDATE" => {
+                if let Some(id) = get_id(&data, &["id"]) {
+                    self.channels.insert(id, data);
+                }
+            }
+            "CHANNEL_DELETE" => {
+                if let Some(id) = get_id(&data, &["id"]) {
+                    self.channels.remove(&id);
+                }
+            }
+            "GUILD_ROLE_CREATE" | "GUILD_ROLE_UPDATE" => {
+                if let Some(role) = data.get("role") {
+                    if let Some(id) = get_id(role, &["id"]) {
+                        self.roles.insert(id, role.clone());
+                    }
+                }
+            }
+            "GUILD_ROLE_DELETE" => {
+                if let Some(id) = get_id(&data, &["role_id"]) {
+                    self.roles.remove(&id);
+                }
+            }
+            "GUILD_EMOJIS_UPDATE" => {
+                self.emojis.clear();
+                insert_all(&mut self.emojis, data.get("emojis"), &["id"]);
+            }
+            "GUILD_MEMBER_ADD" => {
+                if let Some(id) = get_id(&data, &["user", "id"]) {
+                    self.members.insert(id, data);
+                }
+            }
+            "GUILD_MEMBER_UPDATE" => {
+                if let Some(id) = get_id(&data, &["user", "id"]) {
+                    match self.members.get_mut(&id) {
+                        Some(member) => merge(member, &data),
+                        None => {
+                            self.members.insert(id, data);
+                        }
+                    }
+                }
+            }
+            "GUILD_MEMBER_REMOVE" => {
+                if let Some(id) = get_id(&data, &["user", "id"]) {
+                    self.members.remove(&id);
+                }
+            }
+            "GUILD_MEMBERS_CHUNK" => {
+                insert_all(&mut self.members, data.get("members"), &["user", "id"]);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn into_export(self) -> Option<GuildExport> {
+        Some(GuildExport {
+            guild: Some(self.guild?),
+            channels: self.channels.into_values().collect(),
+            roles: self.roles.into_values().collect(),
+            emojis: self.emojis.into_values().collect(),
+            members: self.members.into_values().collect(),
+            ..GuildExport::default()
+        })
+    }
+
+    pub fn take_channel(&mut self, channel_id: Id<ChannelMarker>) -> Option<Value> {
+        self.guild.as_ref()?;
+        self.channels.remove(channel_id.to_string().as_str())
+    }
+
+    pub fn take_member(&mut self, user_id: Id<UserMarker>) -> Option<Value> {
+        self.guild.as_ref()?;
+        self.members.remove(user_id.to_string().as_str())
+    }
+}
+
+fn parse_entry(line: Vec<u8>) -> Option<Entry> {
+    match parse_line(line) {
+        Ok(entry) => entry,
+        Err(err) => {
+            warn!("Skipping invalid recorded payload: {:?}", err);
+            None
+        }
+    }
+}
+
+fn parse_line(mut line: Vec<u8>) -> ApiResult<Option<Entry>> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+
+    let value = simd_json::to_owned_value(line.as_mut_slice())?;
+    let timestamp = value
+        .get_u64("timestamp")
+        .ok_or_else(|| ApiError::InvalidRecording("Missing timestamp".to_owned()))?;
+
+    let payload = match value.get("payload") {
+        Some(payload) => payload,
+        None => return Ok(None),
+    };
+    let (kind, data) = match (payload.get_str("t"), payload.get("d")) {
+        (Some(kind), Some(data)) => (kind, data),
+        _ => return Ok(None),
+    };
+
+    let guild_id = match kind {
+        "GUILD_CREATE" | "GUILD_UPDATE" | "GUILD_DELETE" => get_id(data, &["id"]),
+        _ => get_id(data, &["guild_id"]),
+    };
+
+    Ok(guild_id.map(|guild_id| Entry {
+        timestamp,
+        guild_id,
+        kind: kind.to_owned(),
+        data: data.clone(),
+    }))
+}
+
+fn replay(
+    mut reader: impl BufRead,
+    guild_id: &str,
+    state: &mut GuildState,
+    timestamp: u64,
+) -> ApiResult<()> {
+    let mut line = vec![];
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 || line.last() != Some(&b'\n') {
+            break;
+        }
+
+        if !line
+            .windows(guild_id.len())
+            .any(|window| window == guild_id.as_bytes())
+        {
+            continue;
+        }
+
+        let entry = match parse_entry(mem::take(&mut line)) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        if entry.timestamp > timestamp {
+            break;
+        }
+
+        if entry.guild_id == guild_id {
+            state.apply(entry.kind.as_str(), entry.data);
+        }
+    }
+
+    Ok(())
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> ApiResult<Option<T>> {
+    let mut data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(Some(simd_json::from_slice(data.as_mut_slice())?))
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> ApiResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp = path.with_extension("tmp");
+    fs::write(temp.as_path(), simd_json::to_vec(value)?)?;
+    fs::rename(temp, path)?;
+
+    Ok(())
+}
+
+fn find_snapshot(directory: &Path, timestamp: u64) -> ApiResult<Option<PathBuf>> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut latest = None;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+            continue;
+        }
+
+        let snapshot_timestamp = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok());
+        if let Some(snapshot_timestamp) = snapshot_timestamp {
+            if snapshot_timestamp <= timestamp
+                && latest
+                    .as_ref()
+                    .is_none_or(|(latest, _)| snapshot_timestamp > *latest)
+            {
+                latest = Some((snapshot_timestamp, path));
+            }
+        }
+    }
+
+    Ok(latest.map(|(_, path)| path))
+}
+
+fn rebuild(
+    recording: &Path,
+    snapshots: &Path,
+    guild_id: Id<GuildMarker>,
+    timestamp: u64,
+) -> ApiResult<GuildState> {
+    let guild_id = guild_id.to_string();
+
+    let snapshot = match find_snapshot(snapshots.join(guild_id.as_str()).as_path(), timestamp)? {
+        Some(path) => read_json::<Snapshot>(path.as_path())?.unwrap_or_default(),
+        None => Snapshot::default(),
+    };
+    let mut state = snapshot.state;
+
+    let mut file = match File::open(recording) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(state),
+        Err(err) => return Err(err.into()),
+    };
+    file.seek(SeekFrom::Start(snapshot.offset))?;
+    replay(
+        BufReader::new(file),
+        guild_id.as_str(),
+        &mut state,
+        timestamp,
+    )?;
+
+    Ok(state)
+}
+
+impl Journal {
+    fn load(snapshots: &Path) -> ApiResult<Self> {
+        let mut journal = Self {
+            position: read_json(snapshots.join(SNAPSHOT_POSITION).as_path())?.unwrap_or_default(),
+            ..Self::default()
+        };
+
+        let entries = match fs::read_dir(snapshots) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(journal),
+            Err(err) => return Err(err.into()),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            let guild_id = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if path.is_dir() => name.to_owned(),
+                _ => continue,
+            };
+
+            if let Some(path) = find_snapshot(path.as_path(), u64::MAX)? {
+                if let Some(snapshot) = read_json::<Snapshot>(path.as_path())? {
+                    journal
+                        .guilds
+                        .insert(guild_id, (snapshot.offset, snapshot.state));
+                }
+            }
+        }
+
+        Ok(journal)
+    }
+
+    fn advance(&mut self, recording: &Path, snapshots: &Path) -> ApiResult<()> {
+        let mut file = match File::open(recording) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        if file.metadata()?.len() < self.position.offset {
+            return Err(ApiError::InvalidRecording(
+                "Recording is shorter than the snapshot position".to_owned(),
+            ));
+        }
+
+        file.seek(SeekFrom::Start(self.position.offset))?;
+        let mut reader = BufReader::new(file);
+
+        let mut changed = HashSet::new();
+        let mut line = vec![];
+        loop {
+            line.clear();
+            let length = reader.read_until(b'\n', &mut line)?;
+            if length == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+
+            let start = self.position.offset;
+            self.position.offset += length as u64;
+
+            let entry = match parse_entry(mem::take(&mut line)) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            self.position.timestamp = self.position.timestamp.max(entry.timestamp);
+
+            let (offset, state) = self.guilds.entry(entry.guild_id.clone()).or_default();
+            if *offset > start {
+                continue;
+            }
+
+            state.apply(entry.kind.as_str(), entry.data);
+            changed.insert(entry.guild_id);
+        }
+
+        for guild_id in changed {
+            if let Some((offset, state)) = self.guilds.get_mut(&guild_id) {
+                *offset = self.position.offset;
+                write_json(
+                    snapshots
+                        .join(guild_id.as_str())
+                        .join(format!("{}.json", self.position.timestamp))
+                        .as_path(),
+                    &SnapshotRef {
+                        timestamp: self.position.timestamp,
+                        offset: self.position.offset,
+                        state,
+                    },
+                )?;
+            }
+        }
+
+        write_json(snapshots.join(SNAPSHOT_POSITION).as_path(), &self.position)
+    }
+}
+
+pub async fn get_guild(guild_id: Id<GuildMarker>, timestamp: u64) -> ApiResult<GuildState> {
+    tokio::task::spawn_blocking(move || {
+        rebuild(
+            Path::new(CONFIG.record_path.as_str()),
+            Path::new(CONFIG.snapshot_path.as_str()),
+            guild_id,
+            timestamp,
+        )
+    })
+    .await
+    .map_err(|err| ApiError::InvalidRecording(format!("{}", err)))?
+}
+
+pub async fn run_jobs() {
+    if CONFIG.record_path.is_empty() || CONFIG.snapshot_interval == 0 || recorder::is_replaying() {
+        return;
+    }
+
+    let recording = PathBuf::from(CONFIG.record_path.as_str());
+    let snapshots = PathBuf::from(CONFIG.snapshot_path.as_str());
+
+    let mut journal = match Journal::load(snapshots.as_path()) {
+        Ok(journal) => journal,
+        Err(err) => {
+            warn!("Failed to load snapshots: {:?}", err);
+            return;
+        }
+    };
+
+    loop {
+        sleep(Duration::from_millis(CONFIG.snapshot_interval)).await;
+
+        let recording = recording.clone();
+        let snapshots = snapshots.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let result = journal.advance(recording.as_path(), snapshots.as_path());
+            (journal, result)
+        })
+        .await;
+
+        match result {
+            Ok((returned, result)) => {
+                journal = returned;
+                if let Err(err) = result {
+                    warn!("Failed to write snapshots: {:?}", err);
+                }
+            }
+            Err(err) => {
+                warn!("Failed to write snapshots: {:?}", err);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rebuild, replay, GuildState, Journal};
+
+    use simd_json::ValueAccess;
+    use std::{env, fs, io::Cursor, path::PathBuf, process};
+    use twilight_model::id::Id;
+
+    const RECORDING: &str = concat!(
+        r#"{"shard":0,"timestamp":100,"payload":{"op":0,"t":"GUILD_CREATE","d":{"id":"1","name":"Guild","channels":[{"id":"2","name":"general"}],"roles":[{"id":"3","name":"Moderator"}],"members":[{"user":{"id":"4"},"roles":[]}]}}}"#,
+        "\n",
+        r#"{"shard":0,"timestamp":200,"payload":{"op":0,"t":"GUILD_MEMBER_UPDATE","d":{"guild_id":"1","user":{"id":"4"},"roles":["3"]}}}"#,
+        "\n",
+        r#"{"shard":0,"timestamp":300,"payload":{"op":0,"t":"CHANNEL_UPDATE","d":{"id":"2","guild_id":"1","name":"chat"}}}"#,
+        "\n",
+        r#"{"shard":0,"timestamp":400,"payload":{"op":0,"t":"GUILD_MEMBER_REMOVE","d":{"guild_id":"1","user":{"id":"4"}}}}"#,
+        "\n",
+    );
+
+    fn get_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("twilight-dispatch-{}-{}", name, process::id()))
+    }
+
+    fn get_roles(state: &mut GuildState) -> Option<usize> {
+        state
+            .take_member(Id::new(4))
+            .and_then(|member| member.get_array("roles").map(|roles| roles.len()))
+    }
+
+    #[test]
+    fn replay_until_timestamp() {
+        let mut state = GuildState::default();
+        replay(Cursor::new(RECORDING), "1", &mut state, 250).unwrap();
+
+        let mut channel = GuildState::default();
+        replay(Cursor::new(RECORDING), "1", &mut channel, 350).unwrap();
+
+        assert_eq!(get_roles(&mut state), Some(1));
+        assert_eq!(
+            channel
+                .take_channel(Id::new(2))
+                .and_then(|channel| channel.get_str("name").map(|name| name.to_owned())),
+            Some("chat".to_owned())
+        );
+        assert_eq!(get_roles(&mut channel), Some(1));
+    }
+
+    #[test]
+    fn replay_ignores_other_guilds() {
+        let mut state = GuildState::default();
+        replay(Cursor::new(RECORDING), "4", &mut state, u64::MAX).unwrap();
+
+        assert!(state.into_export().is_none());
+    }
+
+    #[test]
+    fn snapshot_matches_full_replay() {
+        let recording = get_path("recording");
+        let snapshots = get_path("snapshots");
+        let first = &RECORDING[..RECORDING.find("\n{").unwrap() + 1];
+
+        fs::write(recording.as_path(), first).unwrap();
+        let mut journal = Journal::load(snapshots.as_path()).unwrap();
+        journal
+            .advance(recording.as_path(), snapshots.as_path())
+            .unwrap();
+
+        fs::write(recording.as_path(), RECORDING).unwrap();
+        let mut journal = Journal::load(snapshots.as_path()).unwrap();
+        assert_eq!(journal.position.offset, first.len() as u64);
+        journal
+            .advance(recording.as_path(), snapshots.as_path())
+            .unwrap();
+        assert_eq!(journal.position.offset, RECORDING.len() as u64);
+
+        for timestamp in [50, 100, 200, 300, 400] {
+            let mut expected = GuildState::default();
+            replay(Cursor::new(RECORDING), "1", &mut expected, timestamp).unwrap();
+            let mut actual = rebuild(
+                recording.as_path(),
+                snapshots.as_path(),
+                Id::new(1),
+                timestamp,
+            )
+            .unwrap();
+
+            assert_eq!(get_roles(&mut actual), get_roles(&mut expected));
+            assert_eq!(
+                actual.into_export().map(|export| export.channels),
+                expected.into_export().map(|export| export.channels)
+            );
+        }
+
+        fs::remove_file(recording).unwrap();
+        fs::remove_dir_all(snapshots).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+use memmap2::{Mmap, MmapMut};
+use std::{
+    fs::OpenOptions,
+    io::Result,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tracing::warn;
+
+const HEADER_SIZE: u64 = 64;
+const WRAP_MARKER: u32 = u32::MAX;
+
+fn get_position(mmap: &[u8]) -> &AtomicU64 {
+    assert!(mmap.len() >= 8);
+
+    // SAFETY: The first 8 bytes of the mapping are reserved for the write position, the mapping
+    // is page aligned and the returned reference cannot outlive it.
+    unsafe { &*(mmap.as_ptr() as *const AtomicU64) }
+}
+
+pub struct ShmWriter {
+    mmap: MmapMut,
+    capacity: u64,
+}
+
+impl ShmWriter {
+    pub fn open(path: &str, capacity: u64) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        file.set_len(HEADER_SIZE + capacity)?;
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        mmap[8..16].copy_from_slice(&capacity.to_le_bytes());
+        get_position(&mmap).store(0, Ordering::Release);
+
+        Ok(Self { mmap, capacity })
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) {
+        let start = (HEADER_SIZE + offset) as usize;
+        self.mmap[start..start + data.len()].copy_from_slice(data);
+    }
+
+    pub fn write(&mut self, payload: &[u8]) {
+        let length = 4 + payload.len() as u64;
+        if length > self.capacity {
+            warn!("Payload too large for shared memory buffer");
+            return;
+        }
+
+        let mut position = get_position(&self.mmap).load(Ordering::Acquire);
+        let mut offset = position % self.capacity;
+
+        if offset + length > self.capacity {
+            if self.capacity - offset >= 4 {
+                self.write_at(offset, &WRAP_MARKER.to_le_bytes());
+            }
+            position += self.capacity - offset;
+            offset = 0;
+        }
+
+        self.write_at(offset, &(payload.len() as u32).to_le_bytes());
+        self.write_at(offset + 4, payload);
+
+        get_position(&self.mmap).store(position + length, Ordering::Release);
+    }
+}
+
+// The reader is only used by consumers through the client feature.
+#[allow(dead_code)]
+pub struct ShmReader {
+    mmap: Mmap,
+    capacity: u64,
+    position: u64,
+}
+
+#[allow(dead_code)]
+impl ShmReader {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let mut capacity = [0; 8];
+        capacity.copy_from_slice(&mmap[8..16]);
+        let capacity = u64::from_le_bytes(capacity);
+        let position = get_position(&mmap).load(Ordering::Acquire);
+
+        Ok(Self {
+            mmap,
+            capacity,
+            position,
+        })
+    }
+
+    fn read_at(&self, offset: u64, length: u64) -> &[u8] {
+        let start = (HEADER_SIZE + offset) as usize;
+        &self.mmap[start..start + length as usize]
+    }
+
+    fn is_overrun(&mut self, start: u64) -> bool {
+        let written = get_position(&self.mmap).load(Ordering::Acquire);
+        if written < start || written - start > self.capacity {
+            self.position = written;
+            return true;
+        }
+
+        false
+    }
+
+    pub fn read(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let written = get_position(&self.mmap).load(Ordering::Acquire);
+            if self.position == written || self.is_overrun(self.position) {
+                return None;
+            }
+
+            let offset = self.position % self.capacity;
+            if self.capacity - offset < 4 {
+                self.position += self.capacity - offset;
+                continue;
+            }
+
+            let mut length = [0; 4];
+            length.copy_from_slice(self.read_at(offset, 4));
+            let length = u32::from_le_bytes(length);
+
+            if length == WRAP_MARKER {
+                self.position += self.capacity - offset;
+                continue;
+            }
+
+            let start = self.position;
+            if offset + 4 + length as u64 > self.capacity {
+                self.position = get_position(&self.mmap).load(Ordering::Acquire);
+                return None;
+            }
+
+            let payload = self.read_at(offset + 4, length as u64).to_vec();
+            if self.is_overrun(start) {
+                return None;
+            }
+
+            self.position += 4 + length as u64;
+
+            return Some(payload);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShmReader, ShmWriter};
+
+    use std::{env, fs, process};
+
+    fn get_path(name: &str) -> String {
+        env::temp_dir()
+            .join(format!("twilight-dispatch-{}-{}", name, process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let path = get_path("roundtrip");
+        let mut writer = ShmWriter::open(path.as_str(), 64).unwrap();
+        let mut reader = ShmReader::open(path.as_str()).unwrap();
+
+        assert_eq!(reader.read(), None);
+
+        for i in 0..20 {
+            let payload = format!("event {}", i).into_bytes();
+            writer.write(payload.as_slice());
+            assert_eq!(reader.read(), Some(payload));
+            assert_eq!(reader.read(), None);
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn overrun_skips_to_writer() {
+        let path = get_path("overrun");
+        let mut writer = ShmWriter::open(path.as_str(), 64).unwrap();
+        let mut reader = ShmReader::open(path.as_str()).unwrap();
+
+        for i in 0..20 {
+            writer.write(format!("event {}", i).as_bytes());
+        }
+        assert_eq!(reader.read(), None);
+
+        writer.write(b"latest");
+        assert_eq!(reader.read(), Some(b"latest".to_vec()));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn writer_restart_resets_reader() {
+        let path = get_path("restart");
+        let mut writer = ShmWriter::open(path.as_str(), 64).unwrap();
+        let mut reader = ShmReader::open(path.as_str()).unwrap();
+
+        writer.write(b"before");
+        assert_eq!(reader.read(), Some(b"before".to_vec()));
+
+        let mut writer = ShmWriter::open(path.as_str(), 64).unwrap();
+        assert_eq!(reader.read(), None);
+
+        writer.write(b"after");
+        assert_eq!(reader.read(), Some(b"after".to_vec()));
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    config::CONFIG,
+    constants::SAMPLE_FLUSH_INTERVAL,
+    models::{ApiResult, PayloadInfo},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    mem,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use time::OffsetDateTime;
+use tokio::time::{sleep, Duration};
+use tracing::warn;
+
+lazy_static! {
+    static ref COUNTER: AtomicU64 = AtomicU64::new(0);
+    static ref BUFFER: Mutex<HashMap<PathBuf, Vec<u8>>> = Mutex::new(HashMap::new());
+}
+
+fn is_sampled(kind: &str) -> bool {
+    if CONFIG.sample_rate <= 0.0 {
+        return false;
+    }
+
+    if !CONFIG.sample_events.is_empty() && !CONFIG.sample_events.iter().any(|event| event == kind) {
+        return false;
+    }
+
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed) as f64;
+    let rate = CONFIG.sample_rate.min(100.0) / 100.0;
+
+    ((count + 1.0) * rate).floor() > (count * rate).floor()
+}
+
+fn get_path(kind: &str) -> PathBuf {
+    let now = OffsetDateTime::now_utc();
+
+    PathBuf::from(CONFIG.sample_path.as_str())
+        .join(format!("dt={}", now.date()))
+        .join(format!("type={}", kind))
+        .join(format!("part-{:02}.jsonl.gz", now.hour()))
+}
+
+pub fn record(kind: &str, payload: &PayloadInfo) {
+    if !is_sampled(kind) {
+        return;
+    }
+
+    let mut payload = match simd_json::to_vec(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            warn!("Failed to serialize sampled payload: {:?}", err);
+            return;
+        }
+    };
+    payload.push(b'\n');
+
+    BUFFER
+        .lock()
+        .unwrap()
+        .entry(get_path(kind))
+        .or_default()
+        .extend_from_slice(payload.as_slice());
+}
+
+fn write(path: &Path, data: &[u8]) -> ApiResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+pub async fn flush() {
+    let buffer = mem::take(&mut *BUFFER.lock().unwrap());
+    if buffer.is_empty() {
+        return;
+    }
+
+    let result = tokio::task::spawn_blocking(move || {
+        for (path, data) in buffer {
+            if let Err(err) = write(&path, data.as_slice()) {
+                warn!("Failed to write samples to {}: {:?}", path.display(), err);
+            }
+        }
+    })
+    .await;
+
+    if let Err(err) = result {
+        warn!("Failed to write samples: {:?}", err);
+    }
+}
+
+pub async fn run_jobs() {
+    if CONFIG.sample_rate <= 0.0 {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(SAMPLE_FLUSH_INTERVAL as u64)).await;
+
+        flush().await;
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    config::CONFIG,
+    constants::SCHEMA_DIR,
+    models::{ApiResult, JsonSchema, JsonSchemaProperty, JsonSchemaType, SchemaField},
+    utils::get_schema,
+};
+
+use std::{collections::BTreeMap, fs, path::Path};
+use tracing::info;
+
+fn field(
+    name: &'static str,
+    kind: &'static str,
+    optional: bool,
+    description: &'static str,
+) -> SchemaField {
+    SchemaField {
+        name,
+        kind,
+        optional,
+        description,
+    }
+}
+
+fn get_models() -> Vec<(&'static str, Vec<SchemaField>)> {
+    vec![
+        ("Envelope", get_schema().envelope),
+        (
+            "DeliveryInfo",
+            vec![
+                field(
+                    "op",
+                    "integer",
+                    false,
+                    "Operation, 0 for send, 1 for reconnect, 2 for tap and 3 for replay",
+                ),
+                field("shard", "integer", false, "Shard to send the command to"),
+                field("data", "object", true, "Gateway command or tap options"),
+                field(
+                    "priority",
+                    "integer",
+                    true,
+                    "Priority, 0 for low, 1 for normal and 2 for high",
+                ),
+                field(
+                    "correlation_id",
+                    "string",
+                    true,
+                    "Identifier to publish the result of the command with",
+                ),
+            ],
+        ),
+        (
+            "DeliveryResult",
+            vec![
+                field(
+                    "correlation_id",
+                    "string",
+                    false,
+                    "Identifier of the command",
+                ),
+                field("shard", "integer", false, "Shard the command was sent to"),
+                field("success", "boolean", false, "Whether the command succeeded"),
+                field("error", "string", true, "Description of the error"),
+            ],
+        ),
+        (
+            "StatusInfo",
+            vec![
+                field("cluster", "integer", false, "Cluster running the shard"),
+                field("shard", "integer", false, "Shard ID"),
+                field("status", "string", false, "Connection stage of the shard"),
+                field("latency", "integer", false, "Latency in milliseconds"),
+                field(
+                    "last_ack",
+                    "string",
+                    false,
+                    "Time of the last heartbeat ack",
+                ),
+                field("updated", "string", false, "Time the status was updated"),
+            ],
+        ),
+        (
+            "SessionInfo",
+            vec![
+                field("session_id", "string", false, "Discord session ID"),
+                field("sequence", "integer", false, "Last sequence received"),
+                field(
+                    "resume_gateway_url",
+                    "string",
+                    true,
+                    "Gateway URL to resume the session with",
+                ),
+            ],
+        ),
+    ]
+}
+
+fn to_json_schema(name: &str, fields: &[SchemaField]) -> JsonSchema {
+    JsonSchema {
+        schema: "http://json-schema.org/draft-07/schema#",
+        id: format!(
+            "twilight-dispatch/v{}/{}.schema.json",
+            CONFIG.envelope_version, name
+        ),
+        title: name.to_owned(),
+        kind: "object",
+        properties: fields
+            .iter()
+            .map(|field| {
+                (
+                    field.name,
+                    JsonSchemaProperty {
+                        kind: if field.optional {
+                            JsonSchemaType::Nullable([field.kind, "null"])
+                        } else {
+                            JsonSchemaType::Required(field.kind)
+                        },
+                        description: field.description,
+                    },
+                )
+            })
+            .collect::<BTreeMap<_, _>>(),
+        required: fields
+            .iter()
+            .filter(|field| !field.optional)
+            .map(|field| field.name)
+            .collect(),
+    }
+}
+
+fn to_typescript(name: &str, fields: &[SchemaField]) -> String {
+    let mut output = format!("export interface {} {{\n", name);
+
+    for field in fields {
+        let kind = match field.kind {
+            "integer" => "number",
+            "object" => "unknown",
+            kind => kind,
+        };
+
+        output.push_str(format!("    /** {} */\n", field.description).as_str());
+        output.push_str(
+            format!(
+                "    {}{}: {};\n",
+                field.name,
+                if field.optional { "?" } else { "" },
+                if field.optional {
+                    format!("{} | null", kind)
+                } else {
+                    kind.to_owned()
+                }
+            )
+            .as_str(),
+        );
+    }
+
+    output.push_str("}\n");
+    output
+}
+
+pub fn generate() -> ApiResult<()> {
+    let path = Path::new(SCHEMA_DIR);
+    fs::create_dir_all(path)?;
+
+    let mut typescript = format!(
+        "// Generated by twilight-dispatch {} for envelope version {}\n",
+        env!("CARGO_PKG_VERSION"),
+        CONFIG.envelope_version
+    );
+
+    for (name, fields) in get_models() {
+        let schema = simd_json::to_string_pretty(&to_json_schema(name, fields.as_slice()))?;
+        fs::write(path.join(format!("{}.schema.json", name)), schema)?;
+
+        typescript.push('\n');
+        typescript.push_str(to_typescript(name, fields.as_slice()).as_str());
+    }
+
+    fs::write(path.join("index.d.ts"), typescript)?;
+
+    info!("Generated schemas in {}", path.display());
+
+    Ok(())
+}
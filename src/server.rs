@@ -0,0 +1,684 @@
+use crate::{
+    authz::{self, Credentials},
+    cache, chunks,
+    config::CONFIG,
+    constants::{
+        AUTHZ_EXPIRES_HEADER, AUTHZ_SIGNATURE_HEADER, AUTHZ_TOKEN_HEADER, PRIVILEGED_ROUTES,
+        SERVER_ACCEPT_BACKOFF, SERVER_BODY_LIMIT, SERVER_CONNECTION_LIMIT,
+    },
+    firehose, identify, integrations, leave, logging, metrics,
+    models::{
+        ApiError, ApiResult, CaptureInfo, GuildLeaveInfo, GuildMigrationInfo, GuildShardInfo,
+        MemberRequestInfo,
+    },
+    rewind,
+    startup::get_progress,
+    utils::{
+        constant_time_eq, get_config_info, get_guild_shard, get_redis_connection, get_registry,
+        get_schema,
+    },
+    watermark,
+};
+
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE},
+    server::{conn::Http, Server},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use lazy_static::lazy_static;
+use prometheus::{Encoder, TextEncoder};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::BufReader,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    net::TcpListener,
+    time::{sleep, Duration},
+};
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+use tracing::warn;
+use twilight_gateway::Cluster;
+use twilight_model::id::Id;
+
+lazy_static! {
+    static ref CONNECTIONS: Mutex<Vec<redis::aio::Connection>> = Mutex::new(vec![]);
+}
+
+fn json_response<T: Serialize>(value: &T) -> ApiResult<Response<Body>> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(simd_json::to_vec(value)?))?)
+}
+
+fn status_response(status: StatusCode) -> ApiResult<Response<Body>> {
+    Ok(Response::builder().status(status).body(Body::empty())?)
+}
+
+fn is_auth_configured() -> bool {
+    !CONFIG.server_token.is_empty() || !CONFIG.server_username.is_empty()
+}
+
+fn is_privileged(method: &Method, segments: &[&str]) -> bool {
+    PRIVILEGED_ROUTES.iter().any(|(route_method, route)| {
+        method.as_str() == *route_method
+            && route.len() == segments.len()
+            && route
+                .iter()
+                .zip(segments)
+                .all(|(part, segment)| *part == "*" || part == segment)
+    })
+}
+
+fn is_authorized(req: &Request<Body>) -> bool {
+    if !is_auth_configured() {
+        return true;
+    }
+
+    let header = match req.headers().get(AUTHORIZATION).map(|value| value.to_str()) {
+        Some(Ok(header)) => header,
+        _ => return false,
+    };
+
+    if let Some(token) = header.strip_prefix("Bearer ") {
+        !CONFIG.server_token.is_empty()
+            && constant_time_eq(token.as_bytes(), CONFIG.server_token.as_bytes())
+    } else if let Some(credentials) = header.strip_prefix("Basic ") {
+        let expected = base64::encode(format!(
+            "{}:{}",
+            CONFIG.server_username, CONFIG.server_password
+        ));
+
+        !CONFIG.server_username.is_empty()
+            && constant_time_eq(credentials.as_bytes(), expected.as_bytes())
+    } else {
+        false
+    }
+}
+
+async fn read_body(mut body: Body) -> ApiResult<Option<Bytes>> {
+    let mut data = vec![];
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if data.len() + chunk.len() > SERVER_BODY_LIMIT {
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(Some(data.into()))
+}
+
+async fn get_connection(redis: &redis::Client) -> ApiResult<redis::aio::Connection> {
+    let pooled = CONNECTIONS.lock().unwrap().pop();
+    match pooled {
+        Some(conn) => Ok(conn),
+        None => get_redis_connection(redis).await,
+    }
+}
+
+fn release_connection(conn: redis::aio::Connection) {
+    let mut connections = CONNECTIONS.lock().unwrap();
+    if connections.len() < SERVER_CONNECTION_LIMIT {
+        connections.push(conn);
+    }
+}
+
+struct ServerState {
+    clusters: Vec<Arc<Cluster>>,
+    redis: redis::Client,
+    endpoints: Vec<String>,
+}
+
+async fn serve(req: Request<Body>, state: Arc<ServerState>) -> ApiResult<Response<Body>> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_owned();
+    let segments: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+
+    if !state.endpoints.is_empty()
+        && !segments
+            .first()
+            .is_some_and(|segment| state.endpoints.iter().any(|endpoint| endpoint == segment))
+    {
+        return status_response(StatusCode::NOT_FOUND);
+    }
+
+    let events_guild_id = match segments.as_slice() {
+        ["guilds", guild_id, "events"] if method == Method::GET => guild_id.parse().ok(),
+        _ => None,
+    };
+
+    let firehose_authorized =
+        events_guild_id.is_some_and(|guild_id| firehose::is_authorized(&req, guild_id));
+
+    if segments.as_slice() != ["healthcheck"] && !is_authorized(&req) && !firehose_authorized {
+        return status_response(StatusCode::UNAUTHORIZED);
+    }
+
+    if !is_auth_configured() && !firehose_authorized && is_privileged(&method, segments.as_slice())
+    {
+        return status_response(StatusCode::FORBIDDEN);
+    }
+
+    let req = if method != Method::GET {
+        let (parts, body) = req.into_parts();
+
+        let get_header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let credentials = Credentials {
+            token: get_header(AUTHZ_TOKEN_HEADER),
+            signature: get_header(AUTHZ_SIGNATURE_HEADER),
+            expires: get_header(AUTHZ_EXPIRES_HEADER),
+            user: None,
+        };
+        if !authz::precheck("http", &credentials) {
+            return status_response(StatusCode::FORBIDDEN);
+        }
+
+        let length = get_header(CONTENT_LENGTH.as_str()).and_then(|length| length.parse().ok());
+        if length.is_some_and(|length: usize| length > SERVER_BODY_LIMIT) {
+            return status_response(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        let body = match read_body(body).await? {
+            Some(body) => body,
+            None => return status_response(StatusCode::PAYLOAD_TOO_LARGE),
+        };
+        if !authz::authorize("http", &credentials, &body) {
+            return status_response(StatusCode::FORBIDDEN);
+        }
+
+        Request::from_parts(parts, Body::from(body))
+    } else {
+        req
+    };
+
+    match (&method, segments.as_slice()) {
+        (&Method::GET, ["metrics"]) => {
+            let mut buffer = vec![];
+            let metrics = metrics::gather();
+
+            let encoder = TextEncoder::new();
+            encoder.encode(metrics.as_slice(), &mut buffer)?;
+
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, encoder.format_type())
+                .body(Body::from(buffer))?)
+        }
+        (&Method::GET, ["healthcheck"]) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from("{\"status\":\"OK\"}"))?),
+        (&Method::GET, ["progress"]) => json_response(&get_progress()),
+        (&Method::GET, ["schema"]) => json_response(&get_schema()),
+        (&Method::GET, ["registry"]) => json_response(&get_registry()),
+        (&Method::GET, ["config"]) => json_response(&get_config_info()?),
+        (&Method::GET, ["watermark"]) => json_response(&watermark::get_info()),
+        (&Method::GET, ["identify"]) => json_response(&identify::get_info()),
+        (&Method::GET, ["shards", "for-guild", guild_id]) => match guild_id.parse() {
+            Ok(guild_id) => {
+                let shard = get_guild_shard(guild_id);
+                let cluster = state
+                    .clusters
+                    .iter()
+                    .position(|cluster| cluster.shard(shard).is_some())
+                    .map(|index| index as u64);
+
+                json_response(&GuildShardInfo {
+                    guild_id,
+                    shard,
+                    cluster,
+                    local: cluster.is_some(),
+                })
+            }
+            Err(_) => status_response(StatusCode::BAD_REQUEST),
+        },
+        (&Method::GET, ["shards", shard, "history"]) => match shard.parse() {
+            Ok(shard) => {
+                let mut conn = get_connection(&state.redis).await?;
+                let result = cache::get_history(&mut conn, shard).await?;
+                release_connection(conn);
+
+                json_response(&result)
+            }
+            Err(_) => status_response(StatusCode::BAD_REQUEST),
+        },
+        (&Method::GET, ["guilds", _, "events"]) => match events_guild_id.map(firehose::subscribe) {
+            Some(Some(body)) => Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, "text/event-stream")
+                .header(CACHE_CONTROL, "no-cache")
+                .body(body)?),
+            Some(None) => status_response(StatusCode::SERVICE_UNAVAILABLE),
+            None => status_response(StatusCode::BAD_REQUEST),
+        },
+        (&Method::GET, ["guilds", guild_id, "events", "token"]) => match guild_id.parse() {
+            Ok(guild_id) if !CONFIG.firehose_secret.is_empty() => {
+                json_response(&firehose::get_token(guild_id))
+            }
+            Ok(_) => status_response(StatusCode::NOT_FOUND),
+            Err(_) => status_response(StatusCode::BAD_REQUEST),
+        },
+        (&Method::GET, ["guilds", guild_id, "export"]) => {
+            match guild_id.parse().ok().and_then(Id::new_checked) {
+                Some(guild_id) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = cache::export_guild(&mut conn, guild_id).await?;
+                    release_connection(conn);
+
+                    json_response(&result)
+                }
+                None => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "at", timestamp]) => {
+            match (
+                guild_id.parse().ok().and_then(Id::new_checked),
+                timestamp.parse(),
+            ) {
+                (Some(guild_id), Ok(timestamp)) if !CONFIG.record_path.is_empty() => {
+                    match rewind::get_guild(guild_id, timestamp).await?.into_export() {
+                        Some(export) => json_response(&export),
+                        None => status_response(StatusCode::NOT_FOUND),
+                    }
+                }
+                (Some(_), Ok(_)) => status_response(StatusCode::NOT_FOUND),
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "at", timestamp, "channels", channel_id]) => {
+            match (
+                guild_id.parse().ok().and_then(Id::new_checked),
+                timestamp.parse(),
+                channel_id.parse().ok().and_then(Id::new_checked),
+            ) {
+                (Some(guild_id), Ok(timestamp), Some(channel_id))
+                    if !CONFIG.record_path.is_empty() =>
+                {
+                    match rewind::get_guild(guild_id, timestamp)
+                        .await?
+                        .take_channel(channel_id)
+                    {
+                        Some(channel) => json_response(&channel),
+                        None => status_response(StatusCode::NOT_FOUND),
+                    }
+                }
+                (Some(_), Ok(_), Some(_)) => status_response(StatusCode::NOT_FOUND),
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "at", timestamp, "members", user_id]) => {
+            match (
+                guild_id.parse().ok().and_then(Id::new_checked),
+                timestamp.parse(),
+                user_id.parse().ok().and_then(Id::new_checked),
+            ) {
+                (Some(guild_id), Ok(timestamp), Some(user_id))
+                    if !CONFIG.record_path.is_empty() =>
+                {
+                    match rewind::get_guild(guild_id, timestamp)
+                        .await?
+                        .take_member(user_id)
+                    {
+                        Some(member) => json_response(&member),
+                        None => status_response(StatusCode::NOT_FOUND),
+                    }
+                }
+                (Some(_), Ok(_), Some(_)) => status_response(StatusCode::NOT_FOUND),
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "channels", "tree"]) => {
+            match guild_id.parse().ok().and_then(Id::new_checked) {
+                Some(guild_id) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = cache::get_channel_tree(&mut conn, guild_id).await?;
+                    release_connection(conn);
+
+                    json_response(&result)
+                }
+                None => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "members", user_id, "highest-role"]) => {
+            match (
+                guild_id.parse().ok().and_then(Id::new_checked),
+                user_id.parse().ok().and_then(Id::new_checked),
+            ) {
+                (Some(guild_id), Some(user_id)) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = cache::get_highest_role(&mut conn, guild_id, user_id).await?;
+                    release_connection(conn);
+
+                    match result {
+                        Some(role) => json_response(&role),
+                        None => status_response(StatusCode::NOT_FOUND),
+                    }
+                }
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "members", user_id, "can-act", target_id]) => {
+            match (
+                guild_id.parse().ok().and_then(Id::new_checked),
+                user_id.parse().ok().and_then(Id::new_checked),
+                target_id.parse().ok().and_then(Id::new_checked),
+            ) {
+                (Some(guild_id), Some(user_id), Some(target_id)) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = cache::can_act_on(&mut conn, guild_id, user_id, target_id).await?;
+                    release_connection(conn);
+
+                    match result {
+                        Some(info) => json_response(&info),
+                        None => status_response(StatusCode::NOT_FOUND),
+                    }
+                }
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "channels", "forums"]) => {
+            match guild_id.parse().ok().and_then(Id::new_checked) {
+                Some(guild_id) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = cache::get_forum_settings(&mut conn, guild_id).await?;
+                    release_connection(conn);
+
+                    json_response(&result)
+                }
+                None => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "integrations"]) => {
+            match guild_id.parse().ok().and_then(Id::new_checked) {
+                Some(guild_id) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = integrations::get_integrations(&mut conn, guild_id).await?;
+                    release_connection(conn);
+
+                    json_response(&result)
+                }
+                None => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "commands", "permissions"]) => {
+            match guild_id.parse().ok().and_then(Id::new_checked) {
+                Some(guild_id) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = integrations::get_command_permissions(&mut conn, guild_id).await?;
+                    release_connection(conn);
+
+                    json_response(&result)
+                }
+                None => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", guild_id, "moderation", user_id]) => {
+            match (
+                guild_id.parse().ok().and_then(Id::new_checked),
+                user_id.parse().ok().and_then(Id::new_checked),
+            ) {
+                (Some(guild_id), Some(user_id)) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = cache::get_moderation(&mut conn, guild_id, user_id).await?;
+                    release_connection(conn);
+
+                    json_response(&result)
+                }
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::POST, ["guilds", "leave"]) => {
+            let mut body = hyper::body::to_bytes(req.into_body()).await?.to_vec();
+            match simd_json::from_slice::<GuildLeaveInfo>(body.as_mut_slice()) {
+                Ok(info) if !info.guild_ids.is_empty() || info.max_members.is_some() => {
+                    json_response(&leave::start(&state.redis, info).await?)
+                }
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", "leave", nonce]) => match leave::get(nonce) {
+            Some(status) => json_response(&status),
+            None => status_response(StatusCode::NOT_FOUND),
+        },
+        (&Method::DELETE, ["guilds", "leave", nonce]) => match leave::cancel(nonce) {
+            Some(status) => json_response(&status),
+            None => status_response(StatusCode::NOT_FOUND),
+        },
+        (&Method::DELETE, ["guilds", guild_id]) => {
+            match guild_id.parse().ok().and_then(Id::new_checked) {
+                Some(guild_id) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = cache::purge_guild(&mut conn, guild_id).await?;
+                    release_connection(conn);
+
+                    json_response(&result)
+                }
+                None => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::POST, ["guilds", guild_id, "request-members"]) => {
+            let mut body = hyper::body::to_bytes(req.into_body()).await?.to_vec();
+            let info = simd_json::from_slice::<MemberRequestInfo>(body.as_mut_slice());
+            match (guild_id.parse().ok().and_then(Id::new_checked), info) {
+                (Some(guild_id), Ok(info)) if info.user_ids.len() <= 100 => {
+                    match chunks::request(state.clusters.as_slice(), guild_id, info).await {
+                        Ok(status) => json_response(&status),
+                        Err(ApiError::InvalidShard(_)) => status_response(StatusCode::NOT_FOUND),
+                        Err(err) => Err(err),
+                    }
+                }
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["guilds", _, "request-members", nonce]) => match chunks::get(nonce) {
+            Some(status) => json_response(&status),
+            None => status_response(StatusCode::NOT_FOUND),
+        },
+        (&Method::POST, ["guilds", guild_id, "migrate"]) => {
+            let mut body = hyper::body::to_bytes(req.into_body()).await?.to_vec();
+            let migration = simd_json::from_slice::<GuildMigrationInfo>(body.as_mut_slice());
+            match (guild_id.parse().ok().and_then(Id::new_checked), migration) {
+                (Some(guild_id), Ok(migration)) if migration.from != migration.to => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = cache::migrate_guild(&mut conn, guild_id, &migration).await?;
+                    release_connection(conn);
+
+                    json_response(&result)
+                }
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::DELETE, ["users", user_id]) => {
+            match user_id.parse().ok().and_then(Id::new_checked) {
+                Some(user_id) => {
+                    let mut conn = get_connection(&state.redis).await?;
+                    let result = cache::purge_user(&mut conn, user_id).await?;
+                    release_connection(conn);
+
+                    json_response(&result)
+                }
+                None => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::PUT, ["log", "filter"]) => {
+            let body = hyper::body::to_bytes(req.into_body()).await?;
+            match std::str::from_utf8(&body).map(|filter| logging::set_filter(filter.trim())) {
+                Ok(Ok(())) => status_response(StatusCode::NO_CONTENT),
+                _ => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::POST, ["log", "capture"]) => {
+            let mut body = hyper::body::to_bytes(req.into_body()).await?.to_vec();
+            match simd_json::from_slice::<CaptureInfo>(body.as_mut_slice()) {
+                Ok(capture) => match logging::set_capture(capture) {
+                    Ok(()) => status_response(StatusCode::NO_CONTENT),
+                    Err(ApiError::InvalidCommand(_)) => status_response(StatusCode::BAD_REQUEST),
+                    Err(err) => Err(err),
+                },
+                Err(_) => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        _ => status_response(StatusCode::NOT_FOUND),
+    }
+}
+
+fn get_tls_config() -> ApiResult<ServerConfig> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(
+        CONFIG.server_tls_cert.as_str(),
+    )?))?
+    .into_iter()
+    .map(Certificate)
+    .collect();
+
+    let key = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(
+        CONFIG.server_tls_key.as_str(),
+    )?))?
+    .into_iter()
+    .next()
+    .map(PrivateKey)
+    .ok_or(())?;
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    Ok(config)
+}
+
+async fn run_tls_server(addr: SocketAddr, state: Arc<ServerState>) -> ApiResult<()> {
+    let acceptor = TlsAcceptor::from(Arc::new(get_tls_config()?));
+    let listener = TcpListener::bind(addr).await?;
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!("Failed to accept connection: {:?}", err);
+                sleep(Duration::from_millis(SERVER_ACCEPT_BACKOFF as u64)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Failed to accept TLS connection: {:?}", err);
+                    return;
+                }
+            };
+
+            let service = service_fn(move |req| serve(req, state.clone()));
+            if let Err(err) = Http::new().serve_connection(stream, service).await {
+                warn!("Failed to serve TLS connection: {:?}", err);
+            }
+        });
+    }
+}
+
+async fn run_listener(addr: SocketAddr, state: Arc<ServerState>) -> ApiResult<()> {
+    if !CONFIG.server_tls_cert.is_empty() {
+        return run_tls_server(addr, state).await;
+    }
+
+    let make_svc = make_service_fn(move |_| {
+        let state = state.clone();
+        async move { Ok::<_, hyper::Error>(service_fn(move |req| serve(req, state.clone()))) }
+    });
+
+    Server::bind(&addr).serve(make_svc).await?;
+
+    Err(().into())
+}
+
+pub async fn run_server(clusters: Vec<Arc<Cluster>>, redis: redis::Client) -> ApiResult<()> {
+    let addr = SocketAddr::new(
+        IpAddr::from_str(CONFIG.prometheus_host.as_str())?,
+        CONFIG.prometheus_port as u16,
+    );
+
+    for listener in CONFIG.server_listeners.iter() {
+        let addr = SocketAddr::from_str(listener.addr.as_str())?;
+        let state = Arc::new(ServerState {
+            clusters: clusters.clone(),
+            redis: redis.clone(),
+            endpoints: listener.endpoints.clone(),
+        });
+
+        tokio::spawn(async move {
+            if let Err(err) = run_listener(addr, state).await {
+                warn!("Failed to run server on {}: {:?}", addr, err);
+            }
+        });
+    }
+
+    run_listener(
+        addr,
+        Arc::new(ServerState {
+            clusters,
+            redis,
+            endpoints: vec![],
+        }),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn privileged_routes_match() {
+        assert!(is_privileged(&Method::GET, &["config"]));
+        assert!(is_privileged(&Method::GET, &["guilds", "1", "export"]));
+        assert!(is_privileged(&Method::GET, &["guilds", "1", "events"]));
+        assert!(is_privileged(&Method::DELETE, &["guilds", "1"]));
+        assert!(is_privileged(&Method::DELETE, &["guilds", "leave", "abc"]));
+        assert!(is_privileged(&Method::POST, &["guilds", "1", "migrate"]));
+        assert!(is_privileged(&Method::PUT, &["log", "filter"]));
+        assert!(is_privileged(&Method::GET, &["shards", "0", "history"]));
+        assert!(is_privileged(
+            &Method::GET,
+            &["guilds", "1", "integrations"]
+        ));
+        assert!(is_privileged(
+            &Method::GET,
+            &["guilds", "1", "moderation", "2"]
+        ));
+        assert!(is_privileged(
+            &Method::GET,
+            &["guilds", "1", "at", "1650000000000", "members", "2"]
+        ));
+    }
+
+    #[test]
+    fn public_routes_do_not_match() {
+        assert!(!is_privileged(&Method::GET, &["healthcheck"]));
+        assert!(!is_privileged(&Method::GET, &["metrics"]));
+        assert!(!is_privileged(&Method::GET, &["guilds", "leave", "abc"]));
+        assert!(!is_privileged(
+            &Method::GET,
+            &["guilds", "1", "channels", "tree"]
+        ));
+        assert!(!is_privileged(&Method::GET, &["log", "filter"]));
+    }
+}
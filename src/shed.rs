@@ -0,0 +1,88 @@
+use crate::{
+    config::CONFIG,
+    constants::{DEGRADED_COLOR, RESUME_COLOR, SHED_INTERVAL},
+    metrics::GATEWAY_SHEDDING,
+    models::ApiResult,
+    utils::{log_discord, publish_event},
+};
+
+use lapin::{options::QueueDeclareOptions, types::FieldTable, Channel};
+use simd_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+static SHEDDING: AtomicBool = AtomicBool::new(false);
+
+pub fn is_shed(kind: &str) -> bool {
+    SHEDDING.load(Ordering::Relaxed) && CONFIG.shed_events.iter().any(|value| value == kind)
+}
+
+async fn get_depth(channel: &Channel) -> ApiResult<u32> {
+    let queue = channel
+        .queue_declare(
+            CONFIG.shed_queue.as_str(),
+            QueueDeclareOptions {
+                passive: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    Ok(queue.message_count())
+}
+
+async fn publish(channel: &Channel, shedding: bool, depth: u32) -> ApiResult<()> {
+    let data = json!({
+        "shedding": shedding,
+        "events": CONFIG.shed_events.clone(),
+        "depth": depth,
+    });
+
+    publish_event(channel, "GATEWAY_SHEDDING", data).await
+}
+
+pub async fn run_jobs(channel: Channel, channel_queue: Channel) {
+    if CONFIG.shed_events.is_empty() {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(SHED_INTERVAL as u64)).await;
+
+        let depth = match get_depth(&channel_queue).await {
+            Ok(depth) => depth,
+            Err(err) => {
+                warn!("Failed to get queue depth: {:?}", err);
+                continue;
+            }
+        };
+
+        let shedding = SHEDDING.load(Ordering::Relaxed);
+
+        if !shedding && depth as u64 >= CONFIG.shed_high {
+            SHEDDING.store(true, Ordering::Relaxed);
+            GATEWAY_SHEDDING.set(1);
+            warn!("Shedding {:?} (queue depth: {})", CONFIG.shed_events, depth);
+            log_discord(
+                DEGRADED_COLOR,
+                format!("Shedding events (queue depth: {})", depth),
+            );
+        } else if shedding && depth as u64 <= CONFIG.shed_low {
+            SHEDDING.store(false, Ordering::Relaxed);
+            GATEWAY_SHEDDING.set(0);
+            info!("Stopped shedding events (queue depth: {})", depth);
+            log_discord(
+                RESUME_COLOR,
+                format!("Stopped shedding events (queue depth: {})", depth),
+            );
+        } else {
+            continue;
+        }
+
+        if let Err(err) = publish(&channel, !shedding, depth).await {
+            warn!("Failed to publish shedding notice: {:?}", err);
+        }
+    }
+}
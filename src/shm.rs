@@ -0,0 +1,28 @@
+use crate::{config::CONFIG, models::ApiResult, ring::ShmWriter};
+
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use tracing::info;
+
+lazy_static! {
+    static ref WRITER: Mutex<Option<ShmWriter>> = Mutex::new(None);
+}
+
+pub fn init() -> ApiResult<()> {
+    if CONFIG.shm_path.is_empty() {
+        return Ok(());
+    }
+
+    let writer = ShmWriter::open(CONFIG.shm_path.as_str(), CONFIG.shm_size)?;
+    *WRITER.lock().unwrap() = Some(writer);
+
+    info!("Writing events to shared memory at {}", CONFIG.shm_path);
+
+    Ok(())
+}
+
+pub fn publish(payload: &[u8]) {
+    if let Some(writer) = WRITER.lock().unwrap().as_mut() {
+        writer.write(payload);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{
+    config::CONFIG,
+    constants::AUTOMOD_INTENTS,
+    intents, mapping,
+    metrics::GATEWAY_SHARDS_READY,
+    migration,
+    models::{ApiError, ApiResult, PayloadInfo},
+    utils::{
+        get_cluster_ranges, get_gateway_info, get_redis_connection, get_redis_info, get_shards,
+    },
+};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use simd_json::ValueAccess;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tokio::time::{sleep, Duration};
+use tracing::{error, info, warn};
+use twilight_gateway::{Cluster, Intents};
+
+lazy_static! {
+    static ref READY_SHARDS: Mutex<HashSet<u64>> = Mutex::new(HashSet::new());
+    static ref BACKFILL: Mutex<HashMap<u64, HashSet<String>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StartupProgress {
+    pub ready: u64,
+    pub total: u64,
+}
+
+pub fn set_ready(shard: u64) {
+    let mut ready = READY_SHARDS.lock().unwrap();
+    if !ready.insert(shard) {
+        return;
+    }
+
+    let total = get_shards();
+    GATEWAY_SHARDS_READY.set(ready.len() as i64);
+
+    if ready.len() as u64 == total {
+        info!("Startup complete ({}/{} shards ready)", ready.len(), total);
+    } else {
+        info!("Startup progress ({}/{} shards ready)", ready.len(), total);
+    }
+}
+
+pub fn get_progress() -> StartupProgress {
+    StartupProgress {
+        ready: READY_SHARDS.lock().unwrap().len() as u64,
+        total: get_shards(),
+    }
+}
+
+pub fn is_backfill(shard: u64, kind: &str, payload: &PayloadInfo) -> bool {
+    match kind {
+        "READY" => {
+            let guilds = payload
+                .d
+                .get_array("guilds")
+                .map(|guilds| {
+                    guilds
+                        .iter()
+                        .filter_map(|guild| guild.get_str("id").map(|id| id.to_owned()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            BACKFILL.lock().unwrap().insert(shard, guilds);
+            false
+        }
+        "GUILD_CREATE" => match payload.d.get_str("id") {
+            Some(id) => BACKFILL
+                .lock()
+                .unwrap()
+                .get_mut(&shard)
+                .is_some_and(|guilds| guilds.remove(id)),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_cluster_ready(cluster: &Cluster) -> bool {
+    let ready = READY_SHARDS.lock().unwrap();
+    if ready.len() as u64 >= get_shards() {
+        return true;
+    }
+
+    cluster
+        .shards()
+        .all(|shard| ready.contains(&shard.config().shard()[0]))
+}
+
+pub async fn run_clusters(clusters: Vec<Arc<Cluster>>) {
+    if CONFIG.startup_prewarm {
+        for cluster in clusters {
+            tokio::spawn(async move {
+                cluster.up().await;
+            });
+        }
+        return;
+    }
+
+    for (index, cluster) in clusters.into_iter().enumerate() {
+        info!("Starting up cluster {}", index);
+
+        let cluster_clone = cluster.clone();
+        tokio::spawn(async move {
+            cluster_clone.up().await;
+        });
+
+        while !is_cluster_ready(&cluster) {
+            sleep(Duration::from_millis(1000)).await;
+        }
+    }
+}
+
+pub async fn migrate_layout() -> ApiResult<()> {
+    if CONFIG.state_layout != 2 {
+        return Err(ApiError::InvalidConfig(vec![
+            "STATE_LAYOUT must be set to 2 to migrate the cache".to_owned(),
+        ]));
+    }
+
+    let redis = redis::Client::open(get_redis_info())?;
+    let mut conn = get_redis_connection(&redis).await?;
+
+    migration::load(&mut conn).await?;
+
+    info!("Migrating cache to layout 2");
+    let migrated = migration::migrate(&mut conn, Duration::ZERO).await?;
+    info!("Migrated {} keys", migrated);
+
+    Ok(())
+}
+
+pub async fn migrate_clusters() -> ApiResult<()> {
+    let redis = redis::Client::open(get_redis_info())?;
+    let mut conn = get_redis_connection(&redis).await?;
+
+    mapping::migrate(&mut conn).await
+}
+
+pub async fn dry_run() -> ApiResult<()> {
+    let mut problems = vec![];
+
+    if CONFIG.shards_start > CONFIG.shards_end || CONFIG.shards_end >= CONFIG.shards_total {
+        problems.push("Shard range is outside of the total shards".to_owned());
+    }
+
+    if CONFIG.clusters == 0 || CONFIG.clusters > get_shards() {
+        problems.push("Number of clusters must be between 1 and the number of shards".to_owned());
+    }
+
+    match intents::from_bits(CONFIG.intents) {
+        Some(intents) => {
+            let privileged = intents & (Intents::GUILD_MEMBERS | Intents::GUILD_PRESENCES);
+            if !privileged.is_empty() {
+                info!("Privileged intents requested: {:?}", privileged);
+            }
+            if !intents.contains(Intents::GUILD_MEMBERS) {
+                info!("Member requests are limited to queries without the GUILD_MEMBERS intent");
+            }
+            if CONFIG.state_presence && !intents.contains(Intents::GUILD_PRESENCES) {
+                warn!("Presence caching is enabled without the GUILD_PRESENCES intent");
+            }
+            if CONFIG.state_automod && intents.bits() & AUTOMOD_INTENTS == 0 {
+                warn!("Auto moderation caching is enabled without the auto moderation intents");
+            }
+        }
+        None => problems.push(format!("Invalid intents: {}", CONFIG.intents)),
+    }
+
+    if CONFIG.intents_fallback != 0 && intents::from_bits(CONFIG.intents_fallback).is_none() {
+        problems.push(format!(
+            "Invalid fallback intents: {}",
+            CONFIG.intents_fallback
+        ));
+    }
+
+    let redis = redis::Client::open(get_redis_info())?;
+    match get_redis_connection(&redis).await {
+        Ok(mut conn) => {
+            if let Err(err) = redis::cmd("PING").query_async::<_, String>(&mut conn).await {
+                problems.push(format!("Failed to ping Redis: {:?}", err));
+            }
+        }
+        Err(err) => problems.push(format!("Failed to connect to Redis: {:?}", err)),
+    }
+
+    let amqp = lapin::Connection::connect(
+        format!(
+            "amqp://{}:{}@{}:{}/%2f",
+            CONFIG.rabbit_username, CONFIG.rabbit_password, CONFIG.rabbit_host, CONFIG.rabbit_port
+        )
+        .as_str(),
+        lapin::ConnectionProperties::default(),
+    )
+    .await;
+    if let Err(err) = amqp {
+        problems.push(format!("Failed to connect to RabbitMQ: {:?}", err));
+    }
+
+    match get_gateway_info().await {
+        Ok(info) => {
+            let limit = info.session_start_limit;
+            info!(
+                "Discord recommends {} shards (sessions remaining: {}/{}, max concurrency: {})",
+                info.shards, limit.remaining, limit.total, limit.max_concurrency
+            );
+
+            if CONFIG.shards_total < info.shards {
+                warn!("Running fewer shards than recommended by Discord");
+            }
+            if CONFIG.shards_concurrency > limit.max_concurrency {
+                problems.push(format!(
+                    "Shard concurrency exceeds the maximum of {}",
+                    limit.max_concurrency
+                ));
+            }
+            if limit.remaining < get_shards() {
+                problems.push(format!(
+                    "Not enough sessions remaining to identify {} shards",
+                    get_shards()
+                ));
+            }
+        }
+        Err(err) => problems.push(format!("Failed to get gateway information: {:?}", err)),
+    }
+
+    if CONFIG.clusters > 0 && CONFIG.clusters <= get_shards() {
+        for (index, (from, to)) in get_cluster_ranges().into_iter().enumerate() {
+            info!("Cluster {}: shards {} to {}", index, from, to);
+        }
+    }
+
+    if problems.is_empty() {
+        info!("Dry run completed successfully");
+        return Ok(());
+    }
+
+    for problem in problems.iter() {
+        error!("{}", problem);
+    }
+
+    Err(ApiError::InvalidConfig(problems))
+}
@@ -0,0 +1,127 @@
+use crate::{config::CONFIG, models::ApiResult, utils::get_redis_connection};
+
+use lazy_static::lazy_static;
+use redis::{aio::ConnectionLike, Cmd, Pipeline, RedisFuture, RedisResult, Value};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Mutex,
+};
+
+lazy_static! {
+    static ref STATUS: Mutex<Vec<redis::aio::Connection>> = Mutex::new(vec![]);
+    static ref EXPIRY: Mutex<Vec<redis::aio::Connection>> = Mutex::new(vec![]);
+}
+
+pub enum TargetConnection<'a> {
+    Default(&'a mut redis::aio::Connection),
+    Separate {
+        conn: Option<redis::aio::Connection>,
+        pool: &'static Mutex<Vec<redis::aio::Connection>>,
+        failed: bool,
+    },
+}
+
+impl TargetConnection<'_> {
+    fn check<T>(&mut self, result: &RedisResult<T>) {
+        if let (Self::Separate { failed, .. }, Err(err)) = (self, result) {
+            if err.is_io_error() || err.is_connection_dropped() {
+                *failed = true;
+            }
+        }
+    }
+}
+
+impl Deref for TargetConnection<'_> {
+    type Target = redis::aio::Connection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Default(conn) => conn,
+            Self::Separate { conn, .. } => conn.as_ref().unwrap(),
+        }
+    }
+}
+
+impl DerefMut for TargetConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Self::Default(conn) => conn,
+            Self::Separate { conn, .. } => conn.as_mut().unwrap(),
+        }
+    }
+}
+
+impl Drop for TargetConnection<'_> {
+    fn drop(&mut self) {
+        if let Self::Separate {
+            conn,
+            pool,
+            failed: false,
+        } = self
+        {
+            if let Some(conn) = conn.take() {
+                pool.lock().unwrap().push(conn);
+            }
+        }
+    }
+}
+
+impl ConnectionLike for TargetConnection<'_> {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let result = self.deref_mut().req_packed_command(cmd).await;
+            self.check(&result);
+            result
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let result = self
+                .deref_mut()
+                .req_packed_commands(cmd, offset, count)
+                .await;
+            self.check(&result);
+            result
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.deref().get_db()
+    }
+}
+
+async fn get<'a>(
+    conn: &'a mut redis::aio::Connection,
+    pool: &'static Mutex<Vec<redis::aio::Connection>>,
+    url: &str,
+) -> ApiResult<TargetConnection<'a>> {
+    if url.is_empty() {
+        return Ok(TargetConnection::Default(conn));
+    }
+
+    let pooled = pool.lock().unwrap().pop();
+    let conn = match pooled {
+        Some(conn) => conn,
+        None => get_redis_connection(&redis::Client::open(url)?).await?,
+    };
+
+    Ok(TargetConnection::Separate {
+        conn: Some(conn),
+        pool,
+        failed: false,
+    })
+}
+
+pub async fn status(conn: &mut redis::aio::Connection) -> ApiResult<TargetConnection<'_>> {
+    get(conn, &STATUS, CONFIG.redis_status_url.as_str()).await
+}
+
+pub async fn expiry(conn: &mut redis::aio::Connection) -> ApiResult<TargetConnection<'_>> {
+    get(conn, &EXPIRY, CONFIG.redis_expiry_url.as_str()).await
+}
@@ -0,0 +1,130 @@
+use crate::{
+    config::CONFIG,
+    constants::{GUILD_SIZES_KEY, THRESHOLDS_KEY, THRESHOLD_FLUSH_INTERVAL},
+    models::ApiResult,
+    utils::get_tenant_key,
+};
+
+use lazy_static::lazy_static;
+use redis::AsyncCommands;
+use std::{collections::HashMap, mem, sync::Mutex};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+lazy_static! {
+    static ref SIZES: Mutex<HashMap<u64, (u64, u64)>> = Mutex::new(HashMap::new());
+    static ref AVERAGES: Mutex<HashMap<u64, u64>> = Mutex::new(HashMap::new());
+    static ref THRESHOLDS: Mutex<HashMap<u64, u64>> = Mutex::new(HashMap::new());
+}
+
+pub fn record(shard: u64, kind: &str, size: usize) {
+    if CONFIG.large_threshold_target == 0 || kind != "GUILD_CREATE" {
+        return;
+    }
+
+    let mut sizes = SIZES.lock().unwrap();
+    let entry = sizes.entry(shard).or_insert((0, 0));
+    entry.0 += size as u64;
+    entry.1 += 1;
+}
+
+pub fn get(from: u64, to: u64) -> u64 {
+    let threshold = CONFIG
+        .large_thresholds
+        .iter()
+        .find(|[start, end, _]| *start <= from && to <= *end)
+        .map_or(CONFIG.large_threshold, |[_, _, threshold]| *threshold);
+
+    if CONFIG.large_threshold_target == 0 {
+        return threshold;
+    }
+
+    let mut thresholds = THRESHOLDS.lock().unwrap();
+    let threshold = (from..=to)
+        .filter_map(|shard| thresholds.get(&shard).copied())
+        .min()
+        .map_or(threshold, |tuned| tuned.min(threshold));
+
+    let averages = AVERAGES.lock().unwrap();
+    let sizes: Vec<u64> = (from..=to)
+        .filter_map(|shard| averages.get(&shard).copied())
+        .collect();
+
+    let mut tuned = threshold;
+    if !sizes.is_empty() {
+        let average = sizes.iter().sum::<u64>() / sizes.len() as u64;
+        if average > CONFIG.large_threshold_target {
+            tuned = (threshold * CONFIG.large_threshold_target / average).max(50);
+            info!(
+                "Lowering large threshold of shards {} to {} from {} to {} (average GUILD_CREATE \
+                 size: {})",
+                from, to, threshold, tuned, average
+            );
+        }
+    }
+
+    for shard in from..=to {
+        thresholds.insert(shard, tuned);
+    }
+
+    tuned
+}
+
+pub async fn load(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    if CONFIG.large_threshold_target == 0 {
+        return Ok(());
+    }
+
+    let averages: HashMap<u64, u64> = conn.hgetall(get_tenant_key(GUILD_SIZES_KEY)).await?;
+    *AVERAGES.lock().unwrap() = averages;
+
+    let thresholds: HashMap<u64, u64> = conn.hgetall(get_tenant_key(THRESHOLDS_KEY)).await?;
+    *THRESHOLDS.lock().unwrap() = thresholds;
+
+    Ok(())
+}
+
+async fn flush(conn: &mut redis::aio::Connection) -> ApiResult<()> {
+    let sizes = mem::take(&mut *SIZES.lock().unwrap());
+
+    let averages: Vec<(u64, u64)> = sizes
+        .into_iter()
+        .map(|(shard, (total, count))| (shard, total / count))
+        .collect();
+    if averages.is_empty() {
+        return Ok(());
+    }
+
+    let thresholds: Vec<(u64, u64)> = {
+        let thresholds = THRESHOLDS.lock().unwrap();
+        averages
+            .iter()
+            .filter_map(|(shard, _)| thresholds.get(shard).map(|threshold| (*shard, *threshold)))
+            .collect()
+    };
+
+    let mut pipe = redis::pipe();
+    pipe.hset_multiple(get_tenant_key(GUILD_SIZES_KEY), averages.as_slice())
+        .ignore();
+    if !thresholds.is_empty() {
+        pipe.hset_multiple(get_tenant_key(THRESHOLDS_KEY), thresholds.as_slice())
+            .ignore();
+    }
+    let _: () = pipe.query_async(conn).await?;
+
+    Ok(())
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection) {
+    if CONFIG.large_threshold_target == 0 {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(THRESHOLD_FLUSH_INTERVAL as u64)).await;
+
+        if let Err(err) = flush(conn).await {
+            warn!("Failed to save GUILD_CREATE sizes: {:?}", err);
+        }
+    }
+}
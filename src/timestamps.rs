@@ -0,0 +1,66 @@
+use crate::config::CONFIG;
+
+use simd_json::{owned::Value, ValueAccess};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
+
+fn is_timestamp_key(key: &str) -> bool {
+    key.ends_with("timestamp")
+        || key.ends_with("_at")
+        || key.ends_with("_until")
+        || key == "premium_since"
+}
+
+fn format(datetime: OffsetDateTime) -> Option<Value> {
+    datetime
+        .to_offset(UtcOffset::UTC)
+        .format(&Rfc3339)
+        .ok()
+        .map(Value::from)
+}
+
+fn normalize_value(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if let Value::String(string) = &*value {
+                    if is_timestamp_key(key) {
+                        if let Some(normalized) = OffsetDateTime::parse(string, &Rfc3339)
+                            .ok()
+                            .and_then(format)
+                        {
+                            *value = normalized;
+                        }
+                        continue;
+                    }
+                }
+
+                normalize_value(value);
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(normalize_value),
+        _ => {}
+    }
+}
+
+pub fn normalize(kind: &str, data: &mut Value) {
+    if !CONFIG.normalize_timestamps {
+        return;
+    }
+
+    normalize_value(data);
+
+    // The timestamp of typing events is in seconds since the unix epoch
+    if kind == "TYPING_START" {
+        if let Value::Object(object) = data {
+            if let Some(timestamp) = object.get_mut("timestamp") {
+                if let Some(normalized) = timestamp
+                    .as_i64()
+                    .and_then(|seconds| OffsetDateTime::from_unix_timestamp(seconds).ok())
+                    .and_then(format)
+                {
+                    *timestamp = normalized;
+                }
+            }
+        }
+    }
+}
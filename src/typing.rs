@@ -0,0 +1,81 @@
+use crate::{
+    config::CONFIG, deploy, metrics::GATEWAY_AGGREGATED_EVENTS, models::PayloadInfo,
+    utils::publish_event,
+};
+
+use lapin::Channel;
+use lazy_static::lazy_static;
+use simd_json::{json, ValueAccess};
+use std::{
+    collections::{BTreeSet, HashMap},
+    mem,
+    sync::Mutex,
+};
+use tokio::time::{sleep, Duration};
+use tracing::warn;
+
+#[derive(Default)]
+struct TypingActivity {
+    guild_id: Option<String>,
+    user_ids: BTreeSet<String>,
+    events: u64,
+}
+
+lazy_static! {
+    static ref ACTIVITY: Mutex<HashMap<String, TypingActivity>> = Mutex::new(HashMap::new());
+}
+
+pub fn is_aggregated(kind: &str, payload: &PayloadInfo) -> bool {
+    if CONFIG.typing_window == 0 || kind != "TYPING_START" {
+        return false;
+    }
+
+    let (channel_id, user_id) = match (
+        payload.d.get_str("channel_id"),
+        payload.d.get_str("user_id"),
+    ) {
+        (Some(channel_id), Some(user_id)) => (channel_id, user_id),
+        _ => return false,
+    };
+
+    let mut activity = ACTIVITY.lock().unwrap();
+    let entry = activity.entry(channel_id.to_owned()).or_default();
+    entry.guild_id = payload
+        .d
+        .get_str("guild_id")
+        .map(|guild_id| guild_id.to_owned());
+    entry.user_ids.insert(user_id.to_owned());
+    entry.events += 1;
+
+    GATEWAY_AGGREGATED_EVENTS.with_label_values(&[kind]).inc();
+
+    true
+}
+
+pub async fn run_jobs(channel: Channel) {
+    if CONFIG.typing_window == 0 {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(CONFIG.typing_window)).await;
+
+        let activity = mem::take(&mut *ACTIVITY.lock().unwrap());
+        if deploy::is_draining() {
+            continue;
+        }
+
+        for (channel_id, entry) in activity {
+            let data = json!({
+                "guild_id": entry.guild_id,
+                "channel_id": channel_id,
+                "user_ids": entry.user_ids.into_iter().collect::<Vec<_>>(),
+                "events": entry.events,
+            });
+
+            if let Err(err) = publish_event(&channel, "TYPING_ACTIVITY", data).await {
+                warn!("Failed to publish typing activity: {:?}", err);
+            }
+        }
+    }
+}
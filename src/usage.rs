@@ -0,0 +1,81 @@
+use crate::{
+    config::CONFIG,
+    constants::{usage_key, USAGE_FLUSH_INTERVAL},
+    models::{ApiResult, PayloadInfo},
+};
+
+use lazy_static::lazy_static;
+use simd_json::ValueAccess;
+use std::{
+    collections::HashMap,
+    mem,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::time::{sleep, Duration};
+use tracing::warn;
+
+lazy_static! {
+    static ref COUNTERS: Mutex<HashMap<(String, String), u64>> = Mutex::new(HashMap::new());
+}
+
+pub fn record(kind: &str, payload: &PayloadInfo) {
+    if !CONFIG.usage_enabled {
+        return;
+    }
+
+    if let Some(guild_id) = payload.d.get_str("guild_id") {
+        *COUNTERS
+            .lock()
+            .unwrap()
+            .entry((guild_id.to_owned(), kind.to_owned()))
+            .or_default() += 1;
+    }
+}
+
+async fn flush(
+    conn: &mut redis::aio::Connection,
+    counters: &HashMap<(String, String), u64>,
+) -> ApiResult<()> {
+    let hour = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 3600;
+
+    let mut pipe = redis::pipe();
+
+    for ((guild_id, kind), count) in counters {
+        let key = usage_key(guild_id, hour);
+        pipe.hincr(&key, kind, *count).ignore();
+        pipe.expire(&key, CONFIG.usage_ttl as usize).ignore();
+    }
+
+    let _: () = pipe.query_async(conn).await?;
+
+    Ok(())
+}
+
+pub async fn run_jobs(conn: &mut redis::aio::Connection) {
+    if !CONFIG.usage_enabled {
+        return;
+    }
+
+    loop {
+        sleep(Duration::from_millis(USAGE_FLUSH_INTERVAL as u64)).await;
+
+        let counters = mem::take(&mut *COUNTERS.lock().unwrap());
+        if counters.is_empty() {
+            continue;
+        }
+
+        if let Err(err) = flush(conn, &counters).await {
+            warn!("Failed to flush usage counters: {:?}", err);
+
+            let mut current = COUNTERS.lock().unwrap();
+            for (key, count) in counters {
+                *current.entry(key).or_default() += count;
+            }
+        }
+    }
+}
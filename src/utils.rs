@@ -1,14 +1,30 @@
 use crate::{
     cache,
     config::CONFIG,
-    constants::{channel_key, private_channel_key, SESSIONS_KEY, SHARDS_KEY},
-    models::{ApiResult, SessionInfo},
+    conflict,
+    constants::{EXCHANGE, REDACTED_KEYS, REGISTRY, SESSIONS_KEY, SHARDS_KEY, SIGNATURE_HEADER},
+    identify, intents,
+    keys::{channel_key, private_channel_key},
+    mapping,
+    models::{
+        ApiResult, ClusterInfo, ConfigInfo, PayloadInfo, RegistryInfo, SchemaField, SchemaInfo,
+        SessionInfo,
+    },
+    rest::{self, CLIENT},
+    resume, targets, threshold,
 };
 
 use futures_util::Stream;
-use lazy_static::lazy_static;
+use hmac::{Hmac, Mac};
+use lapin::{
+    options::BasicPublishOptions,
+    types::{AMQPValue, FieldTable},
+    BasicProperties, Channel as AmqpChannel,
+};
+use redis::{ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
 use serde::Serialize;
-use simd_json::owned::Value;
+use sha2::Sha256;
+use simd_json::{owned::Value, ValueAccess};
 use std::{collections::HashMap, fmt::Debug, future::Future, pin::Pin, sync::Arc, time::Duration};
 use time::OffsetDateTime;
 use tokio::{
@@ -16,46 +32,47 @@ use tokio::{
         mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
         oneshot::{self, Sender},
     },
-    time::sleep,
+    time::{sleep, timeout},
 };
 use tracing::warn;
 use twilight_gateway::{
-    cluster::ShardScheme, queue::Queue, shard::ResumeSession, Cluster, Event, EventTypeFlags,
-    Intents,
+    cluster::ShardScheme,
+    queue::Queue,
+    shard::{Information, ResumeSession},
+    Cluster, Event, EventTypeFlags, Intents,
 };
-use twilight_http::client::Client;
 use twilight_model::{
     channel::{embed::Embed, Channel},
     datetime::Timestamp,
     gateway::{
+        connection_info::BotConnectionInfo,
         payload::outgoing::update_presence::UpdatePresencePayload,
         presence::{Activity, UserOrId},
+        OpCode,
     },
     id::{marker::UserMarker, Id},
 };
 
-lazy_static! {
-    static ref CLIENT: Client = Client::new(CONFIG.bot_token.clone());
-}
-
 #[derive(Clone, Debug)]
-pub struct LocalQueue(UnboundedSender<Sender<()>>);
+pub struct LocalQueue(UnboundedSender<(u64, Sender<()>)>, Duration);
 
 impl LocalQueue {
     pub fn new(duration: Duration) -> Self {
         let (tx, rx) = unbounded_channel();
-        tokio::spawn(waiter(rx, duration));
+        tokio::spawn(waiter(rx, 0, duration));
 
-        Self(tx)
+        Self(tx, duration)
     }
 }
 
 impl Queue for LocalQueue {
-    fn request(&'_ self, [_, _]: [u64; 2]) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+    fn request(&'_ self, [shard, _]: [u64; 2]) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
         Box::pin(async move {
             let (tx, rx) = oneshot::channel();
 
-            if let Err(err) = self.0.clone().send(tx) {
+            conflict::wait(shard).await;
+            identify::record_waiting(0, shard, self.1);
+            if let Err(err) = self.0.clone().send((shard, tx)) {
                 warn!("skipping, send failed: {:?}", err);
                 return;
             }
@@ -66,18 +83,18 @@ impl Queue for LocalQueue {
 }
 
 #[derive(Debug)]
-pub struct LargeBotQueue(Vec<UnboundedSender<Sender<()>>>);
+pub struct LargeBotQueue(Vec<UnboundedSender<(u64, Sender<()>)>>, Duration);
 
 impl LargeBotQueue {
     pub fn new(buckets: usize, duration: Duration) -> Self {
         let mut queues = Vec::with_capacity(buckets);
-        for _ in 0..buckets {
+        for bucket in 0..buckets {
             let (tx, rx) = unbounded_channel();
-            tokio::spawn(waiter(rx, duration));
+            tokio::spawn(waiter(rx, bucket as u64, duration));
             queues.push(tx)
         }
 
-        Self(queues)
+        Self(queues, duration)
     }
 }
 
@@ -88,7 +105,9 @@ impl Queue for LargeBotQueue {
         let (tx, rx) = oneshot::channel();
 
         Box::pin(async move {
-            if let Err(err) = self.0[bucket].clone().send(tx) {
+            conflict::wait(shard_id[0]).await;
+            identify::record_waiting(bucket as u64, shard_id[0], self.1);
+            if let Err(err) = self.0[bucket].clone().send((shard_id[0], tx)) {
                 warn!("skipping, send failed: {:?}", err);
                 return;
             }
@@ -98,8 +117,9 @@ impl Queue for LargeBotQueue {
     }
 }
 
-async fn waiter(mut rx: UnboundedReceiver<Sender<()>>, duration: Duration) {
-    while let Some(req) = rx.recv().await {
+async fn waiter(mut rx: UnboundedReceiver<(u64, Sender<()>)>, bucket: u64, duration: Duration) {
+    while let Some((shard, req)) = rx.recv().await {
+        identify::record_identified(bucket, shard);
         if let Err(err) = req.send(()) {
             warn!("skipping, send failed: {:?}", err);
         }
@@ -123,6 +143,27 @@ pub fn get_channel_key(channel: &Channel) -> String {
     }
 }
 
+pub fn get_activity(name: String) -> Activity {
+    Activity {
+        application_id: None,
+        assets: None,
+        buttons: Vec::new(),
+        created_at: None,
+        details: None,
+        emoji: None,
+        flags: None,
+        id: None,
+        instance: None,
+        kind: CONFIG.activity_type,
+        name,
+        party: None,
+        secrets: None,
+        state: None,
+        timestamps: None,
+        url: None,
+    }
+}
+
 pub async fn get_clusters(
     resumes: HashMap<u64, ResumeSession>,
     queue: Arc<dyn Queue>,
@@ -130,12 +171,53 @@ pub async fn get_clusters(
     Vec<Arc<Cluster>>,
     Vec<impl Stream<Item = (u64, Event)> + Send + Sync + Unpin + 'static>,
 )> {
+    let ranges = get_cluster_ranges();
+    let mut clusters = Vec::with_capacity(ranges.len());
+    let mut events = Vec::with_capacity(ranges.len());
+
+    for (index, (from, to)) in ranges.into_iter().enumerate() {
+        let (cluster, event) = Cluster::builder(CONFIG.bot_token.clone(), intents::get())
+            .gateway_url(Some(resume::get_gateway_url(index, from, to, |shard| {
+                resumes.contains_key(&shard)
+            })))
+            .shard_scheme(ShardScheme::Range {
+                from,
+                to,
+                total: CONFIG.shards_total,
+            })
+            .queue(queue.clone())
+            .presence(
+                UpdatePresencePayload::new(
+                    vec![get_activity(CONFIG.activity_name.clone())],
+                    false,
+                    None,
+                    CONFIG.status,
+                )
+                .unwrap(),
+            )
+            .large_threshold(threshold::get(from, to))?
+            .resume_sessions(resumes.clone())
+            .event_types(get_event_flags())
+            .build()
+            .await?;
+
+        clusters.push(Arc::new(cluster));
+        events.push(event);
+    }
+
+    Ok((clusters, events))
+}
+
+pub fn get_cluster_ranges() -> Vec<(u64, u64)> {
+    mapping::get().unwrap_or_else(get_default_cluster_ranges)
+}
+
+pub fn get_default_cluster_ranges() -> Vec<(u64, u64)> {
     let shards = get_shards();
     let base = shards / CONFIG.clusters;
     let extra = shards % CONFIG.clusters;
 
-    let mut clusters = Vec::with_capacity(CONFIG.clusters as usize);
-    let mut events = Vec::with_capacity(CONFIG.clusters as usize);
+    let mut ranges = Vec::with_capacity(CONFIG.clusters as usize);
     let mut last_index = CONFIG.shards_start;
 
     for i in 0..CONFIG.clusters {
@@ -145,56 +227,49 @@ pub async fn get_clusters(
             last_index + base - 1
         };
 
-        let (cluster, event) = Cluster::builder(
-            CONFIG.bot_token.clone(),
-            Intents::from_bits(CONFIG.intents).unwrap(),
-        )
-        .gateway_url(Some("wss://gateway.discord.gg".to_owned()))
-        .shard_scheme(ShardScheme::Range {
-            from: last_index,
-            to: index,
-            total: CONFIG.shards_total,
-        })
-        .queue(queue.clone())
-        .presence(
-            UpdatePresencePayload::new(
-                vec![Activity {
-                    application_id: None,
-                    assets: None,
-                    buttons: Vec::new(),
-                    created_at: None,
-                    details: None,
-                    emoji: None,
-                    flags: None,
-                    id: None,
-                    instance: None,
-                    kind: CONFIG.activity_type,
-                    name: CONFIG.activity_name.clone(),
-                    party: None,
-                    secrets: None,
-                    state: None,
-                    timestamps: None,
-                    url: None,
-                }],
-                false,
-                None,
-                CONFIG.status,
-            )
-            .unwrap(),
-        )
-        .large_threshold(CONFIG.large_threshold)?
-        .resume_sessions(resumes.clone())
-        .event_types(get_event_flags())
-        .build()
-        .await?;
-
-        clusters.push(Arc::new(cluster));
-        events.push(event);
+        ranges.push((last_index, index));
 
         last_index = index + 1;
     }
 
-    Ok((clusters, events))
+    ranges
+}
+
+pub async fn get_gateway_info() -> ApiResult<BotConnectionInfo> {
+    rest::wait().await;
+
+    Ok(CLIENT.gateway().authed().exec().await?.model().await?)
+}
+
+pub fn get_redis_info() -> ConnectionInfo {
+    let addr = if CONFIG.redis_tls {
+        ConnectionAddr::TcpTls {
+            host: CONFIG.redis_host.clone(),
+            port: CONFIG.redis_port as u16,
+            insecure: false,
+        }
+    } else {
+        ConnectionAddr::Tcp(CONFIG.redis_host.clone(), CONFIG.redis_port as u16)
+    };
+
+    ConnectionInfo {
+        addr,
+        redis: RedisConnectionInfo {
+            db: CONFIG.redis_database,
+            username: Some(CONFIG.redis_username.clone()).filter(|value| !value.is_empty()),
+            password: Some(CONFIG.redis_password.clone()).filter(|value| !value.is_empty()),
+        },
+    }
+}
+
+pub async fn get_redis_connection(client: &redis::Client) -> ApiResult<redis::aio::Connection> {
+    let conn = timeout(
+        Duration::from_millis(CONFIG.redis_timeout),
+        client.get_async_connection(),
+    )
+    .await??;
+
+    Ok(conn)
 }
 
 pub fn get_queue() -> Arc<dyn Queue> {
@@ -210,19 +285,38 @@ pub fn get_queue() -> Arc<dyn Queue> {
 pub async fn get_resume_sessions(
     conn: &mut redis::aio::Connection,
 ) -> ApiResult<HashMap<u64, ResumeSession>> {
-    let shards: u64 = cache::get(conn, SHARDS_KEY).await?.unwrap_or_default();
+    let mut conn = targets::status(conn).await?;
+    let conn = &mut *conn;
+
+    let shards: u64 = cache::get(conn, get_tenant_key(SHARDS_KEY))
+        .await?
+        .unwrap_or_default();
     if shards != CONFIG.shards_total || !CONFIG.resume {
         return Ok(HashMap::new());
     }
 
-    let sessions: HashMap<String, SessionInfo> =
-        cache::get(conn, SESSIONS_KEY).await?.unwrap_or_default();
+    let key = get_tenant_key(SESSIONS_KEY);
+    let sessions: HashMap<u64, SessionInfo> = if cache::is_legacy_key(conn, key.as_str()).await? {
+        let sessions: HashMap<String, SessionInfo> =
+            cache::get(conn, key.as_str()).await?.unwrap_or_default();
+        cache::del(conn, key.as_str()).await?;
+
+        sessions
+            .into_iter()
+            .filter_map(|(k, v)| Some((k.parse().ok()?, v)))
+            .collect()
+    } else {
+        cache::get_shard_hash(conn, key.as_str()).await?
+    };
+
+    resume::load(&sessions);
 
     Ok(sessions
         .into_iter()
+        .filter(|(k, _)| (CONFIG.shards_start..=CONFIG.shards_end).contains(k))
         .map(|(k, v)| {
             (
-                k.parse().unwrap(),
+                k,
                 ResumeSession {
                     session_id: v.session_id,
                     sequence: v.sequence,
@@ -232,6 +326,70 @@ pub async fn get_resume_sessions(
         .collect())
 }
 
+pub fn get_sessions(clusters: &[Arc<Cluster>]) -> Vec<(u64, SessionInfo)> {
+    let mut sessions = vec![];
+    for cluster in clusters {
+        for (shard, info) in cluster.info() {
+            sessions.push((
+                shard,
+                SessionInfo {
+                    session_id: info.session_id().unwrap_or_default().to_owned(),
+                    sequence: info.seq(),
+                    resume_gateway_url: resume::get(shard),
+                },
+            ));
+        }
+    }
+
+    sessions
+}
+
+pub async fn dump_sessions(
+    conn: &mut redis::aio::Connection,
+    clusters: &[Arc<Cluster>],
+) -> ApiResult<()> {
+    let sessions = get_sessions(clusters);
+
+    let mut conn = targets::status(conn).await?;
+    cache::set_shard_hash(
+        &mut conn,
+        get_tenant_key(SESSIONS_KEY).as_str(),
+        sessions.as_slice(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn save_sessions(
+    conn: &mut redis::aio::Connection,
+    clusters: &[Arc<Cluster>],
+) -> ApiResult<()> {
+    let mut sessions = vec![];
+    for cluster in clusters {
+        for (key, value) in cluster.down_resumable().into_iter() {
+            sessions.push((
+                key,
+                SessionInfo {
+                    session_id: value.session_id,
+                    sequence: value.sequence,
+                    resume_gateway_url: resume::get(key),
+                },
+            ));
+        }
+    }
+
+    let mut conn = targets::status(conn).await?;
+    cache::set_shard_hash(
+        &mut conn,
+        get_tenant_key(SESSIONS_KEY).as_str(),
+        sessions.as_slice(),
+    )
+    .await?;
+
+    Ok(())
+}
+
 pub fn get_event_flags() -> EventTypeFlags {
     let mut event_flags = EventTypeFlags::GATEWAY_HELLO
         | EventTypeFlags::GATEWAY_INVALIDATE_SESSION
@@ -263,7 +421,9 @@ pub fn get_event_flags() -> EventTypeFlags {
             | EventTypeFlags::VOICE_STATE_UPDATE;
 
         if CONFIG.state_member {
-            event_flags |= EventTypeFlags::MEMBER_ADD
+            event_flags |= EventTypeFlags::INTERACTION_CREATE
+                | EventTypeFlags::MEMBER_ADD
+                | EventTypeFlags::MESSAGE_CREATE
                 | EventTypeFlags::MEMBER_REMOVE
                 | EventTypeFlags::MEMBER_CHUNK
                 | EventTypeFlags::MEMBER_UPDATE;
@@ -279,11 +439,262 @@ pub fn get_event_flags() -> EventTypeFlags {
                 | EventTypeFlags::MESSAGE_DELETE_BULK
                 | EventTypeFlags::MESSAGE_UPDATE;
         }
+
+        if CONFIG.state_moderation {
+            event_flags |= EventTypeFlags::BAN_ADD
+                | EventTypeFlags::BAN_REMOVE
+                | EventTypeFlags::MEMBER_UPDATE;
+        }
     }
 
     event_flags
 }
 
+pub fn get_state_events() -> Vec<&'static str> {
+    let mut events = vec![];
+
+    if CONFIG.state_enabled {
+        events.extend([
+            "CHANNEL_CREATE",
+            "CHANNEL_DELETE",
+            "CHANNEL_PINS_UPDATE",
+            "CHANNEL_UPDATE",
+            "GUILD_CREATE",
+            "GUILD_DELETE",
+            "GUILD_EMOJIS_UPDATE",
+            "GUILD_UPDATE",
+            "GUILD_ROLE_CREATE",
+            "GUILD_ROLE_DELETE",
+            "GUILD_ROLE_UPDATE",
+            "USER_UPDATE",
+            "VOICE_STATE_UPDATE",
+        ]);
+
+        if CONFIG.state_member {
+            events.extend([
+                "GUILD_MEMBER_ADD",
+                "GUILD_MEMBER_REMOVE",
+                "GUILD_MEMBERS_CHUNK",
+                "GUILD_MEMBER_UPDATE",
+                "INTERACTION_CREATE",
+                "MESSAGE_CREATE",
+            ]);
+
+            if CONFIG.state_presence {
+                events.push("PRESENCE_UPDATE");
+            }
+        }
+
+        if CONFIG.state_message {
+            events.extend([
+                "MESSAGE_CREATE",
+                "MESSAGE_DELETE",
+                "MESSAGE_DELETE_BULK",
+                "MESSAGE_UPDATE",
+            ]);
+        }
+
+        if CONFIG.state_moderation {
+            events.extend(["GUILD_BAN_ADD", "GUILD_BAN_REMOVE", "GUILD_MEMBER_UPDATE"]);
+        }
+
+        if CONFIG.state_automod {
+            events.extend([
+                "AUTO_MODERATION_ACTION_EXECUTION",
+                "AUTO_MODERATION_RULE_CREATE",
+                "AUTO_MODERATION_RULE_DELETE",
+                "AUTO_MODERATION_RULE_UPDATE",
+            ]);
+        }
+
+        if CONFIG.state_integrations {
+            events.extend([
+                "APPLICATION_COMMAND_PERMISSIONS_UPDATE",
+                "INTEGRATION_CREATE",
+                "INTEGRATION_DELETE",
+                "INTEGRATION_UPDATE",
+            ]);
+        }
+    }
+
+    events.sort_unstable();
+    events.dedup();
+
+    events
+}
+
+pub fn get_envelope_exchange(version: u64) -> String {
+    format!("{}.v{}", EXCHANGE, version)
+}
+
+pub fn get_envelopes() -> Vec<(String, u64)> {
+    let mut envelopes = vec![(EXCHANGE.to_owned(), CONFIG.envelope_version)];
+
+    if CONFIG.envelope_dual_version != 0 {
+        envelopes.push((
+            get_envelope_exchange(CONFIG.envelope_dual_version),
+            CONFIG.envelope_dual_version,
+        ));
+    }
+
+    envelopes
+}
+
+pub fn get_registry() -> RegistryInfo {
+    RegistryInfo {
+        tenant: CONFIG.tenant.clone(),
+        entries: REGISTRY.to_vec(),
+    }
+}
+
+pub fn get_schema() -> SchemaInfo {
+    let mut envelope = vec![];
+
+    if CONFIG.envelope_version > 1 {
+        envelope.push(SchemaField {
+            name: "v",
+            kind: "integer",
+            optional: false,
+            description: "Version of the message format",
+        });
+    }
+
+    envelope.extend([
+        SchemaField {
+            name: "op",
+            kind: "integer",
+            optional: false,
+            description: "Discord gateway opcode, always 0 for dispatch events",
+        },
+        SchemaField {
+            name: "t",
+            kind: "string",
+            optional: false,
+            description: "Event name, also used as the routing key",
+        },
+        SchemaField {
+            name: "d",
+            kind: "object",
+            optional: false,
+            description: "Event data as received from Discord",
+        },
+    ]);
+
+    if CONFIG.envelope_version > 1 {
+        envelope.push(SchemaField {
+            name: "shard",
+            kind: "integer",
+            optional: false,
+            description: "Shard that received the event",
+        });
+    }
+
+    if CONFIG.state_old {
+        envelope.push(SchemaField {
+            name: "old",
+            kind: "object",
+            optional: true,
+            description: "Previous state of the object from the state cache",
+        });
+    }
+
+    envelope.push(SchemaField {
+        name: "startup",
+        kind: "boolean",
+        optional: true,
+        description: "Set on GUILD_CREATE events of guilds the shard already had on connecting",
+    });
+
+    SchemaInfo {
+        version: CONFIG.envelope_version,
+        exchange: EXCHANGE,
+        envelope,
+        intents: CONFIG.intents,
+        state_events: get_state_events(),
+    }
+}
+
+pub fn get_config_info() -> ApiResult<ConfigInfo> {
+    let mut config = to_value(&*CONFIG)?;
+
+    if let Value::Object(object) = &mut config {
+        for key in REDACTED_KEYS {
+            if let Some(value) = object.get_mut(key) {
+                if value.as_str().is_some_and(|value| !value.is_empty())
+                    || value.as_array().is_some_and(|value| !value.is_empty())
+                {
+                    *value = Value::from("[redacted]");
+                }
+            }
+        }
+    }
+
+    Ok(ConfigInfo {
+        clusters: get_cluster_ranges()
+            .into_iter()
+            .enumerate()
+            .map(|(index, (from, to))| ClusterInfo {
+                cluster: index as u64,
+                shard_start: from,
+                shard_end: to,
+            })
+            .collect(),
+        intents: format!("{:?}", Intents::from_bits_truncate(CONFIG.intents)),
+        event_types: format!("{:?}", get_event_flags()),
+        state_events: get_state_events(),
+        config,
+    })
+}
+
+pub fn get_properties(payload: &[u8]) -> BasicProperties {
+    if CONFIG.signing_keys.is_empty() {
+        return BasicProperties::default();
+    }
+
+    let signature = CONFIG
+        .signing_keys
+        .iter()
+        .filter_map(|key| key.split_once(':'))
+        .map(|(id, secret)| format!("{}={}", id, sign(secret, payload)))
+        .collect::<Vec<String>>()
+        .join(",");
+
+    let mut headers = FieldTable::default();
+    headers.insert(
+        SIGNATURE_HEADER.into(),
+        AMQPValue::LongString(signature.into()),
+    );
+
+    BasicProperties::default().with_headers(headers)
+}
+
+pub async fn publish_event(channel: &AmqpChannel, kind: &str, data: Value) -> ApiResult<()> {
+    let payload = PayloadInfo {
+        v: None,
+        op: OpCode::Event,
+        s: None,
+        t: Some(kind.to_owned()),
+        d: data,
+        shard: None,
+        old: None,
+        startup: None,
+    };
+
+    let payload = simd_json::to_vec(&payload)?;
+
+    channel
+        .basic_publish(
+            EXCHANGE,
+            kind,
+            BasicPublishOptions::default(),
+            &payload,
+            get_properties(payload.as_slice()),
+        )
+        .await?;
+
+    Ok(())
+}
+
 pub fn log_discord(color: usize, message: impl Into<String>) {
     if CONFIG.log_channel == 0 {
         return;
@@ -315,6 +726,7 @@ pub fn log_discord(color: usize, message: impl Into<String>) {
             .embeds(embeds);
 
         if let Ok(message) = message {
+            rest::wait().await;
             if let Err(err) = message.exec().await {
                 warn!("Failed to post message to Discord: {:?}", err)
             }
@@ -354,6 +766,7 @@ pub fn log_discord_guild(color: usize, title: impl Into<String>, message: impl I
             .embeds(embeds);
 
         if let Ok(message) = message {
+            rest::wait().await;
             if let Err(err) = message.exec().await {
                 warn!("Failed to post message to Discord: {:?}", err)
             }
@@ -361,10 +774,48 @@ pub fn log_discord_guild(color: usize, title: impl Into<String>, message: impl I
     });
 }
 
+pub fn get_shard_quality(info: &Information) -> i64 {
+    let latencies: Vec<i64> = info
+        .latency()
+        .recent()
+        .iter()
+        .map(|value| value.as_millis() as i64)
+        .collect();
+
+    let mut score = 100;
+
+    if !latencies.is_empty() {
+        let average = latencies.iter().sum::<i64>() / latencies.len() as i64;
+        let jitter = latencies.iter().max().unwrap() - latencies.iter().min().unwrap();
+
+        score -= (average / 20).min(50);
+        score -= (jitter / 25).min(20);
+    }
+
+    if let Some(received) = info.latency().received() {
+        let gap = received.elapsed().as_secs() as i64;
+        score -= (gap - 45).clamp(0, 30);
+    }
+
+    score.max(0)
+}
+
 pub fn get_shards() -> u64 {
     CONFIG.shards_end - CONFIG.shards_start + 1
 }
 
+pub fn get_tenant_key(key: &str) -> String {
+    if CONFIG.tenant.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}:{}", key, CONFIG.tenant)
+    }
+}
+
+pub fn get_guild_shard(guild_id: u64) -> u64 {
+    (guild_id >> 22) % CONFIG.shards_total
+}
+
 pub fn to_value<T>(value: &T) -> ApiResult<Value>
 where
     T: Serialize + ?Sized,
@@ -375,6 +826,17 @@ where
     Ok(result)
 }
 
-pub fn get_keys(value: &str) -> Vec<&str> {
-    return value.split(':').collect();
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+pub fn sign(secret: &str, payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload);
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
 }
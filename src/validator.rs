@@ -0,0 +1,135 @@
+use crate::{
+    config::CONFIG,
+    metrics::GATEWAY_PAYLOAD_DIVERGENCES,
+    models::{ApiResult, PayloadInfo},
+};
+
+use lazy_static::lazy_static;
+use serde::{de::DeserializeOwned, Serialize};
+use simd_json::{owned::Value, Value as _, ValueAccess};
+use std::{
+    collections::{BTreeSet, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use tracing::warn;
+use twilight_model::{
+    channel::{Channel, Message},
+    gateway::presence::Presence,
+    guild::{Guild, Member, PartialGuild, Role},
+    voice::VoiceState,
+};
+
+lazy_static! {
+    static ref COUNTER: AtomicU64 = AtomicU64::new(0);
+    static ref REPORTED: Mutex<HashSet<(String, String)>> = Mutex::new(HashSet::new());
+}
+
+fn is_sampled() -> bool {
+    if CONFIG.validate_rate <= 0.0 {
+        return false;
+    }
+
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed) as f64;
+    let rate = CONFIG.validate_rate.min(100.0) / 100.0;
+
+    ((count + 1.0) * rate).floor() > (count * rate).floor()
+}
+
+fn roundtrip<T: DeserializeOwned + Serialize>(data: &Value) -> ApiResult<Value> {
+    let model: T = simd_json::serde::from_owned_value(data.clone())?;
+    let mut bytes = simd_json::to_vec(&model)?;
+
+    Ok(simd_json::to_owned_value(bytes.as_mut_slice())?)
+}
+
+fn get_model(kind: &str, data: &Value) -> Option<ApiResult<Value>> {
+    match kind {
+        "GUILD_CREATE" => Some(roundtrip::<Guild>(data)),
+        "GUILD_UPDATE" => Some(roundtrip::<PartialGuild>(data)),
+        "CHANNEL_CREATE" | "CHANNEL_UPDATE" | "CHANNEL_DELETE" | "THREAD_CREATE"
+        | "THREAD_UPDATE" => Some(roundtrip::<Channel>(data)),
+        "MESSAGE_CREATE" => Some(roundtrip::<Message>(data)),
+        "GUILD_MEMBER_ADD" => Some(roundtrip::<Member>(data)),
+        "GUILD_ROLE_CREATE" | "GUILD_ROLE_UPDATE" => Some(roundtrip::<Role>(data.get("role")?)),
+        "PRESENCE_UPDATE" => Some(roundtrip::<Presence>(data)),
+        "VOICE_STATE_UPDATE" => Some(roundtrip::<VoiceState>(data)),
+        _ => None,
+    }
+}
+
+fn get_unknown_fields(path: &str, raw: &Value, model: &Value, fields: &mut BTreeSet<String>) {
+    if let (Some(raw), Some(model)) = (raw.as_object(), model.as_object()) {
+        for (key, value) in raw.iter() {
+            let path = if path.is_empty() {
+                key.to_owned()
+            } else {
+                format!("{}.{}", path, key)
+            };
+
+            match model.get(key.as_str()) {
+                Some(model) => get_unknown_fields(path.as_str(), value, model, fields),
+                None if !value.is_null() => {
+                    fields.insert(path);
+                }
+                None => {}
+            }
+        }
+    } else if let (Some(raw), Some(model)) = (raw.as_array(), model.as_array()) {
+        let path = format!("{}[]", path);
+        for (raw, model) in raw.iter().zip(model) {
+            get_unknown_fields(path.as_str(), raw, model, fields);
+        }
+    }
+}
+
+fn report(kind: &str, field: &str, message: String) {
+    GATEWAY_PAYLOAD_DIVERGENCES
+        .with_label_values(&[kind, field])
+        .inc();
+
+    if REPORTED
+        .lock()
+        .unwrap()
+        .insert((kind.to_owned(), field.to_owned()))
+    {
+        warn!("{}", message);
+    }
+}
+
+pub fn record(kind: &str, payload: &PayloadInfo) {
+    if !is_sampled() {
+        return;
+    }
+
+    let (raw, model) = match kind {
+        "GUILD_ROLE_CREATE" | "GUILD_ROLE_UPDATE" => match payload.d.get("role") {
+            Some(role) => (role, get_model(kind, &payload.d)),
+            None => return,
+        },
+        _ => (&payload.d, get_model(kind, &payload.d)),
+    };
+
+    match model {
+        Some(Ok(model)) => {
+            let mut fields = BTreeSet::new();
+            get_unknown_fields("", raw, &model, &mut fields);
+
+            for field in fields {
+                report(
+                    kind,
+                    field.as_str(),
+                    format!("Unknown field {} in {} payload", field, kind),
+                );
+            }
+        }
+        Some(Err(err)) => report(
+            kind,
+            "",
+            format!("Failed to deserialize {} payload: {:?}", kind, err),
+        ),
+        None => {}
+    }
+}
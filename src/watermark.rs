@@ -0,0 +1,100 @@
+use crate::{
+    config::CONFIG,
+    models::{ShardWatermark, WatermarkInfo},
+};
+
+use lazy_static::lazy_static;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Default)]
+struct ShardState {
+    pending: BTreeMap<u64, u64>,
+    sequence: Option<u64>,
+    published: Option<u64>,
+}
+
+lazy_static! {
+    static ref SHARDS: Mutex<HashMap<u64, ShardState>> = Mutex::new(HashMap::new());
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+pub fn receive(shard: u64, sequence: Option<u64>) {
+    if let Some(sequence) = sequence {
+        SHARDS
+            .lock()
+            .unwrap()
+            .entry(shard)
+            .or_default()
+            .pending
+            .insert(sequence, now());
+    }
+}
+
+pub fn acknowledge(shard: u64, sequence: Option<u64>, published: bool) {
+    let sequence = match sequence {
+        Some(sequence) => sequence,
+        None => return,
+    };
+
+    let mut shards = SHARDS.lock().unwrap();
+    let state = shards.entry(shard).or_default();
+    state.pending.remove(&sequence);
+
+    if published {
+        state.sequence = Some(sequence);
+        state.published = Some(now());
+    }
+}
+
+pub fn get_ages() -> Vec<(u64, u64)> {
+    let now = now();
+
+    SHARDS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|(shard, state)| {
+            state
+                .published
+                .map(|published| (*shard, now.saturating_sub(published) / 1000))
+        })
+        .collect()
+}
+
+pub fn get_info() -> WatermarkInfo {
+    let now = now();
+    let shards = SHARDS.lock().unwrap();
+
+    let mut watermark = now;
+    let mut info = HashMap::new();
+
+    for shard in CONFIG.shards_start..=CONFIG.shards_end {
+        let state = shards.get(&shard);
+        let oldest = state.and_then(|state| state.pending.values().min().copied());
+
+        watermark = watermark.min(oldest.unwrap_or(now));
+        info.insert(
+            shard.to_string(),
+            ShardWatermark {
+                sequence: state.and_then(|state| state.sequence),
+                published: state.and_then(|state| state.published),
+                pending: state.map_or(0, |state| state.pending.len() as u64),
+            },
+        );
+    }
+
+    WatermarkInfo {
+        watermark,
+        shards: info,
+    }
+}
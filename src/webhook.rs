@@ -0,0 +1,129 @@
+use crate::{
+    config::CONFIG, constants::WEBHOOK_BUFFER_SIZE, metrics::WEBHOOK_DROPPED, utils::sign,
+};
+
+use hyper::{
+    client::HttpConnector,
+    header::{CONTENT_TYPE, USER_AGENT},
+    Body, Client, Method, Request,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use lazy_static::lazy_static;
+use std::sync::Arc;
+use tokio::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Semaphore,
+    },
+    time::{sleep, Duration},
+};
+use tracing::warn;
+
+lazy_static! {
+    static ref CLIENT: Client<HttpsConnector<HttpConnector>> = Client::builder().build(
+        HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build()
+    );
+    static ref SEMAPHORE: Arc<Semaphore> =
+        Arc::new(Semaphore::new(CONFIG.webhook_concurrency as usize));
+    static ref QUEUES: Vec<Sender<Job>> = CONFIG
+        .webhook_urls
+        .iter()
+        .map(|url| {
+            let (tx, rx) = mpsc::channel(WEBHOOK_BUFFER_SIZE);
+            tokio::spawn(run(url.as_str(), rx));
+            tx
+        })
+        .collect();
+}
+
+type Job = (Arc<Vec<u8>>, Option<Arc<str>>);
+
+pub fn is_enabled(kind: &str) -> bool {
+    !CONFIG.webhook_urls.is_empty()
+        && (CONFIG.webhook_events.is_empty()
+            || CONFIG.webhook_events.iter().any(|event| event == kind))
+}
+
+async fn post(url: &str, payload: &[u8], signature: Option<&str>) -> bool {
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(USER_AGENT, "twilight-dispatch");
+
+    if let Some(signature) = signature {
+        request = request.header("X-Signature-256", format!("sha256={}", signature));
+    }
+
+    let request = match request.body(Body::from(payload.to_vec())) {
+        Ok(request) => request,
+        Err(err) => {
+            warn!("Failed to build webhook request: {:?}", err);
+            return true;
+        }
+    };
+
+    match CLIENT.request(request).await {
+        Ok(response) if response.status().is_success() => true,
+        Ok(response) => {
+            warn!(
+                "Webhook {} responded with status {}",
+                url,
+                response.status()
+            );
+            false
+        }
+        Err(err) => {
+            warn!("Failed to send webhook {}: {:?}", url, err);
+            false
+        }
+    }
+}
+
+pub fn dispatch(payload: Vec<u8>) {
+    let payload = Arc::new(payload);
+    let signature = if CONFIG.webhook_secret.is_empty() {
+        None
+    } else {
+        Some(Arc::<str>::from(sign(
+            CONFIG.webhook_secret.as_str(),
+            payload.as_slice(),
+        )))
+    };
+
+    for (url, queue) in CONFIG.webhook_urls.iter().zip(QUEUES.iter()) {
+        if queue
+            .try_send((payload.clone(), signature.clone()))
+            .is_err()
+        {
+            WEBHOOK_DROPPED.with_label_values(&[url.as_str()]).inc();
+        }
+    }
+}
+
+async fn run(url: &str, mut rx: Receiver<Job>) {
+    while let Some((payload, signature)) = rx.recv().await {
+        let _permit = match SEMAPHORE.acquire().await {
+            Ok(permit) => permit,
+            Err(_) => return,
+        };
+
+        let mut delivered = false;
+        for attempt in 0..=CONFIG.webhook_retries {
+            if post(url, payload.as_slice(), signature.as_deref()).await {
+                delivered = true;
+                break;
+            }
+
+            sleep(Duration::from_millis(500 << attempt.min(6))).await;
+        }
+
+        if !delivered {
+            warn!("Giving up on webhook {}", url);
+        }
+    }
+}